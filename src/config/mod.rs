@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -8,6 +9,20 @@ pub struct Config {
     pub llm: Vec<LLMConfig>,
     #[serde(default)]
     pub skills: SkillsConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+    #[serde(default)]
+    pub harness: HarnessConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub fallback: FallbackConfig,
+    #[serde(default)]
+    pub cassette: CassetteConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,13 +35,60 @@ pub struct LLMConfig {
     pub name: String,
     pub model: String,
     pub base_url: String,
-    pub api_key_env: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_key_envs: Vec<String>,
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
     #[serde(default = "default_context_window")]
     pub context_window: usize,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reasoning_effort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
+    pub provider: ProviderKind,
+}
+
+/// API an LLM is reached through.
+///
+/// # Variants
+/// - `Openai`: OpenAI-compatible chat completions, with bearer authentication.
+/// - `Anthropic`: Anthropic Messages API, with `x-api-key` authentication.
+/// - `Ollama`: Ollama's native `/api/chat`, with the schema as `format`.
+/// - `LlamaCpp`: llama.cpp server's chat completions, constrained by a GBNF grammar.
+/// - `Script`: No API; replays the script file named by `base_url`, for offline tests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    Openai,
+    Anthropic,
+    Ollama,
+    LlamaCpp,
+    Script,
+}
+
+/// How the agent's structured response is requested from the LLM.
+///
+/// # Variants
+/// - `JsonSchema`: `response_format` with a strict JSON Schema.
+/// - `JsonObject`: `response_format: json_object`, with the schema in the prompt.
+/// - `Text`: Free text, with the schema in the prompt.
+/// - `Tools`: Actions offered as function tools, read back from `tool_calls`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    #[default]
+    JsonSchema,
+    JsonObject,
+    Text,
+    Tools,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -35,6 +97,382 @@ pub struct SkillsConfig {
     pub paths: Vec<String>,
 }
 
+/// Context compaction settings.
+///
+/// # Fields
+/// - `compact_threshold`: Fraction of `context_window` at which the session
+///   compacts history proactively (e.g. 0.8 = 80%).
+/// - `keep_recent`: Number of trailing messages kept verbatim when compacting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextConfig {
+    #[serde(default = "default_compact_threshold")]
+    pub compact_threshold: f64,
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            compact_threshold: default_compact_threshold(),
+            keep_recent: default_keep_recent(),
+        }
+    }
+}
+
+/// Retry settings for LLM API calls.
+///
+/// # Fields
+/// - `max_attempts`: Attempts per LLM call, across all API key profiles.
+/// - `backoff_ms`: Delay before the first retry; doubles with each further retry.
+/// - `max_backoff_ms`: Upper bound for the delay, and for waiting on `Retry-After`.
+/// - `read_timeout_secs`: Longest wait for response data before a request counts as timed out.
+/// - `rate_limit_cooldown_secs`: Time a key profile is skipped after a rate limit, unless
+///   the API sent `Retry-After`.
+/// - `auth_cooldown_secs`: Time a key profile is skipped after it was rejected.
+/// - `timeout_cooldown_secs`: Time a key profile is skipped after a request timed out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    #[serde(default = "default_rate_limit_cooldown_secs")]
+    pub rate_limit_cooldown_secs: u64,
+    #[serde(default = "default_auth_cooldown_secs")]
+    pub auth_cooldown_secs: u64,
+    #[serde(default = "default_timeout_cooldown_secs")]
+    pub timeout_cooldown_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            read_timeout_secs: default_read_timeout_secs(),
+            rate_limit_cooldown_secs: default_rate_limit_cooldown_secs(),
+            auth_cooldown_secs: default_auth_cooldown_secs(),
+            timeout_cooldown_secs: default_timeout_cooldown_secs(),
+        }
+    }
+}
+
+/// LLMs a session falls back to when the active one keeps failing.
+///
+/// # Fields
+/// - `llms`: Names of the fallback LLMs, in the order they are tried; empty
+///   disables fallback.
+/// - `restore_after_secs`: Time after which the session tries the active LLM
+///   again; unset to stay on the fallback until `/switch`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FallbackConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub llms: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restore_after_secs: Option<u64>,
+}
+
+/// Recording and replay of LLM traffic, for deterministic reruns.
+///
+/// # Fields
+/// - `mode`: Whether requests are recorded, replayed, or sent as usual.
+/// - `path`: The cassette file (JSON lines); required unless `mode` is `off`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CassetteConfig {
+    #[serde(default)]
+    pub mode: CassetteMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// What the cassette does with LLM requests.
+///
+/// # Variants
+/// - `Off`: Requests go to the API; nothing is recorded.
+/// - `Record`: Requests go to the API; each successful exchange is appended to the cassette.
+/// - `Replay`: Requests are answered from the cassette; the API is never called.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    #[default]
+    Off,
+    Record,
+    Replay,
+}
+
+/// Session persistence settings.
+///
+/// # Fields
+/// - `autosave`: Write every session's log from the first message, without `/save`.
+/// - `dir`: Directory for session logs (default: `~/.minusagent/sessions`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionsConfig {
+    #[serde(default)]
+    pub autosave: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+}
+
+/// Command execution limits applied by the harness.
+///
+/// # Fields
+/// - `timeout_secs`: Wall-clock limit per command; the process group is killed when it expires.
+/// - `max_output_bytes`: Bytes kept from each of stdout and stderr; the rest is dropped with a marker.
+/// - `cpu_secs`: Optional `RLIMIT_CPU` for the command, in seconds of CPU time.
+/// - `memory_mb`: Optional `RLIMIT_AS` for the command, in megabytes of address space.
+/// - `workdir`: Directory commands start in (default: the process working directory).
+/// - `persistent_shell`: Carry the working directory and exported variables
+///   from one command to the next.
+/// - `backend`: Name of the backend commands run on, unless an LLM or the
+///   session picks another one.
+/// - `backends`: Named backends; `"local"` is always available.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HarnessConfig {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_mb: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workdir: Option<String>,
+    #[serde(default = "default_persistent_shell")]
+    pub persistent_shell: bool,
+    #[serde(default = "default_backend")]
+    pub backend: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub backends: BTreeMap<String, BackendConfig>,
+}
+
+impl Default for HarnessConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            max_output_bytes: default_max_output_bytes(),
+            cpu_secs: None,
+            memory_mb: None,
+            workdir: None,
+            persistent_shell: default_persistent_shell(),
+            backend: default_backend(),
+            backends: BTreeMap::new(),
+        }
+    }
+}
+
+/// Where the harness runs commands.
+///
+/// # Variants
+/// - `Local`: `sh` on this machine, in `workdir`.
+/// - `Container`: A long-lived Docker or Podman container with `workspace`
+///   (default: `workdir`) bind-mounted at `mount`; `run_args` are passed to
+///   `<runtime> run`.
+/// - `Sandbox`: Unprivileged Linux namespaces via `tool` (`bwrap`, `unshare`,
+///   or `auto`); everything but `workspace` (default: `workdir`), `writable`,
+///   and a private `/tmp` is read-only, and the network is off unless
+///   `network` is set.
+/// - `Ssh`: A remote `host` reached with the system `ssh` over one shared
+///   master connection; commands start in `workdir` (default: the login
+///   directory) with `env` exported, and `ssh_args` are passed to `ssh`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendConfig {
+    Local,
+    Container {
+        #[serde(default = "default_runtime")]
+        runtime: String,
+        image: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+        #[serde(default = "default_mount")]
+        mount: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        run_args: Vec<String>,
+    },
+    Sandbox {
+        #[serde(default = "default_sandbox_tool")]
+        tool: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workspace: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        writable: Vec<String>,
+        #[serde(default)]
+        network: bool,
+    },
+    Ssh {
+        host: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        port: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workdir: Option<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        ssh_args: Vec<String>,
+    },
+}
+
+impl BackendConfig {
+    /// Returns the backend kind, as written in the `type` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Container { .. } => "container",
+            Self::Sandbox { .. } => "sandbox",
+            Self::Ssh { .. } => "ssh",
+        }
+    }
+}
+
+/// Outcome of a command policy check, ordered from least to most restrictive.
+/// Defaults to `Allow`, the decision for commands no rule matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    #[default]
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// Command approval policy.
+///
+/// # Fields
+/// - `default`: Decision for simple commands that no rule matches.
+/// - `rules`: Ordered rules; the first match decides for each simple command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    #[serde(default = "default_decision")]
+    pub default: Decision,
+    #[serde(default = "default_rules")]
+    pub rules: Vec<PolicyRule>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            default: default_decision(),
+            rules: default_rules(),
+        }
+    }
+}
+
+/// A single policy rule. All conditions that are set must match.
+///
+/// # Fields
+/// - `action`: Decision when the rule matches.
+/// - `words`: Glob patterns matched against the leading words, the first
+///   one by program name (e.g. `["git", "push"]`).
+/// - `pattern`: Regex matched against the words joined by spaces.
+/// - `paths`: Glob patterns matched against arguments resolved to absolute paths.
+/// - `reason`: Explanation shown to the user and the LLM.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub action: Decision,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Built-in rules: deny clearly destructive commands, ask before risky ones.
+const DEFAULT_POLICY_RULES: &str = r#"[
+    { "action": "deny", "words": ["rm"], "paths": ["/", "/*", "~"],
+      "reason": "deletes the root or home directory" },
+    { "action": "deny", "words": ["mkfs*"], "reason": "formats a filesystem" },
+    { "action": "deny", "words": ["dd"], "pattern": "of=/dev/", "reason": "writes to a raw device" },
+    { "action": "deny", "paths": ["/dev/sd*", "/dev/nvme*"], "reason": "accesses a raw disk" },
+    { "action": "ask", "words": ["rm"], "pattern": " (-[a-zA-Z]*[rR]|--recursive)", "reason": "deletes recursively" },
+    { "action": "ask", "words": ["sudo"], "reason": "runs as root" },
+    { "action": "ask", "words": ["git", "push"], "reason": "publishes commits" },
+    { "action": "ask", "words": ["git", "reset"], "pattern": "--hard", "reason": "discards changes" }
+]"#;
+
+fn default_decision() -> Decision {
+    Decision::default()
+}
+
+fn default_rules() -> Vec<PolicyRule> {
+    serde_json::from_str(DEFAULT_POLICY_RULES).expect("built-in policy rules are valid")
+}
+
+fn default_backend() -> String {
+    "local".to_string()
+}
+
+fn default_runtime() -> String {
+    "docker".to_string()
+}
+
+fn default_sandbox_tool() -> String {
+    "auto".to_string()
+}
+
+fn default_mount() -> String {
+    "/workspace".to_string()
+}
+
+fn default_persistent_shell() -> bool {
+    true
+}
+
+fn default_timeout_secs() -> u64 {
+    120
+}
+
+fn default_max_output_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
+fn default_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_read_timeout_secs() -> u64 {
+    120
+}
+
+fn default_rate_limit_cooldown_secs() -> u64 {
+    120
+}
+
+fn default_auth_cooldown_secs() -> u64 {
+    300
+}
+
+fn default_timeout_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_compact_threshold() -> f64 {
+    0.8
+}
+
+fn default_keep_recent() -> usize {
+    4
+}
+
 fn default_max_tokens() -> u32 {
     4096
 }
@@ -62,12 +500,24 @@ impl Config {
                 name: "codestral".to_string(),
                 model: "codestral-latest".to_string(),
                 base_url: "https://codestral.mistral.ai/v1/chat/completions".to_string(),
-                api_key_env: "LLM_API_KEY".to_string(),
+                api_key_env: Some("LLM_API_KEY".to_string()),
+                api_key_envs: Vec::new(),
                 max_tokens: 4096,
                 context_window: 256_000,
                 reasoning_effort: None,
+                backend: None,
+                stream: false,
+                protocol: Protocol::JsonSchema,
+                provider: ProviderKind::Openai,
             }],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         };
         config.save()?;
         Ok(config)
@@ -103,7 +553,7 @@ impl Config {
     /// Sets a config field by dotted path and saves to disk.
     ///
     /// Supports paths like "agent.max_steps", "llm.0.model".
-    /// Values are auto-parsed as number, bool, or string.
+    /// Values are auto-parsed as integer, float, bool, or string.
     ///
     /// # Arguments
     /// - `key`: Dotted path to the field.
@@ -126,6 +576,12 @@ impl Config {
 
         let parsed = if let Ok(n) = value.parse::<u64>() {
             serde_json::Value::Number(n.into())
+        } else if let Some(f) = value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+        {
+            serde_json::Value::Number(f)
         } else if let Ok(b) = value.parse::<bool>() {
             serde_json::Value::Bool(b)
         } else {
@@ -181,6 +637,16 @@ impl Config {
     /// # Arguments
     /// - `name`: The name of the LLM to promote.
     pub fn promote_llm(&mut self, name: &str) -> Result<(), String> {
+        self.select_llm(name)?;
+        self.save()
+    }
+
+    /// Moves the named LLM to the front of the list without saving, to
+    /// use it for one run only.
+    ///
+    /// # Arguments
+    /// - `name`: The name of the LLM to select.
+    pub fn select_llm(&mut self, name: &str) -> Result<(), String> {
         let idx = self
             .llm
             .iter()
@@ -188,14 +654,26 @@ impl Config {
             .ok_or(format!("LLM '{}' not found", name))?;
         let entry = self.llm.remove(idx);
         self.llm.insert(0, entry);
-        self.save()
+        Ok(())
     }
 }
 
 impl LLMConfig {
-    pub fn api_key(&self) -> Result<String, String> {
-        std::env::var(&self.api_key_env)
-            .map_err(|_| format!("environment variable {} is not set", self.api_key_env))
+    /// Resolves the API keys from the variables named by `api_key_env`
+    /// and `api_key_envs`, in that order. Each key is one profile that
+    /// `LLMClient` rotates through when a key is rate limited or rejected.
+    ///
+    /// # Returns
+    /// The keys, empty if no variable is configured, as for local servers
+    /// without authentication, or an error if a configured variable is not set.
+    pub fn api_keys(&self) -> Result<Vec<String>, String> {
+        self.api_key_env
+            .iter()
+            .chain(&self.api_key_envs)
+            .map(|name| {
+                std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))
+            })
+            .collect()
     }
 }
 
@@ -282,12 +760,39 @@ mod tests {
             name: "test".to_string(),
             model: "gpt-4".to_string(),
             base_url: "https://api.example.com".to_string(),
-            api_key_env: "NONEXISTENT_VAR_12345".to_string(),
+            api_key_env: Some("NONEXISTENT_VAR_12345".to_string()),
+            api_key_envs: Vec::new(),
             max_tokens: 4096,
             context_window: 128_000,
             reasoning_effort: None,
+            backend: None,
+            stream: false,
+            protocol: Protocol::JsonSchema,
+            provider: ProviderKind::Openai,
         };
-        assert!(config.api_key().is_err());
+        assert!(config.api_keys().is_err());
+    }
+
+    #[test]
+    fn test_api_key_profiles_and_retry_defaults() {
+        let json = r#"{
+            "agent": { "max_steps": 5 },
+            "llm": [{
+                "name": "test",
+                "model": "gpt-4",
+                "base_url": "https://api.example.com",
+                "api_key_env": "HOME",
+                "api_key_envs": ["PATH"]
+            }],
+            "retry": { "max_attempts": 6 }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        let keys = config.llm[0].api_keys().unwrap();
+        let vars = ["HOME", "PATH"].map(|name| std::env::var(name).unwrap());
+        assert_eq!(keys, vars);
+        assert_eq!(config.retry.max_attempts, 6);
+        assert_eq!(config.retry.rate_limit_cooldown_secs, 120);
+        assert_eq!(config.retry.auth_cooldown_secs, 300);
     }
 
     #[test]
@@ -304,22 +809,39 @@ mod tests {
                     name: "a".to_string(),
                     model: "model-a".to_string(),
                     base_url: "https://a.com".to_string(),
-                    api_key_env: "KEY_A".to_string(),
+                    api_key_env: Some("KEY_A".to_string()),
+                    api_key_envs: Vec::new(),
                     max_tokens: 4096,
                     context_window: 128_000,
                     reasoning_effort: None,
+                    backend: None,
+                    stream: false,
+                    protocol: Protocol::JsonSchema,
+                    provider: ProviderKind::Openai,
                 },
                 LLMConfig {
                     name: "b".to_string(),
                     model: "model-b".to_string(),
                     base_url: "https://b.com".to_string(),
-                    api_key_env: "KEY_B".to_string(),
+                    api_key_env: Some("KEY_B".to_string()),
+                    api_key_envs: Vec::new(),
                     max_tokens: 4096,
                     context_window: 128_000,
                     reasoning_effort: None,
+                    backend: None,
+                    stream: false,
+                    protocol: Protocol::JsonSchema,
+                    provider: ProviderKind::Openai,
                 },
             ],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         }
     }
 
@@ -340,10 +862,15 @@ mod tests {
             name: "c".to_string(),
             model: "model-c".to_string(),
             base_url: "https://c.com".to_string(),
-            api_key_env: "KEY_C".to_string(),
+            api_key_env: Some("KEY_C".to_string()),
+            api_key_envs: Vec::new(),
             max_tokens: 4096,
             context_window: 128_000,
             reasoning_effort: None,
+            backend: None,
+            stream: false,
+            protocol: Protocol::JsonSchema,
+            provider: ProviderKind::Openai,
         };
         // add_llm calls save(), so just test in-memory mutation
         config.llm.push(llm);
@@ -431,4 +958,63 @@ mod tests {
         let config: Config = serde_json::from_str(json).unwrap();
         assert_eq!(config.llm[0].context_window, 128_000);
     }
+
+    #[test]
+    fn test_policy_config() {
+        let json = r#"{
+            "agent": { "max_steps": 5 },
+            "llm": [],
+            "policy": {
+                "default": "ask",
+                "rules": [{ "action": "allow", "words": ["cargo"] }]
+            }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.policy.default, Decision::Ask);
+        assert_eq!(config.policy.rules.len(), 1);
+        assert_eq!(config.policy.rules[0].action, Decision::Allow);
+
+        let defaults = PolicyConfig::default();
+        assert_eq!(defaults.default, Decision::Allow);
+        assert!(defaults.rules.iter().any(|r| r.action == Decision::Deny));
+    }
+
+    #[test]
+    fn test_harness_backends_config() {
+        let json = r#"{
+            "agent": { "max_steps": 5 },
+            "llm": [{ "name": "a", "model": "m", "base_url": "https://a.com",
+                      "api_key_env": "A", "backend": "box" }],
+            "harness": {
+                "backend": "box",
+                "backends": { "box": { "type": "container", "image": "alpine:3" } }
+            }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.llm[0].backend.as_deref(), Some("box"));
+        assert_eq!(config.harness.backend, "box");
+        assert_eq!(
+            config.harness.backends["box"],
+            BackendConfig::Container {
+                runtime: "docker".to_string(),
+                image: "alpine:3".to_string(),
+                workspace: None,
+                mount: "/workspace".to_string(),
+                run_args: Vec::new(),
+            }
+        );
+        assert_eq!(HarnessConfig::default().backend, "local");
+    }
+
+    #[test]
+    fn test_default_context_config() {
+        let json = r#"{
+            "agent": { "max_steps": 5 },
+            "llm": [],
+            "context": { "keep_recent": 6 }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.context.keep_recent, 6);
+        assert!((config.context.compact_threshold - 0.8).abs() < f64::EPSILON);
+    }
 }
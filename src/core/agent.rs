@@ -1,6 +1,8 @@
+use tokio_util::sync::CancellationToken;
+
 use crate::core::context::Context;
-use crate::core::llm::LLMClient;
-use crate::core::{Action, Node};
+use crate::core::guard::ContextGuard;
+use crate::core::{Action, INTERRUPTED, MAX_STEPS};
 
 /// ReAct agent that owns the reasoning loop.
 ///
@@ -8,10 +10,10 @@ use crate::core::{Action, Node};
 /// internally. Returns `Execute` or `Completed` to Session for dispatch.
 ///
 /// # Fields
-/// - `llm`: The LLM client for chat completions.
+/// - `guard`: The LLM client wrapped with context overflow protection.
 /// - `max_steps`: Maximum total steps (LLM calls) per run.
 pub struct Agent {
-    llm: LLMClient,
+    guard: ContextGuard,
     max_steps: u32,
 }
 
@@ -19,10 +21,20 @@ impl Agent {
     /// Creates a new agent.
     ///
     /// # Arguments
-    /// - `llm`: The LLM client.
+    /// - `guard`: The guarded LLM client.
     /// - `max_steps`: Maximum steps per run.
-    pub fn new(llm: LLMClient, max_steps: u32) -> Self {
-        Self { llm, max_steps }
+    pub fn new(guard: ContextGuard, max_steps: u32) -> Self {
+        Self { guard, max_steps }
+    }
+
+    /// Returns the context guard wrapping the LLM client.
+    pub fn guard(&self) -> &ContextGuard {
+        &self.guard
+    }
+
+    /// Returns a mutable reference to the context guard.
+    pub fn guard_mut(&mut self) -> &mut ContextGuard {
+        &mut self.guard
     }
 
     /// Runs the agent loop until it needs external dispatch or completes.
@@ -31,19 +43,31 @@ impl Agent {
     /// internally. Returns `Execute` to Session for harness dispatch, or
     /// `Completed` when done. Also returns `Completed` on max steps.
     ///
+    /// Cancelling `cancel` drops the in-flight LLM request and returns
+    /// `Completed` with `INTERRUPTED`; context is left as of the last
+    /// finished step.
+    ///
     /// # Arguments
     /// - `ctx`: The conversation context with message history.
+    /// - `cancel`: Token signalling a user interrupt.
     ///
     /// # Returns
     /// `Execute` for Session to dispatch, or `Completed` when finished.
-    pub async fn run(&mut self, ctx: &mut Context) -> Action {
+    pub async fn run(&mut self, ctx: &mut Context, cancel: &CancellationToken) -> Action {
         for _ in 0..self.max_steps {
-            let action = self.llm.run(ctx).await;
+            let action = tokio::select! {
+                action = self.guard.run(ctx) => action,
+                _ = cancel.cancelled() => {
+                    return Action::Completed {
+                        answer: INTERRUPTED.to_string(),
+                    };
+                }
+            };
 
             match action {
                 Action::UseSkill { skills } => {
                     for skill in &skills {
-                        self.llm.prompt_engine().load_skill(ctx, skill);
+                        self.guard.llm().prompt_engine().load_skill(ctx, skill);
                     }
                 }
                 Action::Continue => {
@@ -54,7 +78,7 @@ impl Agent {
         }
 
         Action::Completed {
-            answer: "max steps reached".to_string(),
+            answer: MAX_STEPS.to_string(),
         }
     }
 }
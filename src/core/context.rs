@@ -1,16 +1,136 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::core::llm::{LLMResponse, Thought};
+use crate::core::llm::{LLMResponse, Thought, Usage};
 use crate::skill::SkillMeta;
 
 /// Outcome of a skill or command execution, stored in conversation history.
 ///
 /// Used by `Message::Observation` to record whether an execution succeeded or failed.
+///
+/// # Variants
+/// - `Success`: Plain output of a successful step (e.g. a loaded skill body).
+/// - `Failure`: Error text of a failed step.
+/// - `Command`: Structured result of a shell command that ran to an end.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Outcome {
     Success { output: String },
     Failure { error: String },
+    Command(CommandOutput),
+}
+
+impl Outcome {
+    /// Returns `true` for `Success` and for commands that exited with code 0.
+    pub fn is_success(&self) -> bool {
+        match self {
+            Outcome::Success { .. } => true,
+            Outcome::Failure { .. } => false,
+            Outcome::Command(output) => output.success(),
+        }
+    }
+
+    /// Returns the text shown for this outcome in `Message::Observation::content`.
+    pub fn content(&self) -> String {
+        match self {
+            Outcome::Success { output } => output.clone(),
+            Outcome::Failure { error } => error.clone(),
+            Outcome::Command(output) => output.render(),
+        }
+    }
+}
+
+/// Structured result of a shell command run by the harness.
+///
+/// # Fields
+/// - `exit_code`: Process exit code, or `None` if the command was killed.
+/// - `stdout`: Captured standard output.
+/// - `stderr`: Captured standard error.
+/// - `duration_ms`: Wall-clock run time in milliseconds.
+/// - `stdout_truncated`: Bytes of stdout dropped by the output cap.
+/// - `stderr_truncated`: Bytes of stderr dropped by the output cap.
+/// - `timed_out`: Whether the harness killed the command on timeout.
+/// - `cwd`: Working directory after the command, i.e. where the next one starts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub stdout_truncated: usize,
+    #[serde(default)]
+    pub stderr_truncated: usize,
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cwd: String,
+}
+
+impl CommandOutput {
+    /// Returns `true` if the command exited with code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && !self.timed_out
+    }
+
+    /// Renders the result as plain text: stdout, stderr, and a status line.
+    pub fn render(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.stdout.is_empty() {
+            parts.push(self.stdout.trim_end().to_string());
+        }
+        if !self.stderr.is_empty() {
+            parts.push(format!("[stderr]\n{}", self.stderr.trim_end()));
+        }
+
+        let secs = self.duration_ms as f64 / 1000.0;
+        let mut status = match (self.timed_out, self.exit_code) {
+            (true, _) => format!("[timed out after {:.2}s", secs),
+            (false, Some(code)) => format!("[exit code {} after {:.2}s", code, secs),
+            (false, None) => format!("[killed after {:.2}s", secs),
+        };
+        for (stream, dropped) in [
+            ("stdout", self.stdout_truncated),
+            ("stderr", self.stderr_truncated),
+        ] {
+            if dropped > 0 {
+                status.push_str(&format!(", {} truncated by {} bytes", stream, dropped));
+            }
+        }
+        status.push(']');
+        parts.push(status);
+
+        parts.join("\n")
+    }
+
+    /// Converts the result into the fields of an observation payload.
+    ///
+    /// Truncation and timeout fields are only present when they apply.
+    fn to_json(&self) -> serde_json::Map<String, Value> {
+        let mut map = serde_json::Map::new();
+        map.insert("exit_code".to_string(), serde_json::json!(self.exit_code));
+        map.insert(
+            "duration_ms".to_string(),
+            serde_json::json!(self.duration_ms),
+        );
+        map.insert("stdout".to_string(), serde_json::json!(self.stdout));
+        map.insert("stderr".to_string(), serde_json::json!(self.stderr));
+        if self.stdout_truncated > 0 || self.stderr_truncated > 0 {
+            map.insert(
+                "truncated_bytes".to_string(),
+                serde_json::json!({
+                    "stdout": self.stdout_truncated,
+                    "stderr": self.stderr_truncated,
+                }),
+            );
+        }
+        if self.timed_out {
+            map.insert("timed_out".to_string(), Value::Bool(true));
+        }
+        if !self.cwd.is_empty() {
+            map.insert("cwd".to_string(), Value::String(self.cwd.clone()));
+        }
+        map
+    }
 }
 
 /// A single message in the conversation history.
@@ -19,11 +139,13 @@ pub enum Outcome {
 /// - `User`: Input from the user.
 /// - `Assistant`: LLM response with thought and optional actions/answer.
 /// - `Observation`: Result of a skill execution fed back to the LLM.
+/// - `Summary`: LLM-generated summary replacing `replaced` earlier messages.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
     User { content: String },
     Assistant { thought: Thought, raw: LLMResponse },
     Observation { skill: String, outcome: Outcome, content: String },
+    Summary { content: String, replaced: usize },
 }
 
 impl Message {
@@ -42,22 +164,47 @@ impl Message {
                 "content": serde_json::to_string(raw).unwrap_or_default(),
             }),
             Message::Observation { skill, outcome, content } => {
-                let outcome_str = match outcome {
-                    Outcome::Success { .. } => "success",
-                    Outcome::Failure { .. } => "failure",
+                let outcome_str = if outcome.is_success() {
+                    "success"
+                } else {
+                    "failure"
                 };
+                let mut observation = serde_json::json!({
+                    "role": "observation",
+                    "skill": skill,
+                    "outcome": outcome_str,
+                });
+                match outcome {
+                    Outcome::Command(output) => {
+                        observation.as_object_mut().unwrap().extend(output.to_json());
+                    }
+                    _ => observation["content"] = Value::String(content.clone()),
+                }
                 serde_json::json!({
                     "role": "user",
-                    "content": serde_json::json!({
-                        "role": "observation",
-                        "skill": skill,
-                        "outcome": outcome_str,
-                        "content": content,
-                    }).to_string(),
+                    "content": observation.to_string(),
                 })
             }
+            Message::Summary { content, replaced } => serde_json::json!({
+                "role": "user",
+                "content": serde_json::json!({
+                    "role": "summary",
+                    "replaced_messages": replaced,
+                    "content": content,
+                }).to_string(),
+            }),
         }
     }
+
+    /// Estimates the number of tokens this message occupies in a request.
+    ///
+    /// Uses a rough four-characters-per-token heuristic plus a small
+    /// per-message overhead, for use when API usage is not available.
+    pub fn estimate_tokens(&self) -> usize {
+        let json = self.to_json();
+        let chars = json["content"].as_str().map_or(0, |c| c.chars().count());
+        chars.div_ceil(4) + 4
+    }
 }
 
 /// Manages the conversation state: skill catalog, message history, and token usage.
@@ -66,10 +213,25 @@ impl Message {
 /// - `skills`: Available skills loaded at initialization.
 /// - `messages`: Ordered conversation messages.
 /// - `total_tokens`: Cumulative token count from LLM API responses.
+/// - `prompt_tokens`: Cumulative prompt token count.
+/// - `completion_tokens`: Cumulative completion token count.
+/// - `last_usage`: Usage of the most recent LLM call, i.e. the current context size.
+/// - `revision`: Incremented whenever the history may have been rewritten in place.
+#[derive(Clone)]
 pub struct Context {
     skills: Vec<SkillMeta>,
     messages: Vec<Message>,
     total_tokens: usize,
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    last_usage: Usage,
+    revision: u64,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Context {
@@ -79,6 +241,10 @@ impl Context {
             skills: Vec::new(),
             messages: Vec::new(),
             total_tokens: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            last_usage: Usage::default(),
+            revision: 0,
         }
     }
 
@@ -127,6 +293,59 @@ impl Context {
         self.total_tokens
     }
 
+    /// Records the usage of one LLM call.
+    ///
+    /// Accumulates prompt, completion, and total counts, and remembers the
+    /// call as the latest measurement of the context size.
+    ///
+    /// # Arguments
+    /// - `usage`: Token usage reported by the API.
+    pub fn record_usage(&mut self, usage: &Usage) {
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.total_tokens += usage.total_tokens;
+        self.last_usage = *usage;
+    }
+
+    /// Returns the cumulative prompt token count.
+    pub fn prompt_tokens(&self) -> usize {
+        self.prompt_tokens
+    }
+
+    /// Returns the cumulative completion token count.
+    pub fn completion_tokens(&self) -> usize {
+        self.completion_tokens
+    }
+
+    /// Returns the usage of the most recent LLM call.
+    pub fn last_usage(&self) -> Usage {
+        self.last_usage
+    }
+
+    /// Overrides the latest usage measurement without accumulating totals.
+    ///
+    /// Used after history is rewritten (e.g. compaction) so that the context
+    /// size reflects the new history before the next LLM call.
+    ///
+    /// # Arguments
+    /// - `usage`: The replacement measurement.
+    pub fn set_last_usage(&mut self, usage: Usage) {
+        self.last_usage = usage;
+    }
+
+    /// Estimates the token count of all messages via `Message::estimate_tokens`.
+    pub fn estimate_tokens(&self) -> usize {
+        self.messages.iter().map(Message::estimate_tokens).sum()
+    }
+
+    /// Returns the number of tokens the conversation occupied in the last call.
+    ///
+    /// Computed as prompt plus completion tokens of the latest call, since the
+    /// completion becomes part of the next prompt.
+    pub fn context_tokens(&self) -> usize {
+        self.last_usage.prompt_tokens + self.last_usage.completion_tokens
+    }
+
     /// Appends a user message to the conversation history.
     ///
     /// # Arguments
@@ -152,10 +371,7 @@ impl Context {
     /// - `skill`: The name of the executed skill.
     /// - `outcome`: The execution outcome (Success or Failure).
     pub fn add_observation(&mut self, skill: String, outcome: Outcome) {
-        let content = match &outcome {
-            Outcome::Success { output } => output.clone(),
-            Outcome::Failure { error } => error.clone(),
-        };
+        let content = outcome.content();
         self.messages.push(Message::Observation { skill, outcome, content });
     }
 
@@ -164,13 +380,29 @@ impl Context {
         self.messages.len()
     }
 
+    /// Returns `true` if the conversation has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
     /// Returns a reference to the message list.
     pub fn messages(&self) -> &[Message] {
         &self.messages
     }
 
     /// Returns a mutable reference to the message list.
+    ///
+    /// Bumps the revision, since callers may rewrite existing messages.
     pub fn messages_mut(&mut self) -> &mut Vec<Message> {
+        self.revision += 1;
         &mut self.messages
     }
+
+    /// Returns the history revision.
+    ///
+    /// Unchanged revisions guarantee that existing messages were only
+    /// appended to, which lets persistence write just the new tail.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::core::llm::retry::Failure;
+
+/// A synthetic failure injected to test retry and recovery settings.
+///
+/// # Variants
+/// - `RateLimit`: The next LLM request is answered with a 429.
+/// - `Auth`: The next LLM request is answered with a 401.
+/// - `Timeout`: The next LLM request times out.
+/// - `Overflow`: The next LLM request exceeds the context window.
+/// - `MalformedJson`: The next LLM reply is replaced by one that does not parse.
+/// - `CommandCrash`: The next command fails before it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    RateLimit,
+    Auth,
+    Timeout,
+    Overflow,
+    MalformedJson,
+    CommandCrash,
+}
+
+impl Fault {
+    /// All faults, in the order they are listed to the user.
+    pub const ALL: [Fault; 6] = [
+        Self::RateLimit,
+        Self::Auth,
+        Self::Timeout,
+        Self::Overflow,
+        Self::MalformedJson,
+        Self::CommandCrash,
+    ];
+
+    /// Parses a fault name, accepting `-` in place of `_`.
+    ///
+    /// # Arguments
+    /// - `name`: The name, e.g. `"rate_limit"` or `"malformed-json"`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.replace('-', "_");
+        Self::ALL
+            .into_iter()
+            .find(|fault| fault.as_str() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(Fault::as_str).collect();
+                format!(
+                    "unknown failure '{}' (expected one of: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Returns the name of the fault.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Auth => "auth",
+            Self::Timeout => "timeout",
+            Self::Overflow => "overflow",
+            Self::MalformedJson => "malformed_json",
+            Self::CommandCrash => "command_crash",
+        }
+    }
+
+    /// Returns the API failure the fault stands for, or `None` for faults
+    /// that are not request failures.
+    pub fn failure(&self) -> Option<Failure> {
+        match self {
+            Self::RateLimit => Some(Failure::RateLimit),
+            Self::Auth => Some(Failure::Auth),
+            Self::Timeout => Some(Failure::Timeout),
+            Self::Overflow => Some(Failure::Overflow),
+            Self::MalformedJson | Self::CommandCrash => None,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Faults waiting to be injected, each with the number of calls left to fail.
+///
+/// Cloning shares the queue, so the LLM clients and the harness of a
+/// session see the faults injected through any handle.
+#[derive(Debug, Clone, Default)]
+pub struct Faults(Arc<Mutex<Vec<(Fault, u32)>>>);
+
+impl Faults {
+    /// Makes the next `count` calls fail with the fault, replacing any
+    /// count left for it. A count of 0 removes the fault.
+    ///
+    /// # Arguments
+    /// - `fault`: The failure to inject.
+    /// - `count`: How many calls fail.
+    pub fn inject(&self, fault: Fault, count: u32) {
+        let mut pending = self.lock();
+        pending.retain(|(f, _)| *f != fault);
+        if count > 0 {
+            pending.push((fault, count));
+        }
+    }
+
+    /// Takes one call's worth of the first pending fault accepted by `matches`.
+    ///
+    /// # Arguments
+    /// - `matches`: Selects the faults the calling layer can inject.
+    ///
+    /// # Returns
+    /// The fault to inject into this call, if any.
+    pub fn take(&self, matches: impl Fn(Fault) -> bool) -> Option<Fault> {
+        let mut pending = self.lock();
+        let index = pending.iter().position(|(f, _)| matches(*f))?;
+        let fault = pending[index].0;
+        pending[index].1 -= 1;
+        if pending[index].1 == 0 {
+            pending.remove(index);
+        }
+        Some(fault)
+    }
+
+    /// Returns the pending faults with their remaining counts.
+    pub fn pending(&self) -> Vec<(Fault, u32)> {
+        self.lock().clone()
+    }
+
+    /// Removes all pending faults.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(Fault, u32)>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_and_take() {
+        assert_eq!(Fault::parse("malformed-json"), Ok(Fault::MalformedJson));
+        assert!(Fault::parse("meteor").unwrap_err().contains("rate_limit"));
+
+        let faults = Faults::default();
+        let shared = faults.clone();
+        shared.inject(Fault::RateLimit, 2);
+        shared.inject(Fault::CommandCrash, 1);
+
+        let llm = |f: Fault| f != Fault::CommandCrash;
+        assert_eq!(faults.take(llm), Some(Fault::RateLimit));
+        assert_eq!(
+            faults.pending(),
+            [(Fault::RateLimit, 1), (Fault::CommandCrash, 1)]
+        );
+        assert_eq!(
+            faults.take(|f| f == Fault::CommandCrash),
+            Some(Fault::CommandCrash)
+        );
+        assert_eq!(faults.take(llm), Some(Fault::RateLimit));
+        assert_eq!(faults.take(llm), None);
+
+        faults.inject(Fault::Auth, 3);
+        faults.inject(Fault::Auth, 0);
+        assert!(shared.pending().is_empty());
+    }
+}
@@ -0,0 +1,592 @@
+use serde_json::Value;
+
+use crate::config::ContextConfig;
+use crate::core::context::{Context, Message, Outcome};
+use crate::core::llm::retry::Failure;
+use crate::core::llm::{LLMClient, Usage};
+use crate::core::{Action, Node};
+
+/// Maximum characters kept from an old observation body during truncation.
+const OBSERVATION_LIMIT: usize = 2000;
+
+/// Substrings in provider error messages that indicate a context overflow.
+const OVERFLOW_PATTERNS: &[&str] = &[
+    "context_length_exceeded",
+    "context length",
+    "context window",
+    "maximum context",
+    "too many tokens",
+    "prompt is too long",
+    "input is too long",
+    "reduce the length",
+];
+
+const COMPACT_PROMPT: &str = "\
+You compress agent conversation history. Summarize the transcript below so the \
+agent can continue the task without it. Keep the user's goals, decisions made, \
+commands run and their key results, file paths, errors, and open questions. \
+Reply with the summary text only.";
+
+/// Recovery stages applied in order when the LLM reports a context overflow.
+///
+/// # Variants
+/// - `Truncate`: Shorten old observation bodies.
+/// - `Compact`: Replace older history with an LLM-generated summary.
+/// - `Fail`: Give up and surface the error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Truncate,
+    Compact,
+    Fail,
+}
+
+/// Overflow protection layer wrapping `LLMClient`.
+///
+/// Drives the client's prep → exec → post pipeline itself so that it can
+/// intercept context-overflow errors between exec and post. On overflow it
+/// recovers in three stages: truncate old observations, compact older history
+/// via the LLM, then fail. Token usage is recorded into context by the
+/// client's `post`. Also offers proactive compaction once usage crosses the
+/// configured threshold.
+///
+/// # Fields
+/// - `llm`: The wrapped LLM client.
+/// - `config`: Compaction threshold and retention settings.
+/// - `failure`: Why the last `run` failed, if the LLM API failed it.
+pub struct ContextGuard {
+    llm: LLMClient,
+    config: ContextConfig,
+    failure: Option<Failure>,
+}
+
+impl ContextGuard {
+    /// Wraps an LLM client with overflow protection.
+    ///
+    /// # Arguments
+    /// - `llm`: The LLM client to guard.
+    /// - `config`: Compaction threshold and retention settings.
+    pub fn new(llm: LLMClient, config: ContextConfig) -> Self {
+        Self {
+            llm,
+            config,
+            failure: None,
+        }
+    }
+
+    /// Returns a reference to the wrapped LLM client.
+    pub fn llm(&self) -> &LLMClient {
+        &self.llm
+    }
+
+    /// Returns a mutable reference to the wrapped LLM client.
+    pub fn llm_mut(&mut self) -> &mut LLMClient {
+        &mut self.llm
+    }
+
+    /// Returns why the last `run` failed, if the LLM API failed it; `None`
+    /// after a successful step or an error of another kind.
+    pub fn failure(&self) -> Option<Failure> {
+        self.failure
+    }
+
+    /// Returns `true` if the context size has crossed the compaction threshold.
+    ///
+    /// # Arguments
+    /// - `ctx`: The conversation context.
+    pub fn needs_compaction(&self, ctx: &Context) -> bool {
+        let limit = self.llm.context_window() as f64 * self.config.compact_threshold;
+        ctx.context_tokens() as f64 >= limit
+    }
+
+    /// Compacts older history into a summary, keeping recent messages.
+    ///
+    /// # Arguments
+    /// - `ctx`: The conversation context.
+    ///
+    /// # Returns
+    /// The number of messages replaced by the summary.
+    pub async fn compact(&self, ctx: &mut Context) -> Result<usize, String> {
+        compact(&self.llm, ctx, self.config.keep_recent).await
+    }
+
+    /// Runs one LLM step with overflow recovery.
+    ///
+    /// # Arguments
+    /// - `ctx`: The conversation context.
+    ///
+    /// # Returns
+    /// The action from the LLM, or `Completed` with the error if the call
+    /// fails or recovery is exhausted. The failure of the API, if any, is
+    /// kept for `failure`; tokens spent by a failed call are still recorded.
+    pub async fn run(&mut self, ctx: &mut Context) -> Action {
+        let mut stage = Stage::Truncate;
+        self.failure = None;
+
+        loop {
+            let prep_res = match self.llm.prep(ctx).await {
+                Ok(v) => v,
+                Err(e) => return Action::Completed { answer: e },
+            };
+
+            // Drop failures left by calls outside this step, e.g. compaction
+            self.llm.take_failure();
+            let error = match self.llm.exec(prep_res.clone()).await {
+                Ok(exec_res) => return self.llm.post(ctx, prep_res, exec_res).await,
+                Err(e) => e,
+            };
+            let spent = self.llm.take_spent();
+            if spent.total_tokens > 0 {
+                ctx.record_usage(&spent);
+            }
+            let failure = self.llm.take_failure();
+            if !overflows(failure, &error) {
+                self.failure = failure;
+                return Action::Completed { answer: error };
+            }
+
+            stage = match self.recover(ctx, stage).await {
+                Ok(next) => next,
+                Err(e) => {
+                    self.failure = Some(Failure::Overflow);
+                    return Action::Completed {
+                        answer: format!("context overflow: {}\nrecovery failed: {}", error, e),
+                    };
+                }
+            };
+        }
+    }
+
+    /// Applies the given recovery stage, falling through to later stages
+    /// when a stage cannot make progress.
+    ///
+    /// # Arguments
+    /// - `ctx`: The conversation context to shrink.
+    /// - `stage`: The first stage to attempt.
+    ///
+    /// # Returns
+    /// The stage to attempt on the next overflow, or an error once all
+    /// stages are exhausted.
+    async fn recover(&self, ctx: &mut Context, stage: Stage) -> Result<Stage, String> {
+        if stage == Stage::Truncate
+            && truncate_observations(ctx, self.config.keep_recent, OBSERVATION_LIMIT) > 0
+        {
+            return Ok(Stage::Compact);
+        }
+        if stage != Stage::Fail {
+            self.compact(ctx).await?;
+            return Ok(Stage::Fail);
+        }
+        Err("context still exceeds the window after truncation and compaction".to_string())
+    }
+}
+
+/// Checks whether an LLM error message indicates a context overflow.
+///
+/// # Arguments
+/// - `error`: The error string returned by `LLMClient`.
+pub fn is_context_overflow(error: &str) -> bool {
+    let normalized = error.to_lowercase();
+    OVERFLOW_PATTERNS.iter().any(|p| normalized.contains(p))
+}
+
+/// Checks whether an error of `LLMClient::exec` is a context overflow.
+///
+/// API errors come with their classified `Failure`, so only an `Overflow`
+/// counts; a rate limit about "too many tokens per minute" does not.
+/// Errors without a failure are matched by their text.
+///
+/// # Arguments
+/// - `failure`: The failure the client reported for the error, if any.
+/// - `error`: The error string.
+fn overflows(failure: Option<Failure>, error: &str) -> bool {
+    match failure {
+        Some(failure) => failure == Failure::Overflow,
+        None => is_context_overflow(error),
+    }
+}
+
+/// Shortens observation bodies outside the most recent messages.
+///
+/// # Arguments
+/// - `ctx`: The conversation context.
+/// - `keep_recent`: Number of trailing messages left untouched.
+/// - `limit`: Maximum characters kept per observation.
+///
+/// # Returns
+/// The number of observations truncated.
+pub fn truncate_observations(ctx: &mut Context, keep_recent: usize, limit: usize) -> usize {
+    let end = ctx.len().saturating_sub(keep_recent);
+    let mut count = 0;
+
+    for message in &mut ctx.messages_mut()[..end] {
+        if let Message::Observation {
+            outcome, content, ..
+        } = message
+            && truncate_outcome(outcome, limit)
+        {
+            *content = outcome.content();
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Truncates the text fields of an outcome to `limit` characters each.
+///
+/// # Returns
+/// `true` if any field was shortened.
+fn truncate_outcome(outcome: &mut Outcome, limit: usize) -> bool {
+    let fields = match outcome {
+        Outcome::Success { output: text } | Outcome::Failure { error: text } => vec![text],
+        Outcome::Command(output) => vec![&mut output.stdout, &mut output.stderr],
+    };
+
+    let mut changed = false;
+    for text in fields {
+        if let Some(short) = truncate_text(text, limit) {
+            *text = short;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Replaces all but the most recent messages with a `Message::Summary`.
+///
+/// Earlier summaries in the replaced prefix are folded into the new one.
+/// After the rewrite, the context size is re-estimated from the messages
+/// and the system prompt, so the threshold check does not fire again
+/// before the next LLM call, nor report the window emptier than it is.
+///
+/// # Arguments
+/// - `llm`: The client used to produce the summary.
+/// - `ctx`: The conversation context.
+/// - `keep_recent`: Number of trailing messages kept verbatim.
+///
+/// # Returns
+/// The number of messages replaced.
+pub async fn compact(
+    llm: &LLMClient,
+    ctx: &mut Context,
+    keep_recent: usize,
+) -> Result<usize, String> {
+    let end = ctx.len().saturating_sub(keep_recent);
+    if end == 0 {
+        return Err("not enough history to compact".to_string());
+    }
+
+    let budget = llm.context_window() * 2;
+    let transcript = render_transcript(&ctx.messages()[..end], budget);
+    let request = vec![
+        serde_json::json!({ "role": "system", "content": COMPACT_PROMPT }),
+        serde_json::json!({ "role": "user", "content": transcript }),
+    ];
+
+    let (summary, usage) = llm.complete(&request).await?;
+    ctx.add_total_tokens(usage.total_tokens);
+
+    let messages = ctx.messages_mut();
+    messages.drain(..end);
+    messages.insert(
+        0,
+        Message::Summary {
+            content: summary.trim().to_string(),
+            replaced: end,
+        },
+    );
+
+    let system_prompt = llm.prompt_engine().system_prompt(ctx);
+    let estimate = ctx.estimate_tokens() + system_prompt.chars().count().div_ceil(4);
+    ctx.set_last_usage(Usage {
+        prompt_tokens: estimate,
+        completion_tokens: 0,
+        total_tokens: estimate,
+    });
+    Ok(end)
+}
+
+/// Renders messages as plain text for summarization, keeping at most
+/// `budget` characters from the end.
+fn render_transcript(messages: &[Message], budget: usize) -> String {
+    let text = messages
+        .iter()
+        .map(|m| m.to_json())
+        .map(|v: Value| {
+            format!(
+                "[{}] {}",
+                v["role"].as_str().unwrap_or_default(),
+                v["content"].as_str().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let len = text.chars().count();
+    if len <= budget {
+        return text;
+    }
+    text.chars().skip(len - budget).collect()
+}
+
+/// Truncates `text` to `limit` characters with a marker.
+///
+/// Text that already ends in a marker is left alone, so repeated
+/// recoveries do not cut it again.
+///
+/// # Returns
+/// The shortened text, or `None` if it already fits, was already
+/// truncated, or would not get shorter with the marker.
+fn truncate_text(text: &str, limit: usize) -> Option<String> {
+    if is_truncated(text) {
+        return None;
+    }
+    let (cut, _) = text.char_indices().nth(limit)?;
+    let dropped = text[cut..].chars().count();
+    let short = format!("{}\n...[truncated {} chars]", &text[..cut], dropped);
+    (short.chars().count() < text.chars().count()).then_some(short)
+}
+
+/// Returns `true` if `text` ends in the marker added by `truncate_text`.
+fn is_truncated(text: &str) -> bool {
+    text.strip_suffix(" chars]")
+        .and_then(|rest| rest.rsplit_once("\n...[truncated "))
+        .is_some_and(|(_, count)| !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RetryConfig;
+    use crate::core::context::CommandOutput;
+    use crate::core::llm::tests::{client, http_response, serve_responses};
+
+    #[test]
+    fn test_is_context_overflow() {
+        assert!(is_context_overflow(
+            "LLM returned 400 Bad Request: {\"error\":{\"code\":\"context_length_exceeded\"}}"
+        ));
+        assert!(is_context_overflow(
+            "This model's maximum context length is 8192 tokens"
+        ));
+        assert!(is_context_overflow(
+            "prompt is too long: 210000 tokens > 200000 maximum"
+        ));
+        assert!(!is_context_overflow(
+            "LLM returned 401 Unauthorized: invalid api key"
+        ));
+    }
+
+    #[test]
+    fn test_overflows() {
+        let rate_limit = "LLM returned 429 Too Many Requests: too many tokens per minute";
+        assert!(!overflows(Some(Failure::RateLimit), rate_limit));
+        assert!(overflows(Some(Failure::Overflow), "simulated overflow"));
+        assert!(overflows(
+            None,
+            "prompt is too long: 210000 tokens > 200000 maximum"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_leaves_context_on_rate_limit() {
+        let response = http_response(
+            "429 Too Many Requests",
+            "text/plain",
+            "",
+            "too many tokens per minute",
+        );
+        let (url, _requests) = serve_responses(vec![response]).await;
+        let llm = client(url, false).with_retry(RetryConfig {
+            max_attempts: 1,
+            ..RetryConfig::default()
+        });
+        let mut guard = ContextGuard::new(
+            llm,
+            ContextConfig {
+                keep_recent: 0,
+                ..ContextConfig::default()
+            },
+        );
+        let mut ctx = Context::new();
+        let long = "x".repeat(OBSERVATION_LIMIT * 2);
+        ctx.add_user_message("hi".to_string());
+        ctx.add_observation(
+            "cat".to_string(),
+            Outcome::Success {
+                output: long.clone(),
+            },
+        );
+
+        let action = guard.run(&mut ctx).await;
+        assert!(
+            matches!(&action, Action::Completed { answer } if answer.starts_with("LLM rate_limit error")),
+            "{:?}",
+            action
+        );
+        assert_eq!(guard.failure(), Some(Failure::RateLimit));
+        assert_eq!(ctx.len(), 2);
+        assert!(
+            matches!(&ctx.messages()[1], Message::Observation { content, .. } if *content == long)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_estimates_system_prompt() {
+        let reply = serde_json::json!({
+            "choices": [{ "message": { "content": "user said hello twice" } }],
+        });
+        let response = http_response("200 OK", "application/json", "", &reply.to_string());
+        let (url, _requests) = serve_responses(vec![response]).await;
+        let llm = client(url, false);
+        let mut ctx = Context::new();
+        for text in ["hello", "hello again", "bye"] {
+            ctx.add_user_message(text.to_string());
+        }
+
+        assert_eq!(compact(&llm, &mut ctx, 1).await.unwrap(), 2);
+        let system_prompt = llm.prompt_engine().system_prompt(&ctx);
+        assert!(!system_prompt.is_empty());
+        assert_eq!(
+            ctx.context_tokens(),
+            ctx.estimate_tokens() + system_prompt.chars().count().div_ceil(4)
+        );
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        assert!(truncate_text("short", 10).is_none());
+        let short = truncate_text(&format!("abcd{}", "x".repeat(96)), 4).unwrap();
+        assert!(short.starts_with("abcd\n"));
+        assert!(short.contains("[truncated 96 chars]"));
+    }
+
+    #[test]
+    fn test_truncate_text_is_idempotent() {
+        let short = truncate_text(&"x".repeat(100), 10).unwrap();
+        assert!(truncate_text(&short, 10).is_none());
+        // Cutting 5 chars would add a longer marker
+        assert!(truncate_text(&"x".repeat(15), 10).is_none());
+    }
+
+    #[test]
+    fn test_truncate_text_multibyte() {
+        let short = truncate_text(&"日本語のテキスト".repeat(10), 3).unwrap();
+        assert!(short.starts_with("日本語\n"));
+    }
+
+    #[test]
+    fn test_truncate_observations_keeps_recent() {
+        let mut ctx = Context::new();
+        let long = "x".repeat(100);
+        ctx.add_observation(
+            "old".to_string(),
+            Outcome::Success {
+                output: long.clone(),
+            },
+        );
+        ctx.add_observation(
+            "recent".to_string(),
+            Outcome::Success {
+                output: long.clone(),
+            },
+        );
+
+        let count = truncate_observations(&mut ctx, 1, 10);
+        assert_eq!(count, 1);
+        let truncated = ctx.messages()[0].clone();
+        assert_eq!(truncate_observations(&mut ctx, 1, 10), 0);
+        assert_eq!(
+            serde_json::to_value(&ctx.messages()[0]).unwrap(),
+            serde_json::to_value(&truncated).unwrap()
+        );
+
+        if let Message::Observation {
+            content, outcome, ..
+        } = &ctx.messages()[0]
+        {
+            assert!(content.contains("[truncated 90 chars]"));
+            assert!(matches!(outcome, Outcome::Success { output } if output == content));
+        } else {
+            panic!("expected Observation message");
+        }
+        if let Message::Observation { content, .. } = &ctx.messages()[1] {
+            assert_eq!(content, &long);
+        } else {
+            panic!("expected Observation message");
+        }
+    }
+
+    #[test]
+    fn test_truncate_observations_command() {
+        let mut ctx = Context::new();
+        ctx.add_observation(
+            "cargo test".to_string(),
+            Outcome::Command(CommandOutput {
+                exit_code: Some(101),
+                stdout: "y".repeat(50),
+                stderr: "short".to_string(),
+                ..CommandOutput::default()
+            }),
+        );
+
+        assert_eq!(truncate_observations(&mut ctx, 0, 10), 1);
+        if let Message::Observation {
+            outcome: Outcome::Command(output),
+            content,
+            ..
+        } = &ctx.messages()[0]
+        {
+            assert!(output.stdout.contains("[truncated 40 chars]"));
+            assert_eq!(output.stderr, "short");
+            assert!(content.contains("[truncated 40 chars]"));
+        } else {
+            panic!("expected command Observation message");
+        }
+    }
+
+    #[test]
+    fn test_truncate_observations_nothing_to_do() {
+        let mut ctx = Context::new();
+        ctx.add_user_message("hello".to_string());
+        ctx.add_observation(
+            "s".to_string(),
+            Outcome::Failure {
+                error: "err".to_string(),
+            },
+        );
+        assert_eq!(truncate_observations(&mut ctx, 0, 10), 0);
+    }
+
+    #[test]
+    fn test_summary_to_json() {
+        let summary = Message::Summary {
+            content: "user wants a build fix".to_string(),
+            replaced: 7,
+        };
+        let json = summary.to_json();
+        assert_eq!(json["role"], "user");
+        let inner: Value = serde_json::from_str(json["content"].as_str().unwrap()).unwrap();
+        assert_eq!(inner["role"], "summary");
+        assert_eq!(inner["replaced_messages"], 7);
+        assert!(summary.estimate_tokens() > 0);
+    }
+
+    #[test]
+    fn test_render_transcript_budget() {
+        let messages = vec![
+            Message::User {
+                content: "first".to_string(),
+            },
+            Message::User {
+                content: "second".to_string(),
+            },
+        ];
+        let full = render_transcript(&messages, 1000);
+        assert!(full.contains("[user] first"));
+        assert!(full.contains("[user] second"));
+
+        let tail = render_transcript(&messages, 6);
+        assert_eq!(tail, "second");
+    }
+}
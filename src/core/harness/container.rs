@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::Backend;
+
+/// Backend that runs commands in a long-lived Docker or Podman container.
+///
+/// The container is started on first use with the workspace bind-mounted,
+/// kept alive with `sleep infinity`, and removed when the backend is
+/// dropped. Each command runs via `<runtime> exec`.
+///
+/// # Fields
+/// - `runtime`: Container CLI, e.g. `"docker"` or `"podman"`.
+/// - `image`: Image to start.
+/// - `workspace`: Host directory mounted into the container.
+/// - `mount`: Mount point of the workspace inside the container.
+/// - `run_args`: Extra arguments for `<runtime> run`.
+/// - `name`: Container name, unique per backend.
+/// - `started`: Whether the container is running.
+pub struct Container {
+    runtime: String,
+    image: String,
+    workspace: PathBuf,
+    mount: String,
+    run_args: Vec<String>,
+    name: String,
+    started: bool,
+}
+
+impl Container {
+    /// Creates a container backend. Nothing is started until the first command.
+    ///
+    /// # Arguments
+    /// - `runtime`: Container CLI, e.g. `"docker"` or `"podman"`.
+    /// - `image`: Image to start.
+    /// - `workspace`: Host directory mounted into the container.
+    /// - `mount`: Mount point of the workspace inside the container.
+    /// - `run_args`: Extra arguments for `<runtime> run`.
+    pub fn new(
+        runtime: &str,
+        image: &str,
+        workspace: &Path,
+        mount: &str,
+        run_args: &[String],
+    ) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            runtime: runtime.to_string(),
+            image: image.to_string(),
+            workspace: workspace.to_path_buf(),
+            mount: mount.to_string(),
+            run_args: run_args.to_vec(),
+            name: format!("minusagent-{}", &id[..8]),
+            started: false,
+        }
+    }
+
+    /// Returns the arguments that start the container.
+    fn run_command(&self) -> Vec<String> {
+        let mut args = vec![
+            "run".to_string(),
+            "-d".to_string(),
+            "--rm".to_string(),
+            "--name".to_string(),
+            self.name.clone(),
+            "-v".to_string(),
+            format!("{}:{}", self.workspace.display(), self.mount),
+            "-w".to_string(),
+            self.mount.clone(),
+        ];
+        args.extend(self.run_args.iter().cloned());
+        args.extend([
+            self.image.clone(),
+            "sleep".to_string(),
+            "infinity".to_string(),
+        ]);
+        args
+    }
+
+    /// Returns the arguments that run `script` in the container.
+    fn exec_command(&self, script: &str) -> Vec<String> {
+        vec![
+            "exec".to_string(),
+            self.name.clone(),
+            "sh".to_string(),
+            "-c".to_string(),
+            script.to_string(),
+        ]
+    }
+}
+
+#[async_trait]
+impl Backend for Container {
+    fn kind(&self) -> &'static str {
+        "container"
+    }
+
+    fn root(&self) -> PathBuf {
+        PathBuf::from(&self.mount)
+    }
+
+    async fn prepare(&mut self) -> Result<(), String> {
+        if self.started {
+            return Ok(());
+        }
+        let output = Command::new(&self.runtime)
+            .args(self.run_command())
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| format!("failed to run {}: {}", self.runtime, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "failed to start container from {}: {}",
+                self.image,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let mut cmd = Command::new(&self.runtime);
+        cmd.args(self.exec_command(script));
+        cmd
+    }
+}
+
+impl Drop for Container {
+    /// Removes the container in the background.
+    fn drop(&mut self) {
+        if self.started {
+            let _ = std::process::Command::new(&self.runtime)
+                .args(["rm", "-f", &self.name])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container() -> Container {
+        Container::new(
+            "podman",
+            "alpine:3",
+            Path::new("/home/me/project"),
+            "/workspace",
+            &["--network=none".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_run_command() {
+        let container = container();
+        let args = container.run_command();
+        assert_eq!(args[..5], ["run", "-d", "--rm", "--name", &container.name]);
+        assert_eq!(
+            args[5..],
+            [
+                "-v",
+                "/home/me/project:/workspace",
+                "-w",
+                "/workspace",
+                "--network=none",
+                "alpine:3",
+                "sleep",
+                "infinity",
+            ]
+        );
+        assert!(container.name.starts_with("minusagent-"));
+    }
+
+    #[test]
+    fn test_exec_command() {
+        let container = container();
+        assert_eq!(
+            container.exec_command("ls -la"),
+            ["exec", &container.name, "sh", "-c", "ls -la"]
+        );
+        assert_eq!(container.root(), PathBuf::from("/workspace"));
+    }
+
+    #[tokio::test]
+    async fn test_prepare_fails_without_runtime() {
+        let mut container = Container::new(
+            "minusagent-no-such-runtime",
+            "alpine:3",
+            Path::new("/tmp"),
+            "/workspace",
+            &[],
+        );
+        let err = container.prepare().await.unwrap_err();
+        assert!(err.contains("failed to run minusagent-no-such-runtime"));
+        assert!(!container.started);
+    }
+}
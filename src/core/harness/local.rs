@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{Backend, workspace_dir};
+
+/// Backend that runs commands with `sh` on this machine.
+///
+/// # Fields
+/// - `root`: Directory commands start in (`workdir`, or the process cwd).
+pub struct Local {
+    root: PathBuf,
+}
+
+impl Local {
+    /// Creates a local backend.
+    ///
+    /// # Arguments
+    /// - `workdir`: Directory commands start in, or `None` for the process cwd.
+    pub fn new(workdir: Option<&str>) -> Self {
+        Self {
+            root: workspace_dir(workdir),
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for Local {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    fn root(&self) -> PathBuf {
+        self.root.clone()
+    }
+
+    fn temp_dir(&self) -> PathBuf {
+        std::env::temp_dir()
+    }
+
+    async fn prepare(&mut self) -> Result<(), String> {
+        if !self.root.is_dir() {
+            return Err(format!(
+                "working directory {} does not exist",
+                self.root.display()
+            ));
+        }
+        Ok(())
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    async fn take_file(&self, path: &Path) -> Option<Vec<u8>> {
+        let data = std::fs::read(path).ok()?;
+        let _ = std::fs::remove_file(path);
+        Some(data)
+    }
+
+    /// Killing the local process group already reached every process;
+    /// only the pid file is left to clean up.
+    async fn kill(&self, pid_file: &Path) {
+        let _ = std::fs::remove_file(pid_file);
+    }
+}
@@ -0,0 +1,1054 @@
+pub mod container;
+pub mod local;
+pub mod sandbox;
+pub mod ssh;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{BackendConfig, HarnessConfig};
+use crate::core::context::{CommandOutput, Context, Outcome};
+use crate::core::fault::{Fault, Faults};
+use crate::core::{Action, INTERRUPTED, Node};
+use container::Container;
+use local::Local;
+use sandbox::Sandbox;
+use ssh::Ssh;
+
+/// Name of the built-in backend that runs commands on this machine.
+pub const LOCAL: &str = "local";
+
+/// Where and how commands run: this machine, a container, a remote host.
+///
+/// A backend only decides how to start `sh` on its target and how to reach
+/// files there. Everything else — the shell prelude that carries state,
+/// timeouts, output capture, and observations — is shared by `Harness`.
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// Returns the backend kind, e.g. `"local"` or `"container"`.
+    fn kind(&self) -> &'static str;
+
+    /// Returns the directory commands start in on the target.
+    fn root(&self) -> PathBuf;
+
+    /// Returns a directory on the target for the harness's scratch files.
+    fn temp_dir(&self) -> PathBuf {
+        PathBuf::from("/tmp")
+    }
+
+    /// Makes the target ready, e.g. starts the container. Called before
+    /// every command, so it must be cheap once the target is up.
+    async fn prepare(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Builds the local process that runs `script` with `sh` on the target.
+    ///
+    /// # Arguments
+    /// - `script`: A complete shell script.
+    fn command(&self, script: &str) -> Command;
+
+    /// Reads and deletes a file on the target.
+    ///
+    /// # Returns
+    /// The file contents, or `None` if it does not exist.
+    async fn take_file(&self, path: &Path) -> Option<Vec<u8>> {
+        let path = quote(&path.to_string_lossy());
+        let script = format!("cat {} && rm -f {}", path, path);
+        let output = self.command(&script).output().await.ok()?;
+        output.status.success().then_some(output.stdout)
+    }
+
+    /// Kills what a command left running on the target after its local
+    /// process group was killed.
+    ///
+    /// # Arguments
+    /// - `pid_file`: File on the target holding the shell's pid.
+    async fn kill(&self, pid_file: &Path) {
+        let pid_file = quote(&pid_file.to_string_lossy());
+        let script = format!(
+            "p=$(cat {f} 2>/dev/null) && {{ kill -KILL -- -\"$p\" 2>/dev/null || \
+             {{ pkill -KILL -P \"$p\" 2>/dev/null; kill -KILL \"$p\" 2>/dev/null; }}; }}; rm -f {f}",
+            f = pid_file
+        );
+        let _ = self.command(&script).output().await;
+    }
+
+    /// Checks a finished command for signs that the backend itself failed
+    /// or blocked it, e.g. a sandbox violation.
+    ///
+    /// # Returns
+    /// An error that replaces the command observation with a failure.
+    fn failure(&self, _output: &CommandOutput) -> Option<String> {
+        None
+    }
+}
+
+/// Builds the backend registered under `name`.
+///
+/// `"local"` is always available, even if not listed in `backends`.
+///
+/// # Arguments
+/// - `config`: Harness settings holding the named backends.
+/// - `name`: The backend to build.
+pub fn build_backend(config: &HarnessConfig, name: &str) -> Result<Box<dyn Backend>, String> {
+    let backend = match config.backends.get(name) {
+        Some(backend) => backend,
+        None if name == LOCAL => &BackendConfig::Local,
+        None => return Err(format!("unknown harness backend '{}'", name)),
+    };
+    Ok(match backend {
+        BackendConfig::Local => Box::new(Local::new(config.workdir.as_deref())),
+        BackendConfig::Container {
+            runtime,
+            image,
+            workspace,
+            mount,
+            run_args,
+        } => Box::new(Container::new(
+            runtime,
+            image,
+            &workspace_dir(workspace.as_deref().or(config.workdir.as_deref())),
+            mount,
+            run_args,
+        )),
+        BackendConfig::Sandbox {
+            tool,
+            workspace,
+            writable,
+            network,
+        } => Box::new(Sandbox::new(
+            tool,
+            &workspace_dir(workspace.as_deref().or(config.workdir.as_deref())),
+            writable,
+            *network,
+        )),
+        BackendConfig::Ssh {
+            host,
+            user,
+            port,
+            workdir,
+            env,
+            ssh_args,
+        } => Box::new(Ssh::new(
+            host,
+            user.as_deref(),
+            *port,
+            workdir.as_deref(),
+            env,
+            ssh_args,
+        )),
+    })
+}
+
+/// Resolves a host directory, defaulting to the process working directory.
+fn workspace_dir(dir: Option<&str>) -> PathBuf {
+    match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    }
+}
+
+/// Quotes a string for the shell with single quotes.
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Shell state carried between commands in persistent mode.
+///
+/// # Fields
+/// - `cwd`: Working directory for the next command.
+/// - `env`: Exported environment of the last command, or empty to inherit
+///   the target's environment.
+/// - `unset`: Variables of the target's environment the last command
+///   removed, unset again before the next one.
+#[derive(Debug, Clone)]
+struct ShellState {
+    cwd: PathBuf,
+    env: HashMap<String, String>,
+    unset: BTreeSet<String>,
+}
+
+impl ShellState {
+    fn new(cwd: PathBuf) -> Self {
+        Self {
+            cwd,
+            env: HashMap::new(),
+            unset: BTreeSet::new(),
+        }
+    }
+
+    /// Parses the state dumped by `DUMP_STATE`: the working directory, one
+    /// `NAME=value` line per exported variable, with `%` and newlines
+    /// percent-encoded, and one `-NAME` line per removed variable.
+    ///
+    /// # Returns
+    /// `None` if the dump is empty.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let data = String::from_utf8_lossy(data);
+        let mut lines = data.lines();
+        let mut state = Self::new(PathBuf::from(decode(
+            lines.next().filter(|l| !l.is_empty())?,
+        )));
+
+        let carried = |key: &str| is_name(key) && !matches!(key, "_" | "SHLVL");
+        for line in lines {
+            if let Some(key) = line.strip_prefix('-') {
+                if carried(key) {
+                    state.unset.insert(key.to_string());
+                }
+            } else if let Some((key, value)) = line.split_once('=')
+                && carried(key)
+            {
+                state.env.insert(key.to_string(), decode(value));
+            }
+        }
+        Some(state)
+    }
+}
+
+/// Shell snippet recording the names of the target's exported variables
+/// before the prelude restores any state.
+const BASE_NAMES: &str = "__minusagent_base=$(awk 'BEGIN { for (k in ENVIRON) print k }')";
+
+/// Shell snippet printing the working directory, the exported variables
+/// and the variables missing since `BASE_NAMES`, in the format read by
+/// `ShellState::parse`. Uses only POSIX `awk`, so it also works with
+/// BusyBox and BSD userlands.
+const DUMP_STATE: &str = "\
+__minusagent_pwd=\"$PWD\" __minusagent_base=\"$__minusagent_base\" awk '
+function enc(s) { gsub(/%/, \"%25\", s); gsub(/\\n/, \"%0A\", s); return s }
+BEGIN {
+  print enc(ENVIRON[\"__minusagent_pwd\"])
+  for (k in ENVIRON) if (k !~ /^__minusagent_/) print k \"=\" enc(ENVIRON[k])
+  n = split(ENVIRON[\"__minusagent_base\"], base, \"\\n\")
+  for (i = 1; i <= n; i++) if (!(base[i] in ENVIRON)) print \"-\" base[i]
+}'";
+
+/// Reverses the percent-encoding of `DUMP_STATE`.
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('%') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (char, len) = match rest.get(..3) {
+            Some("%25") => ('%', 3),
+            Some("%0A") => ('\n', 3),
+            _ => ('%', 1),
+        };
+        decoded.push(char);
+        rest = &rest[len..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Longest partial line held back before it is streamed anyway.
+const MAX_CHUNK_BYTES: usize = 8192;
+
+/// How long output is still read after the shell exits, before whatever it
+/// left running in the background is killed and the captures are closed.
+const READ_GRACE: Duration = Duration::from_millis(200);
+
+/// Output stream of a running command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Piece of output streamed while a command runs: one or more complete
+/// lines, or the unterminated rest when the stream ends.
+///
+/// # Fields
+/// - `stream`: Which stream the text came from.
+/// - `text`: The output text, including line endings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputChunk {
+    pub stream: Stream,
+    pub text: String,
+}
+
+/// Returns `true` if `key` is a valid shell variable name.
+fn is_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Execution environment that runs shell commands through the Node pipeline.
+///
+/// - **prep**: validates that a command is set and prepares the backend.
+/// - **exec**: runs the command via the backend (pure compute, no shared access).
+/// - **post**: records the `CommandOutput` as an observation.
+///
+/// Any command that runs to an end, including a non-zero exit or a
+/// timeout, yields a structured observation with exit code, stdout,
+/// stderr, and duration. Only spawn errors and user interrupts fail the
+/// step. Whether a command may run at all is decided beforehand by the
+/// session's `Policy`.
+///
+/// Each command runs in its own local process group with stdin closed, so
+/// a terminal Ctrl+C does not reach it directly; instead, cancelling the
+/// token kills the whole group, and the backend kills whatever is left on
+/// the target. The same happens when the configured timeout expires.
+/// Output beyond `max_output_bytes` is drained but not kept. With an
+/// output channel set, every line is also streamed as an `OutputChunk`
+/// while the command runs, including lines past the cap.
+///
+/// The command is wrapped in a shell prelude that records the shell's pid,
+/// applies rlimits, and changes to the working directory. With
+/// `persistent_shell`, the prelude also restores the exported variables
+/// left by the previous command and dumps the new state on exit, so `cd`
+/// and `export` behave as in an interactive shell on every backend.
+///
+/// # Fields
+/// - `command`: The shell command string to execute.
+/// - `cancel`: Token that aborts the running command when cancelled.
+/// - `output`: Channel that receives output while a command runs.
+/// - `faults`: Synthetic failures to return instead of running the command.
+/// - `config`: Timeout, output, rlimit, and shell state settings.
+/// - `name`: Name of the configured backend.
+/// - `backend`: Where commands run.
+/// - `state`: Working directory and environment for the next command.
+/// - `id`: Unique id used to name scratch files on the target.
+pub struct Harness {
+    command: Option<String>,
+    cancel: CancellationToken,
+    output: Option<UnboundedSender<OutputChunk>>,
+    faults: Faults,
+    config: HarnessConfig,
+    name: String,
+    backend: Box<dyn Backend>,
+    state: ShellState,
+    id: String,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new(HarnessConfig::default())
+    }
+}
+
+impl Harness {
+    /// Creates a harness that runs commands on this machine.
+    ///
+    /// # Arguments
+    /// - `config`: Timeout, output, rlimit, and shell state settings.
+    pub fn new(config: HarnessConfig) -> Self {
+        let backend = Box::new(Local::new(config.workdir.as_deref()));
+        Self::with_backend(config, LOCAL.to_string(), backend)
+    }
+
+    /// Creates a harness that runs commands on the named backend.
+    ///
+    /// # Arguments
+    /// - `config`: Harness settings, including the named backends.
+    /// - `name`: The backend to use (`"local"` or a key of `backends`).
+    ///
+    /// # Returns
+    /// The harness, or an error if the backend is unknown.
+    pub fn build(config: HarnessConfig, name: &str) -> Result<Self, String> {
+        let backend = build_backend(&config, name)?;
+        Ok(Self::with_backend(config, name.to_string(), backend))
+    }
+
+    fn with_backend(config: HarnessConfig, name: String, backend: Box<dyn Backend>) -> Self {
+        Self {
+            command: None,
+            cancel: CancellationToken::new(),
+            output: None,
+            faults: Faults::default(),
+            state: ShellState::new(backend.root()),
+            config,
+            name,
+            backend,
+            id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Replaces limits and shell settings, keeping the backend.
+    ///
+    /// Shell state is kept unless `persistent_shell` is turned off.
+    ///
+    /// # Arguments
+    /// - `config`: The new settings.
+    pub fn set_config(&mut self, config: HarnessConfig) {
+        if !config.persistent_shell {
+            self.state = ShellState::new(self.backend.root());
+        }
+        self.config = config;
+    }
+
+    /// Returns the name of the configured backend.
+    pub fn backend(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the backend kind, e.g. `"container"`.
+    pub fn kind(&self) -> &'static str {
+        self.backend.kind()
+    }
+
+    /// Returns the working directory for the next command on the target.
+    pub fn cwd(&self) -> &Path {
+        &self.state.cwd
+    }
+
+    /// Sets the command to execute in the next run.
+    ///
+    /// # Arguments
+    /// - `command`: The shell command string (supports `&&`, `||`, pipes, etc.).
+    pub fn set_command(&mut self, command: String) {
+        self.command = Some(command);
+    }
+
+    /// Sets the cancellation token for subsequent runs.
+    ///
+    /// # Arguments
+    /// - `cancel`: Token signalling a user interrupt.
+    pub fn set_cancel(&mut self, cancel: CancellationToken) {
+        self.cancel = cancel;
+    }
+
+    /// Sets the channel that receives output chunks in subsequent runs.
+    ///
+    /// # Arguments
+    /// - `output`: Sender for the chunks; the final observation is unaffected.
+    pub fn set_output(&mut self, output: UnboundedSender<OutputChunk>) {
+        self.output = Some(output);
+    }
+
+    /// Sets the faults to inject; `CommandCrash` fails a command before it runs.
+    ///
+    /// # Arguments
+    /// - `faults`: The pending faults, usually shared with the session.
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = faults;
+    }
+
+    /// Returns the path of a scratch file on the target.
+    fn scratch_file(&self, ext: &str) -> PathBuf {
+        self.backend
+            .temp_dir()
+            .join(format!("minusagent-{}.{}", self.id, ext))
+    }
+
+    /// Wraps `command` in the shell prelude.
+    ///
+    /// The prelude records the pid for remote kills, applies rlimits via
+    /// `ulimit`, restores exported and unset variables, changes to the
+    /// working directory (falling back to the root if it was removed), and
+    /// installs an `EXIT` trap that dumps the resulting state while
+    /// preserving the command's exit status.
+    fn script(&self, command: &str) -> String {
+        let pid_file = quote(&self.scratch_file("pid").to_string_lossy());
+        let root = quote(&self.backend.root().to_string_lossy());
+        let mut lines = vec![format!("echo $$ > {} 2>/dev/null", pid_file)];
+
+        if let Some(secs) = self.config.cpu_secs {
+            lines.push(format!("ulimit -t {}", secs));
+        }
+        if let Some(mb) = self.config.memory_mb {
+            lines.push(format!("ulimit -v {}", mb * 1024));
+        }
+
+        if self.config.persistent_shell {
+            lines.push(BASE_NAMES.to_string());
+            let mut env: Vec<_> = self.state.env.iter().collect();
+            env.sort();
+            lines.extend(
+                env.into_iter()
+                    .map(|(key, value)| format!("export {}={}", key, quote(value))),
+            );
+            if !self.state.unset.is_empty() {
+                let names: Vec<_> = self.state.unset.iter().map(String::as_str).collect();
+                lines.push(format!("unset {}", names.join(" ")));
+            }
+            let cwd = quote(&self.state.cwd.to_string_lossy());
+            lines.push(format!("cd {} 2>/dev/null || cd {} || exit 126", cwd, root));
+            lines.push(format!(
+                "__minusagent_state={}",
+                quote(&self.scratch_file("state").to_string_lossy())
+            ));
+            lines.push(format!(
+                "trap {} EXIT",
+                quote(&format!(
+                    "__minusagent_status=$?\n{} > \"$__minusagent_state\"\n\
+                     rm -f {}; exit $__minusagent_status",
+                    DUMP_STATE, pid_file
+                ))
+            ));
+        } else {
+            lines.push(format!("cd {} || exit 126", root));
+            lines.push(format!(
+                "trap 'rm -f {}' EXIT",
+                pid_file.replace('\'', "'\\''")
+            ));
+        }
+
+        lines.push(command.to_string());
+        lines.join("\n")
+    }
+}
+
+/// How a running command ended.
+///
+/// # Variants
+/// - `Exited`: The command and its output streams finished.
+/// - `TimedOut`: The wall-clock limit expired.
+/// - `Cancelled`: The user interrupted the turn.
+enum Ending {
+    Exited(io::Result<ExitStatus>),
+    TimedOut,
+    Cancelled,
+}
+
+/// Output stream captured up to a byte limit, optionally streamed.
+///
+/// # Fields
+/// - `data`: The first `limit` bytes read.
+/// - `total`: Total bytes read, including dropped ones.
+/// - `limit`: Maximum bytes kept.
+/// - `stream`: Which stream this is, for streamed chunks.
+/// - `output`: Channel that receives complete lines as they arrive.
+/// - `line`: Bytes of the current line not yet streamed.
+struct Capture {
+    data: Vec<u8>,
+    total: usize,
+    limit: usize,
+    stream: Stream,
+    output: Option<UnboundedSender<OutputChunk>>,
+    line: Vec<u8>,
+}
+
+impl Capture {
+    fn new(limit: usize, stream: Stream, output: Option<UnboundedSender<OutputChunk>>) -> Self {
+        Self {
+            data: Vec::new(),
+            total: 0,
+            limit,
+            stream,
+            output,
+            line: Vec::new(),
+        }
+    }
+
+    /// Reads `reader` to the end, keeping at most `limit` bytes.
+    ///
+    /// Keeps draining past the limit so the child never blocks on a full pipe.
+    async fn read_from<R: AsyncRead + Unpin>(&mut self, reader: Option<R>) -> io::Result<()> {
+        let Some(mut reader) = reader else {
+            return Ok(());
+        };
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                self.send(self.line.len());
+                return Ok(());
+            }
+            let keep = n.min(self.limit.saturating_sub(self.data.len()));
+            self.data.extend_from_slice(&buf[..keep]);
+            self.total += n;
+
+            if self.output.is_some() {
+                self.line.extend_from_slice(&buf[..n]);
+                match self.line.iter().rposition(|b| *b == b'\n') {
+                    Some(end) => self.send(end + 1),
+                    None if self.line.len() >= MAX_CHUNK_BYTES => self.send(self.line.len()),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Streams the first `len` bytes of the pending line.
+    fn send(&mut self, len: usize) {
+        if let Some(output) = &self.output
+            && len > 0
+        {
+            let text = String::from_utf8_lossy(&self.line[..len]).to_string();
+            self.line.drain(..len);
+            let _ = output.send(OutputChunk {
+                stream: self.stream,
+                text,
+            });
+        }
+    }
+
+    /// Returns the captured bytes as (lossy) UTF-8 text.
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).to_string()
+    }
+
+    /// Returns the number of bytes dropped past the limit.
+    fn dropped(&self) -> usize {
+        self.total - self.data.len()
+    }
+}
+
+/// Sends `SIGKILL` to every process in the group led by `pid`.
+fn kill_group(pid: u32) {
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative pid
+    // addresses the process group created via `process_group(0)`.
+    unsafe {
+        libc::kill(-(pid as i32), libc::SIGKILL);
+    }
+}
+
+#[async_trait]
+impl Node for Harness {
+    /// Validates that a command is set and makes the backend ready.
+    async fn prep(&mut self, _shared: &Context) -> Result<Value, String> {
+        let command = self.command.as_ref().ok_or("no command set")?.clone();
+        self.backend.prepare().await?;
+        Ok(Value::String(command))
+    }
+
+    /// Runs the command on the backend, enforcing the configured timeout,
+    /// output cap, and rlimits. Returns the serialized `CommandOutput`.
+    async fn exec(&mut self, prep_res: Value) -> Result<Value, String> {
+        let command = prep_res.as_str().ok_or("prep_res is not a string")?;
+        if self.faults.take(|f| f == Fault::CommandCrash).is_some() {
+            return Err("execution failed: simulated command crash".to_string());
+        }
+
+        let mut cmd = self.backend.command(&self.script(command));
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+
+        let start = Instant::now();
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("execution failed: {}", e))?;
+        let pid = child.id();
+        let (stdout_pipe, stderr_pipe) = (child.stdout.take(), child.stderr.take());
+        let limit = self.config.max_output_bytes;
+        let mut stdout = Capture::new(limit, Stream::Stdout, self.output.clone());
+        let mut stderr = Capture::new(limit, Stream::Stderr, self.output.clone());
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        let ending = tokio::select! {
+            result = async {
+                let reading = async {
+                    let (out, err) = tokio::join!(
+                        stdout.read_from(stdout_pipe),
+                        stderr.read_from(stderr_pipe),
+                    );
+                    out.and(err)
+                };
+                tokio::pin!(reading);
+                let (status, read) = tokio::select! {
+                    status = child.wait() => (status, None),
+                    read = &mut reading => (child.wait().await, Some(read)),
+                };
+                let read = match read {
+                    Some(read) => read,
+                    // A background process may still hold the pipes open
+                    None => match tokio::time::timeout(READ_GRACE, &mut reading).await {
+                        Ok(read) => read,
+                        Err(_) => {
+                            if let Some(pid) = pid {
+                                kill_group(pid);
+                            }
+                            self.backend.kill(&self.scratch_file("pid")).await;
+                            Ok(())
+                        }
+                    },
+                };
+                read.and(status)
+            } => Ending::Exited(result),
+            _ = tokio::time::sleep(timeout) => Ending::TimedOut,
+            _ = self.cancel.cancelled() => Ending::Cancelled,
+        };
+
+        let (exit_code, timed_out) = match ending {
+            Ending::Exited(status) => {
+                let status = status.map_err(|e| format!("execution failed: {}", e))?;
+                (status.code(), false)
+            }
+            Ending::TimedOut | Ending::Cancelled => {
+                if let Some(pid) = pid {
+                    kill_group(pid);
+                }
+                self.backend.kill(&self.scratch_file("pid")).await;
+                if matches!(ending, Ending::Cancelled) {
+                    return Err(INTERRUPTED.to_string());
+                }
+                (None, true)
+            }
+        };
+
+        if self.config.persistent_shell
+            && !timed_out
+            && let Some(data) = self.backend.take_file(&self.scratch_file("state")).await
+            && let Some(state) = ShellState::parse(&data)
+        {
+            self.state = state;
+        }
+
+        let output = CommandOutput {
+            cwd: self.state.cwd.display().to_string(),
+            exit_code,
+            stdout: stdout.text(),
+            stderr: stderr.text(),
+            duration_ms: start.elapsed().as_millis() as u64,
+            stdout_truncated: stdout.dropped(),
+            stderr_truncated: stderr.dropped(),
+            timed_out,
+        };
+        serde_json::to_value(output).map_err(|e| format!("failed to serialize output: {}", e))
+    }
+
+    /// Writes the command result as an observation to shared context, or a
+    /// failure if the backend reports it failed or blocked the command.
+    async fn post(&mut self, shared: &mut Context, _prep_res: Value, exec_res: Value) -> Action {
+        let command = self.command.take().unwrap_or_default();
+        let outcome = match serde_json::from_value::<CommandOutput>(exec_res) {
+            Ok(output) => match self.backend.failure(&output) {
+                Some(error) => Outcome::Failure {
+                    error: format!("{}\n{}", error, output.render()),
+                },
+                None => Outcome::Command(output),
+            },
+            Err(e) => Outcome::Failure {
+                error: format!("invalid command output: {}", e),
+            },
+        };
+        shared.add_observation(command, outcome);
+        Action::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::Message;
+
+    /// Returns the command output recorded by the last observation.
+    fn last_output(ctx: &Context) -> CommandOutput {
+        match ctx.messages().last() {
+            Some(Message::Observation {
+                outcome: Outcome::Command(output),
+                ..
+            }) => output.clone(),
+            _ => panic!("expected command Observation message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_no_command_set() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Completed { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_run_echo() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        harness.set_command("echo hello".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert_eq!(output.stdout.trim(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_run_failing_command() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        harness.set_command("echo 'test failed'; echo warning >&2; exit 3".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert_eq!(output.exit_code, Some(3));
+        assert!(!output.success());
+        assert_eq!(output.stdout.trim(), "test failed");
+        assert_eq!(output.stderr.trim(), "warning");
+    }
+
+    #[tokio::test]
+    async fn test_run_injected_crash() {
+        let mut harness = Harness::default();
+        let faults = Faults::default();
+        faults.inject(Fault::CommandCrash, 1);
+        harness.set_faults(faults);
+        let mut ctx = Context::new();
+
+        harness.set_command("touch never-created".to_string());
+        match harness.run(&mut ctx).await {
+            Action::Completed { answer } => assert!(answer.contains("simulated command crash")),
+            other => panic!("expected crash, got {:?}", other),
+        }
+        assert!(!Path::new("never-created").exists());
+
+        harness.set_command("echo hello".to_string());
+        assert!(matches!(harness.run(&mut ctx).await, Action::Continue));
+    }
+
+    #[tokio::test]
+    async fn test_command_observation_to_json() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        harness.set_command("echo out; echo err >&2; exit 1".to_string());
+        harness.run(&mut ctx).await;
+
+        let json = ctx.messages().last().unwrap().to_json();
+        let inner: Value = serde_json::from_str(json["content"].as_str().unwrap()).unwrap();
+        assert_eq!(inner["outcome"], "failure");
+        assert_eq!(inner["exit_code"], 1);
+        assert_eq!(inner["stdout"], "out\n");
+        assert_eq!(inner["stderr"], "err\n");
+        assert!(inner["duration_ms"].is_u64());
+        assert!(inner.get("truncated_bytes").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_chained_commands() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        harness.set_command("echo foo && echo bar".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert!(output.stdout.contains("foo"));
+        assert!(output.stdout.contains("bar"));
+    }
+
+    #[tokio::test]
+    async fn test_run_cancelled() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        let cancel = CancellationToken::new();
+        harness.set_cancel(cancel.clone());
+        harness.set_command("sleep 30 & sleep 30".to_string());
+
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+
+        let start = std::time::Instant::now();
+        let action = harness.run(&mut ctx).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(matches!(action, Action::Completed { answer } if answer == INTERRUPTED));
+    }
+
+    #[tokio::test]
+    async fn test_run_timeout() {
+        let mut harness = Harness::new(HarnessConfig {
+            timeout_secs: 1,
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+        harness.set_command("echo started; sleep 30".to_string());
+
+        let start = std::time::Instant::now();
+        let action = harness.run(&mut ctx).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert!(output.timed_out);
+        assert_eq!(output.exit_code, None);
+        assert_eq!(output.stdout.trim(), "started");
+    }
+
+    #[tokio::test]
+    async fn test_run_background_process() {
+        let mut harness = Harness::new(HarnessConfig {
+            timeout_secs: 5,
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+        harness.set_command("sleep 30 & echo started".to_string());
+
+        let start = std::time::Instant::now();
+        harness.run(&mut ctx).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+        let output = last_output(&ctx);
+        assert!(!output.timed_out);
+        assert_eq!(output.exit_code, Some(0));
+        assert_eq!(output.stdout.trim(), "started");
+    }
+
+    #[tokio::test]
+    async fn test_run_truncates_output() {
+        let mut harness = Harness::new(HarnessConfig {
+            max_output_bytes: 10,
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+        harness.set_command("head -c 100000 /dev/zero | tr '\\0' x".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert_eq!(output.stdout, "xxxxxxxxxx");
+        assert_eq!(output.stdout_truncated, 99990);
+        assert_eq!(output.stderr_truncated, 0);
+    }
+
+    #[tokio::test]
+    async fn test_run_cpu_limit() {
+        let mut harness = Harness::new(HarnessConfig {
+            cpu_secs: Some(1),
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+        harness.set_command("ulimit -t".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert_eq!(output.stdout.trim(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_keeps_cwd_and_env() {
+        let root = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        let mut harness = Harness::new(HarnessConfig {
+            workdir: Some(root.display().to_string()),
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        harness.set_command("cd sub && export GREETING=hi; exit 4".to_string());
+        harness.run(&mut ctx).await;
+        let output = last_output(&ctx);
+        assert_eq!(output.exit_code, Some(4));
+        assert!(output.cwd.ends_with("sub"));
+
+        harness.set_command("pwd; echo $GREETING".to_string());
+        harness.run(&mut ctx).await;
+        let output = last_output(&ctx);
+        let lines: Vec<&str> = output.stdout.lines().collect();
+        assert!(lines[0].ends_with("sub"));
+        assert_eq!(lines[1], "hi");
+
+        std::fs::remove_dir(root.join("sub")).unwrap();
+        harness.set_command("pwd".to_string());
+        harness.run(&mut ctx).await;
+        assert_eq!(harness.cwd(), root.as_path());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_unset_and_newlines() {
+        let mut harness = Harness::new(HarnessConfig {
+            workdir: Some("/".to_string()),
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        // HOME comes from the target's environment, so it must be unset again
+        harness.set_command("unset HOME; export MULTI='a%0A\nb'".to_string());
+        harness.run(&mut ctx).await;
+        for _ in 0..2 {
+            harness.set_command("echo \"[${HOME-unset}]\"; printf '%s' \"$MULTI\"".to_string());
+            harness.run(&mut ctx).await;
+            assert_eq!(last_output(&ctx).stdout, "[unset]\na%0A\nb");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stateless_shell() {
+        let mut harness = Harness::new(HarnessConfig {
+            workdir: Some("/".to_string()),
+            persistent_shell: false,
+            ..HarnessConfig::default()
+        });
+        let mut ctx = Context::new();
+
+        harness.set_command("cd /tmp && export GREETING=hi".to_string());
+        harness.run(&mut ctx).await;
+        harness.set_command("pwd; echo \"[$GREETING]\"".to_string());
+        harness.run(&mut ctx).await;
+        assert_eq!(last_output(&ctx).stdout, "/\n[]\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_pipe() {
+        let mut harness = Harness::default();
+        let mut ctx = Context::new();
+        harness.set_command("echo hello world | wc -w".to_string());
+        let action = harness.run(&mut ctx).await;
+        assert!(matches!(action, Action::Continue));
+        let output = last_output(&ctx);
+        assert_eq!(output.stdout.trim(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_run_streams_output() {
+        let mut harness = Harness::new(HarnessConfig {
+            max_output_bytes: 4,
+            ..HarnessConfig::default()
+        });
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        harness.set_output(tx);
+        let mut ctx = Context::new();
+        harness.set_command("echo one; echo two >&2; echo three; printf four".to_string());
+        harness.run(&mut ctx).await;
+
+        let mut stdout = String::new();
+        while let Ok(chunk) = rx.try_recv() {
+            match chunk.stream {
+                Stream::Stdout => stdout.push_str(&chunk.text),
+                Stream::Stderr => assert_eq!(chunk.text, "two\n"),
+            }
+        }
+        assert_eq!(stdout, "one\nthree\nfour");
+
+        // The observation still holds only the capped output
+        let output = last_output(&ctx);
+        assert_eq!(output.stdout, "one\n");
+        assert_eq!(output.stdout_truncated, 10);
+    }
+
+    #[test]
+    fn test_build_backend() {
+        let config = HarnessConfig::default();
+        let harness = Harness::build(config.clone(), "local").unwrap();
+        assert_eq!(harness.backend(), "local");
+        assert_eq!(harness.kind(), "local");
+
+        let err = build_backend(&config, "sandbox").err().unwrap();
+        assert_eq!(err, "unknown harness backend 'sandbox'");
+    }
+
+    #[test]
+    fn test_shell_state_parse() {
+        let data =
+            b"/work%0Adir\nHOME=/root\n_=/bin/env\nSHLVL=2\nBASH_FUNC_f%%=() {}\nA=x=y%0A%250A\n";
+        let state = ShellState::parse(data).unwrap();
+        assert_eq!(state.cwd, PathBuf::from("/work\ndir"));
+        assert_eq!(state.env.len(), 2);
+        assert_eq!(state.env["A"], "x=y\n%0A");
+        assert!(ShellState::parse(b"").is_none());
+
+        let state = ShellState::parse(b"/\nB=2\n-A\n-_\n").unwrap();
+        assert_eq!(state.unset, BTreeSet::from(["A".to_string()]));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+}
@@ -0,0 +1,385 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{Backend, quote};
+use crate::core::context::CommandOutput;
+
+/// Exit code of the sandbox setup script when a mount fails.
+const SETUP_FAILED: i32 = 125;
+
+/// Marker printed by the setup script before exiting with `SETUP_FAILED`.
+const SETUP_MARKER: &str = "minusagent: sandbox setup failed";
+
+/// Error messages that show a command ran into the sandbox, with what it tried.
+const VIOLATIONS: [(&str, &str); 4] = [
+    ("Read-only file system", "write outside the workspace"),
+    ("Network is unreachable", "network access"),
+    ("Temporary failure in name resolution", "network access"),
+    ("Could not resolve host", "network access"),
+];
+
+/// Backend that runs commands in unprivileged Linux namespaces.
+///
+/// Every command gets new user, mount, and PID namespaces, plus a network
+/// namespace with only a loopback device unless `network` is set. The
+/// whole filesystem is mounted read-only except the workspace, the
+/// `writable` paths, and a private `/tmp` that persists across commands.
+/// Uses bubblewrap if available, otherwise `unshare` with a bind-mounted
+/// root and `chroot`.
+///
+/// # Fields
+/// - `tool`: `"bwrap"`, `"unshare"`, or `"auto"` to pick on first use.
+/// - `workspace`: Host directory commands may write to; also the root.
+/// - `writable`: Additional host paths commands may write to.
+/// - `network`: Keep the host network instead of isolating it.
+/// - `dir`: Host scratch directory holding the private `/tmp` and the
+///   mount point of the new root.
+/// - `prepared`: Whether `dir` exists and `tool` is resolved.
+pub struct Sandbox {
+    tool: String,
+    workspace: PathBuf,
+    writable: Vec<PathBuf>,
+    network: bool,
+    dir: PathBuf,
+    prepared: bool,
+}
+
+impl Sandbox {
+    /// Creates a sandbox backend. Nothing is created until the first command.
+    ///
+    /// # Arguments
+    /// - `tool`: `"bwrap"`, `"unshare"`, or `"auto"`.
+    /// - `workspace`: Host directory commands may write to.
+    /// - `writable`: Additional host paths commands may write to.
+    /// - `network`: Keep the host network.
+    pub fn new(tool: &str, workspace: &Path, writable: &[String], network: bool) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            tool: tool.to_string(),
+            workspace: workspace.to_path_buf(),
+            writable: writable.iter().map(PathBuf::from).collect(),
+            network,
+            dir: std::env::temp_dir().join(format!("minusagent-sandbox-{}", &id[..8])),
+            prepared: false,
+        }
+    }
+
+    /// Returns the host directory mounted at `/tmp` in the sandbox.
+    fn tmp_dir(&self) -> PathBuf {
+        self.dir.join("tmp")
+    }
+
+    /// Maps a path under the sandbox's `/tmp` to the host.
+    fn host_path(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix("/tmp") {
+            Ok(rest) => self.tmp_dir().join(rest),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Returns the paths writable in the sandbox besides `/tmp`.
+    fn binds(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.workspace).chain(&self.writable)
+    }
+
+    /// Returns the `bwrap` arguments that run `script`.
+    fn bwrap_command(&self, script: &str) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--unshare-user",
+            "--unshare-pid",
+            "--die-with-parent",
+        ]
+        .map(String::from)
+        .into();
+        if !self.network {
+            args.push("--unshare-net".to_string());
+        }
+        args.extend(["--bind".to_string(), self.tmp_dir().display().to_string()]);
+        args.push("/tmp".to_string());
+        for path in self.binds() {
+            let path = path.display().to_string();
+            args.extend(["--bind".to_string(), path.clone(), path]);
+        }
+        args.extend(["sh", "-c", script].map(String::from));
+        args
+    }
+
+    /// Returns the `unshare` arguments that run `script`.
+    ///
+    /// The setup script bind-mounts `/` recursively onto a scratch root,
+    /// mounts the private `/tmp`, the writable paths, and a fresh `/proc`
+    /// there, remounts everything else read-only, and `chroot`s into it.
+    fn unshare_command(&self, script: &str) -> Vec<String> {
+        let root = self.dir.join("root").display().to_string();
+        let fail = format!("{{ echo '{}' >&2; exit {}; }}", SETUP_MARKER, SETUP_FAILED);
+        let mut skip = vec![format!("{}/tmp", root), format!("{}/proc", root)];
+        let mut setup = vec![
+            format!("R={}", quote(&root)),
+            format!("mount --rbind / \"$R\" || {}", fail),
+            format!(
+                "mount --bind {} \"$R/tmp\" || {}",
+                quote(&self.tmp_dir().display().to_string()),
+                fail
+            ),
+        ];
+        for path in self.binds() {
+            let path = path.display().to_string();
+            setup.push(format!(
+                "mkdir -p \"$R\"{p} && mount --bind {p} \"$R\"{p} || {}",
+                fail,
+                p = quote(&path)
+            ));
+            skip.push(format!("{}{}", root, path));
+        }
+        setup.push(format!("mount -t proc proc \"$R/proc\" || {}", fail));
+        setup.push(format!(
+            "findmnt -rn -o TARGET -R \"$R\" | while read -r m; do case \"$m\" in {}) ;; \
+             *) mount -o remount,bind,ro \"$m\" || exit 1 ;; esac; done || {}",
+            skip.iter().map(|p| quote(p)).collect::<Vec<_>>().join("|"),
+            fail
+        ));
+        setup.push("exec chroot \"$R\" sh -c \"$0\"".to_string());
+
+        let mut args: Vec<String> = ["--user", "--map-root-user", "--mount", "--pid", "--fork"]
+            .map(String::from)
+            .into();
+        if !self.network {
+            args.push("--net".to_string());
+        }
+        args.extend(["sh".to_string(), "-c".to_string(), setup.join("\n")]);
+        args.push(script.to_string());
+        args
+    }
+}
+
+/// Returns `true` if `program` is found on `PATH`.
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+#[async_trait]
+impl Backend for Sandbox {
+    fn kind(&self) -> &'static str {
+        "sandbox"
+    }
+
+    fn root(&self) -> PathBuf {
+        self.workspace.clone()
+    }
+
+    /// Creates the scratch directories, resolves `"auto"`, and checks that
+    /// unprivileged user namespaces work.
+    async fn prepare(&mut self) -> Result<(), String> {
+        if self.prepared {
+            return Ok(());
+        }
+        if !self.workspace.is_dir() {
+            return Err(format!(
+                "working directory {} does not exist",
+                self.workspace.display()
+            ));
+        }
+        std::fs::create_dir_all(self.tmp_dir())
+            .and_then(|_| std::fs::create_dir_all(self.dir.join("root")))
+            .map_err(|e| format!("failed to create {}: {}", self.dir.display(), e))?;
+        if self.tool == "auto" {
+            let tool = if on_path("bwrap") { "bwrap" } else { "unshare" };
+            self.tool = tool.to_string();
+        }
+
+        let output = self
+            .command("true")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| format!("failed to run {}: {}", self.tool, e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "sandbox is unavailable: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        self.prepared = true;
+        Ok(())
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let (program, args) = match self.tool.as_str() {
+            "bwrap" => ("bwrap", self.bwrap_command(script)),
+            _ => ("unshare", self.unshare_command(script)),
+        };
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        cmd
+    }
+
+    async fn take_file(&self, path: &Path) -> Option<Vec<u8>> {
+        let path = self.host_path(path);
+        let data = std::fs::read(&path).ok()?;
+        let _ = std::fs::remove_file(&path);
+        Some(data)
+    }
+
+    /// Every sandboxed process stays in the local process group, or dies
+    /// with the namespace's init, so only the pid file is left to clean up.
+    async fn kill(&self, pid_file: &Path) {
+        let _ = std::fs::remove_file(self.host_path(pid_file));
+    }
+
+    fn failure(&self, output: &CommandOutput) -> Option<String> {
+        if output.exit_code == Some(SETUP_FAILED) && output.stderr.contains(SETUP_MARKER) {
+            return Some(format!("sandbox setup failed: {}", output.stderr.trim()));
+        }
+        VIOLATIONS
+            .iter()
+            .find(|(message, _)| output.stderr.contains(message))
+            .map(|(_, attempt)| format!("sandbox violation: {} is not allowed", attempt))
+    }
+}
+
+impl Drop for Sandbox {
+    /// Removes the scratch directory, including the private `/tmp`.
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox() -> Sandbox {
+        Sandbox::new(
+            "bwrap",
+            Path::new("/home/me/project"),
+            &["/home/me/.cargo".to_string()],
+            false,
+        )
+    }
+
+    #[test]
+    fn test_bwrap_command() {
+        let sandbox = sandbox();
+        let args = sandbox.bwrap_command("ls");
+        let tmp = sandbox.tmp_dir().display().to_string();
+        assert_eq!(args[..3], ["--ro-bind", "/", "/"]);
+        assert!(args.contains(&"--unshare-net".to_string()));
+        assert!(args.windows(3).any(|w| w == ["--bind", &tmp, "/tmp"]));
+        assert!(
+            args.windows(3)
+                .any(|w| w == ["--bind", "/home/me/project", "/home/me/project"])
+        );
+        assert!(
+            args.windows(3)
+                .any(|w| w == ["--bind", "/home/me/.cargo", "/home/me/.cargo"])
+        );
+        assert_eq!(args[args.len() - 3..], ["sh", "-c", "ls"]);
+    }
+
+    #[test]
+    fn test_unshare_command() {
+        let mut sandbox = sandbox();
+        sandbox.network = true;
+        let args = sandbox.unshare_command("ls");
+        assert!(!args.contains(&"--net".to_string()));
+        assert_eq!(args[..2], ["--user", "--map-root-user"]);
+        assert_eq!(args.last().unwrap(), "ls");
+        let setup = &args[args.len() - 2];
+        assert!(setup.contains("mount --bind '/home/me/project' \"$R\"'/home/me/project'"));
+        assert!(setup.ends_with("exec chroot \"$R\" sh -c \"$0\""));
+    }
+
+    #[test]
+    fn test_host_path() {
+        let sandbox = sandbox();
+        assert_eq!(
+            sandbox.host_path(Path::new("/tmp/minusagent-1.state")),
+            sandbox.tmp_dir().join("minusagent-1.state")
+        );
+        assert_eq!(sandbox.host_path(Path::new("/var/x")), Path::new("/var/x"));
+    }
+
+    #[test]
+    fn test_failure() {
+        let sandbox = sandbox();
+        let mut output = CommandOutput {
+            exit_code: Some(1),
+            stderr: "touch: cannot touch '/etc/x': Read-only file system\n".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            sandbox.failure(&output).unwrap(),
+            "sandbox violation: write outside the workspace is not allowed"
+        );
+        output.stderr = "no such file\n".to_string();
+        assert!(sandbox.failure(&output).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_in_sandbox() {
+        use crate::config::{BackendConfig, HarnessConfig};
+        use crate::core::context::{Context, Message, Outcome};
+        use crate::core::harness::Harness;
+        use crate::core::{Action, Node};
+
+        let workspace =
+            std::env::temp_dir().join(format!("minusagent-ws-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(workspace.join("sub")).unwrap();
+        let mut config = HarnessConfig {
+            workdir: Some(workspace.display().to_string()),
+            ..HarnessConfig::default()
+        };
+        config.backends.insert(
+            "jail".to_string(),
+            BackendConfig::Sandbox {
+                tool: "unshare".to_string(),
+                workspace: None,
+                writable: Vec::new(),
+                network: false,
+            },
+        );
+        let mut harness = Harness::build(config, "jail").unwrap();
+        let mut ctx = Context::new();
+
+        harness.set_command("cd sub && echo hi > a && export X=1".to_string());
+        if let Action::Completed { answer } = harness.run(&mut ctx).await {
+            // Unprivileged user namespaces are disabled on this machine
+            assert!(answer.contains("sandbox is unavailable"), "{}", answer);
+            return;
+        }
+
+        harness.set_command("cat a; echo $X; echo tmp > /tmp/t && cat /tmp/t".to_string());
+        harness.run(&mut ctx).await;
+        match ctx.messages().last() {
+            Some(Message::Observation {
+                outcome: Outcome::Command(output),
+                ..
+            }) => assert_eq!(output.stdout, "hi\n1\ntmp\n"),
+            other => panic!("expected command output, got {:?}", other),
+        }
+        assert_eq!(harness.cwd(), workspace.join("sub"));
+
+        harness.set_command("touch /etc/minusagent-sandbox-test".to_string());
+        harness.run(&mut ctx).await;
+        match ctx.messages().last() {
+            Some(Message::Observation {
+                outcome: Outcome::Failure { error },
+                ..
+            }) => assert!(error.starts_with("sandbox violation: write outside the workspace")),
+            other => panic!("expected sandbox violation, got {:?}", other),
+        }
+        assert!(!Path::new("/etc/minusagent-sandbox-test").exists());
+        std::fs::remove_dir_all(&workspace).unwrap();
+    }
+}
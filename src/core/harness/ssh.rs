@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use super::{Backend, quote};
+
+/// Backend that runs commands on a remote host with the system `ssh`.
+///
+/// All commands share one master connection (`ControlMaster`), opened on
+/// first use and closed when the backend is dropped (or after ten idle
+/// minutes if the process dies), so each command only costs a channel on
+/// the existing connection. Killing the local `ssh` does not stop the
+/// remote command; timeouts and interrupts rely on the default
+/// `Backend::kill`, which kills the remote process group.
+///
+/// # Fields
+/// - `destination`: `[user@]host` passed to `ssh`.
+/// - `port`: Optional SSH port.
+/// - `workdir`: Remote directory commands start in (`.` is the login directory).
+/// - `env`: Variables exported on the remote host before every command.
+/// - `ssh_args`: Extra arguments for `ssh`, e.g. `["-i", "~/.ssh/staging"]`.
+/// - `control_path`: Local socket of the master connection.
+/// - `connected`: Whether the master connection was opened.
+pub struct Ssh {
+    destination: String,
+    port: Option<u16>,
+    workdir: String,
+    env: BTreeMap<String, String>,
+    ssh_args: Vec<String>,
+    control_path: PathBuf,
+    connected: bool,
+}
+
+impl Ssh {
+    /// Creates an SSH backend. The connection is opened on the first command.
+    ///
+    /// # Arguments
+    /// - `host`: Remote host name or alias from `~/.ssh/config`.
+    /// - `user`: Optional remote user.
+    /// - `port`: Optional SSH port.
+    /// - `workdir`: Remote directory commands start in, or `None` for the login directory.
+    /// - `env`: Variables exported before every command.
+    /// - `ssh_args`: Extra arguments for `ssh`.
+    pub fn new(
+        host: &str,
+        user: Option<&str>,
+        port: Option<u16>,
+        workdir: Option<&str>,
+        env: &BTreeMap<String, String>,
+        ssh_args: &[String],
+    ) -> Self {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            destination: match user {
+                Some(user) => format!("{}@{}", user, host),
+                None => host.to_string(),
+            },
+            port,
+            workdir: workdir.unwrap_or(".").to_string(),
+            env: env.clone(),
+            ssh_args: ssh_args.to_vec(),
+            // Kept short: socket paths are limited to about 100 bytes
+            control_path: std::env::temp_dir().join(format!("minusagent-ssh-{}", &id[..8])),
+            connected: false,
+        }
+    }
+
+    /// Returns the `ssh` options shared by every invocation.
+    fn options(&self) -> Vec<String> {
+        let mut args = vec![
+            "-o".to_string(),
+            format!("ControlPath={}", self.control_path.display()),
+            "-o".to_string(),
+            "ControlMaster=auto".to_string(),
+            "-o".to_string(),
+            "ControlPersist=10m".to_string(),
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+        ];
+        if let Some(port) = self.port {
+            args.extend(["-p".to_string(), port.to_string()]);
+        }
+        args.extend(self.ssh_args.iter().cloned());
+        args
+    }
+
+    /// Returns the `ssh` arguments that run `script` on the remote host.
+    ///
+    /// The remote login shell receives a single `sh -c '...'` command, with
+    /// the configured variables exported ahead of the script.
+    fn ssh_command(&self, script: &str) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .env
+            .iter()
+            .map(|(key, value)| format!("export {}={}", key, quote(value)))
+            .collect();
+        lines.push(script.to_string());
+
+        let mut args = self.options();
+        args.extend([
+            "-T".to_string(),
+            self.destination.clone(),
+            "--".to_string(),
+            format!("sh -c {}", quote(&lines.join("\n"))),
+        ]);
+        args
+    }
+}
+
+#[async_trait]
+impl Backend for Ssh {
+    fn kind(&self) -> &'static str {
+        "ssh"
+    }
+
+    fn root(&self) -> PathBuf {
+        PathBuf::from(&self.workdir)
+    }
+
+    /// Opens the master connection, failing fast if the host is unreachable
+    /// or would prompt for a password.
+    async fn prepare(&mut self) -> Result<(), String> {
+        if self.connected {
+            return Ok(());
+        }
+        let output = self
+            .command("true")
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| format!("failed to run ssh: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "failed to connect to {}: {}",
+                self.destination,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        self.connected = true;
+        Ok(())
+    }
+
+    fn command(&self, script: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(self.ssh_command(script));
+        cmd
+    }
+}
+
+impl Drop for Ssh {
+    /// Closes the master connection.
+    fn drop(&mut self) {
+        if self.connected {
+            let _ = std::process::Command::new("ssh")
+                .args(self.options())
+                .args(["-O", "exit", &self.destination])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssh() -> Ssh {
+        let env = BTreeMap::from([("RUST_LOG".to_string(), "debug it's".to_string())]);
+        Ssh::new(
+            "staging",
+            Some("deploy"),
+            Some(2222),
+            Some("/srv/app"),
+            &env,
+            &["-i".to_string(), "/keys/staging".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_ssh_command() {
+        let ssh = ssh();
+        let args = ssh.ssh_command("ls -la");
+        let control = format!("ControlPath={}", ssh.control_path.display());
+        assert_eq!(args[..2], ["-o", &control]);
+        assert!(args.contains(&"ControlMaster=auto".to_string()));
+        assert!(args.windows(2).any(|w| w == ["-p", "2222"]));
+        assert!(args.windows(2).any(|w| w == ["-i", "/keys/staging"]));
+        assert_eq!(
+            args[args.len() - 3..args.len() - 1],
+            ["deploy@staging", "--"]
+        );
+        assert_eq!(ssh.root(), PathBuf::from("/srv/app"));
+    }
+
+    #[test]
+    fn test_remote_command_quoting() {
+        // The remote login shell parses the last argument as a command line
+        let remote = ssh().ssh_command("echo \"[$RUST_LOG]\"").pop().unwrap();
+        let output = std::process::Command::new("sh")
+            .args(["-c", &remote])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "[debug it's]\n");
+    }
+
+    #[test]
+    fn test_default_workdir() {
+        let ssh = Ssh::new("localhost", None, None, None, &BTreeMap::new(), &[]);
+        assert_eq!(ssh.root(), PathBuf::from("."));
+        assert_eq!(
+            ssh.ssh_command("pwd")[ssh.options().len()..],
+            ["-T", "localhost", "--", "sh -c 'pwd'"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prepare_fails_for_unreachable_host() {
+        let mut ssh = Ssh::new(
+            "minusagent-no-such-host.invalid",
+            None,
+            None,
+            None,
+            &BTreeMap::new(),
+            &["-o".to_string(), "ConnectTimeout=2".to_string()],
+        );
+        let err = ssh.prepare().await.unwrap_err();
+        assert!(err.starts_with("failed to"), "{}", err);
+        assert!(!ssh.connected);
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{CassetteConfig, CassetteMode};
+
+/// One recorded exchange, a line of the cassette file.
+///
+/// # Fields
+/// - `key`: Hash of the normalized request (see `key`).
+/// - `request`: The request body as sent.
+/// - `response`: The response body: JSON, or the raw text of a stream.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    request: Value,
+    response: Value,
+}
+
+/// A file of recorded LLM exchanges, written in `record` mode and served
+/// in `replay` mode.
+///
+/// Requests are matched by `key`, so a replayed session needs the same
+/// prompts as the recorded one. Identical requests get their recorded
+/// responses in order; the last one repeats.
+///
+/// # Fields
+/// - `path`: The cassette file.
+/// - `mode`: `Record` or `Replay`.
+/// - `responses`: In replay mode, the responses still to serve per key.
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    responses: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl Cassette {
+    /// Opens the cassette of the config, reading it in replay mode.
+    ///
+    /// # Returns
+    /// The cassette, `None` if `mode` is `off`, or an error if the path is
+    /// missing or the file cannot be read.
+    pub fn open(config: &CassetteConfig) -> Result<Option<Self>, String> {
+        if config.mode == CassetteMode::Off {
+            return Ok(None);
+        }
+        let path = PathBuf::from(config.path.as_deref().ok_or("cassette.path is not set")?);
+
+        let mut responses: HashMap<String, VecDeque<String>> = HashMap::new();
+        if config.mode == CassetteMode::Replay {
+            let data = std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read cassette {}: {}", path.display(), e))?;
+            for (i, line) in data
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+            {
+                let entry: Entry = serde_json::from_str(line).map_err(|e| {
+                    format!("invalid cassette {} line {}: {}", path.display(), i + 1, e)
+                })?;
+                let response = match entry.response {
+                    Value::String(text) => text,
+                    json => json.to_string(),
+                };
+                responses.entry(entry.key).or_default().push_back(response);
+            }
+        }
+
+        Ok(Some(Self {
+            path,
+            mode: config.mode,
+            responses: Mutex::new(responses),
+        }))
+    }
+
+    /// Returns `true` if requests are answered from the cassette.
+    pub fn replays(&self) -> bool {
+        self.mode == CassetteMode::Replay
+    }
+
+    /// Serves the recorded response for a request.
+    ///
+    /// # Arguments
+    /// - `body`: The request body.
+    ///
+    /// # Returns
+    /// The response body, or an error if nothing was recorded for the request.
+    pub fn replay(&self, body: &Value) -> Result<String, String> {
+        let key = key(body);
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let queue = responses
+            .get_mut(&key)
+            .filter(|queue| !queue.is_empty())
+            .ok_or(format!(
+                "no response recorded in {} for request {}",
+                self.path.display(),
+                key
+            ))?;
+        Ok(match queue.len() {
+            1 => queue[0].clone(),
+            _ => queue.pop_front().unwrap_or_default(),
+        })
+    }
+
+    /// Appends an exchange to the cassette.
+    ///
+    /// # Arguments
+    /// - `body`: The request body.
+    /// - `response`: The response body as received.
+    pub fn record(&self, body: &Value, response: &str) -> Result<(), String> {
+        let entry = Entry {
+            key: key(body),
+            request: body.clone(),
+            response: serde_json::from_str(response)
+                .unwrap_or_else(|_| Value::String(response.to_string())),
+        };
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| format!("failed to serialize cassette entry: {}", e))?;
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("failed to open cassette {}: {}", self.path.display(), e))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| format!("failed to write cassette {}: {}", self.path.display(), e))
+    }
+}
+
+/// Returns the key of a request: a hash of its body with object keys
+/// sorted and command durations, which differ on every run, zeroed.
+///
+/// # Arguments
+/// - `body`: The request body.
+pub fn key(body: &Value) -> String {
+    let durations = Regex::new(r#"(\\?"duration_ms\\?"):\d+"#).unwrap();
+    let canonical = canonical(body);
+    let normalized = durations.replace_all(&canonical, "${1}:0");
+    // FNV-1a, so keys stay the same across builds
+    let hash = normalized
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Serializes JSON with the keys of every object sorted.
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<_> = map.iter().collect();
+            fields.sort_by_key(|(name, _)| *name);
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(name, value)| {
+                    format!("{}:{}", Value::String(name.clone()), canonical(value))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical).collect();
+            format!("[{}]", items.join(","))
+        }
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_normalizes_requests() {
+        let observation = |ms: u64| {
+            serde_json::json!({ "role": "observation", "exit_code": 0, "duration_ms": ms })
+                .to_string()
+        };
+        let body = |ms: u64| {
+            serde_json::json!({
+                "model": "m",
+                "messages": [{ "role": "user", "content": observation(ms) }],
+            })
+        };
+        let reordered = serde_json::json!({
+            "messages": [{ "content": observation(5), "role": "user" }],
+            "model": "m",
+        });
+
+        assert_eq!(key(&body(12)), key(&body(3400)));
+        assert_eq!(key(&body(12)), key(&reordered));
+        let mut other = body(12);
+        other["model"] = serde_json::json!("n");
+        assert_ne!(key(&body(12)), key(&other));
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("cassette.jsonl");
+        let config = |mode| CassetteConfig {
+            mode,
+            path: Some(path.display().to_string()),
+        };
+        let first = serde_json::json!({ "messages": ["hi"] });
+        let second = serde_json::json!({ "messages": ["bye"] });
+
+        let recorder = Cassette::open(&config(CassetteMode::Record))
+            .unwrap()
+            .unwrap();
+        recorder.record(&first, r#"{"n":1}"#).unwrap();
+        recorder.record(&first, r#"{"n":2}"#).unwrap();
+        recorder.record(&second, "data: [DONE]\n\n").unwrap();
+
+        let player = Cassette::open(&config(CassetteMode::Replay))
+            .unwrap()
+            .unwrap();
+        assert!(player.replays());
+        assert_eq!(player.replay(&first).unwrap(), r#"{"n":1}"#);
+        assert_eq!(player.replay(&first).unwrap(), r#"{"n":2}"#);
+        assert_eq!(player.replay(&first).unwrap(), r#"{"n":2}"#);
+        assert_eq!(player.replay(&second).unwrap(), "data: [DONE]\n\n");
+        let err = player.replay(&serde_json::json!({})).unwrap_err();
+        assert!(err.starts_with("no response recorded in"), "{}", err);
+
+        assert!(
+            Cassette::open(&CassetteConfig::default())
+                .unwrap()
+                .is_none()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
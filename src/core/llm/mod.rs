@@ -0,0 +1,1527 @@
+pub mod cassette;
+pub mod provider;
+pub mod repair;
+pub mod retry;
+pub mod stream;
+pub mod tools;
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::{LLMConfig, Protocol, RetryConfig};
+use crate::core::{Action, Node};
+use crate::core::context::{Context, Message, Outcome};
+use crate::core::fault::{Fault, Faults};
+use crate::core::prompt::PromptEngine;
+use cassette::Cassette;
+use provider::{Delta, Framing, Provider, Request};
+use retry::{Failure, Profiles};
+use stream::{LineParser, SseParser, ThoughtStream};
+
+/// Categories of reasoning in the agent's chain-of-thought.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThoughtType {
+    Planning,
+    Analysis,
+    DecisionMaking,
+    ProblemSolving,
+    MemoryIntegration,
+    SelfReflection,
+    GoalSetting,
+    Prioritization,
+}
+
+/// The thought component of an LLM response, capturing chain-of-thought reasoning.
+///
+/// # Fields
+/// - `thought_type`: Category of reasoning.
+/// - `content`: The actual reasoning text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thought {
+    pub thought_type: ThoughtType,
+    pub content: String,
+}
+
+/// Structured response from the LLM.
+///
+/// # Fields
+/// - `thought`: Chain-of-thought reasoning.
+/// - `action`: Control flow decision (use skill, continue thinking, or complete).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMResponse {
+    pub thought: Thought,
+    pub action: Action,
+}
+
+/// Token usage reported by the LLM API for a single call.
+///
+/// # Fields
+/// - `prompt_tokens`: Tokens consumed by the request messages.
+/// - `completion_tokens`: Tokens generated in the response.
+/// - `total_tokens`: Sum reported by the API (prompt + completion).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
+
+impl Usage {
+    /// Merges counts reported in pieces while a response streams.
+    ///
+    /// Streamed counts are cumulative, so the larger value of each field wins.
+    ///
+    /// # Arguments
+    /// - `other`: The counts from the latest stream event.
+    pub fn merge(&mut self, other: &Usage) {
+        self.prompt_tokens = self.prompt_tokens.max(other.prompt_tokens);
+        self.completion_tokens = self.completion_tokens.max(other.completion_tokens);
+        self.total_tokens = self
+            .total_tokens
+            .max(other.total_tokens)
+            .max(self.prompt_tokens + self.completion_tokens);
+    }
+
+    /// Adds the counts of another call, for requests that take several calls.
+    ///
+    /// # Arguments
+    /// - `other`: The counts of the other call.
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+
+    /// Extracts usage from an OpenAI-compatible response body.
+    ///
+    /// Missing fields default to zero. If `total_tokens` is absent, it is
+    /// computed from the prompt and completion counts.
+    ///
+    /// # Arguments
+    /// - `data`: The response JSON containing a `usage` object.
+    pub fn from_response(data: &Value) -> Self {
+        let field = |name: &str| data["usage"][name].as_u64().unwrap_or(0) as usize;
+        let prompt_tokens = field("prompt_tokens");
+        let completion_tokens = field("completion_tokens");
+        let total_tokens = match field("total_tokens") {
+            0 => prompt_tokens + completion_tokens,
+            n => n,
+        };
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+        }
+    }
+}
+
+/// Number of times an unparsable reply is sent back for correction.
+const PARSE_RETRIES: u32 = 2;
+
+/// Hand-written JSON Schema for `LLMResponse`, used in structured output
+/// requests and embedded in the prompt by the `json_object` and `text` protocols.
+///
+/// The `action` field uses a discriminated union via the `action` tag:
+/// - `{"action": "use_skill", "skills": ["skill_name", ...]}`
+/// - `{"action": "continue"}`
+/// - `{"action": "completed", "answer": "..."}`
+pub fn response_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "thought": thought_schema(),
+            "action": {
+                "oneOf": [
+                    {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string", "const": "use_skill" },
+                            "skills": {
+                                "type": "array",
+                                "items": { "type": "string" }
+                            }
+                        },
+                        "required": ["action", "skills"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string", "const": "execute" },
+                            "command": { "type": "string" }
+                        },
+                        "required": ["action", "command"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string", "const": "continue" }
+                        },
+                        "required": ["action"],
+                        "additionalProperties": false
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "action": { "type": "string", "const": "completed" },
+                            "answer": { "type": "string" }
+                        },
+                        "required": ["action", "answer"],
+                        "additionalProperties": false
+                    }
+                ]
+            }
+        },
+        "required": ["thought", "action"],
+        "additionalProperties": false
+    })
+}
+
+/// JSON Schema for `Thought`, shared by the response schema and the tool
+/// definitions.
+fn thought_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "thought_type": {
+                "type": "string",
+                "enum": [
+                    "planning",
+                    "analysis",
+                    "decision_making",
+                    "problem_solving",
+                    "memory_integration",
+                    "self_reflection",
+                    "goal_setting",
+                    "prioritization"
+                ]
+            },
+            "content": { "type": "string" }
+        },
+        "required": ["thought_type", "content"],
+        "additionalProperties": false
+    })
+}
+
+/// HTTP client for LLM APIs.
+///
+/// Sends conversation messages and parses structured JSON responses, or
+/// tool calls with the `tools` protocol. Messages use the OpenAI chat
+/// format throughout; the configured `provider` translates them for its
+/// API. With `stream` enabled, `chat` reads the response as it is
+/// generated and forwards the thought text to the delta channel.
+/// Failed requests are retried according to `retry`, rotating through the
+/// configured API keys (see `post`).
+/// Implements `Node` so it can be driven through the prep→exec→post pipeline:
+///   - **prep**: validates that the context has messages.
+///   - **exec**: calls the LLM API, passes the response as `Value` in Outcome.
+///   - **post**: deserializes the response and writes it into context.
+///
+/// # Fields
+/// - `client`: Reusable HTTP client.
+/// - `config`: LLM provider configuration.
+/// - `provider`: Wire format of the configured API.
+/// - `profiles`: Resolved API keys from environment, with their cooldowns.
+/// - `retry`: Attempts, backoff and cooldowns for failed requests.
+/// - `prompt_engine`: Builds messages from context for API calls.
+/// - `deltas`: Channel that receives thought text while a response streams.
+/// - `faults`: Synthetic failures to return instead of calling the API.
+/// - `cassette`: Records responses, or replays them instead of calling the API.
+/// - `failure`: Why the last request that gave up failed (see `take_failure`).
+/// - `spent`: Usage of calls made by chats that failed (see `take_spent`).
+pub struct LLMClient {
+    client: Client,
+    config: LLMConfig,
+    provider: Box<dyn Provider>,
+    profiles: Mutex<Profiles>,
+    retry: RetryConfig,
+    prompt_engine: PromptEngine,
+    deltas: Option<UnboundedSender<String>>,
+    faults: Faults,
+    cassette: Option<Cassette>,
+    failure: Mutex<Option<Failure>>,
+    spent: Mutex<Usage>,
+}
+
+impl LLMClient {
+    /// Creates a new LLM client from the given configuration.
+    ///
+    /// # Arguments
+    /// - `config`: LLM provider configuration including model, base_url, and API key env var.
+    /// - `prompt_engine`: The prompt engine for building messages.
+    ///
+    /// # Returns
+    /// A configured `LLMClient` with the default retry settings, or an error
+    /// if a configured API key variable is not set.
+    pub fn new(config: LLMConfig, prompt_engine: PromptEngine) -> Result<Self, String> {
+        let profiles = Profiles::new(config.api_keys()?);
+        let retry = RetryConfig::default();
+        Ok(Self {
+            client: http_client(&retry),
+            provider: provider::build(&config)?,
+            profiles: Mutex::new(profiles),
+            retry,
+            prompt_engine: prompt_engine.with_protocol(config.protocol),
+            config,
+            deltas: None,
+            faults: Faults::default(),
+            cassette: None,
+            failure: Mutex::new(None),
+            spent: Mutex::new(Usage::default()),
+        })
+    }
+
+    /// Sets how failed requests are retried.
+    ///
+    /// # Arguments
+    /// - `retry`: Attempts, backoff, cooldowns and the read timeout.
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.client = http_client(&retry);
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the cassette that records or replays the responses.
+    ///
+    /// # Arguments
+    /// - `cassette`: The opened cassette, or `None` to call the API as usual.
+    pub fn with_cassette(mut self, cassette: Option<Cassette>) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    /// Returns the LLM provider configuration.
+    pub fn config(&self) -> &LLMConfig {
+        &self.config
+    }
+
+    /// Returns the context window size for this LLM.
+    pub fn context_window(&self) -> usize {
+        self.config.context_window
+    }
+
+    /// Returns a reference to the prompt engine.
+    pub fn prompt_engine(&self) -> &PromptEngine {
+        &self.prompt_engine
+    }
+
+    /// Sets the channel that receives thought text from streamed responses.
+    ///
+    /// Each thought is sent in pieces as it is generated, followed by a
+    /// `"\n"` once it is complete. Has no effect unless `stream` is enabled.
+    ///
+    /// # Arguments
+    /// - `deltas`: Sender for the thought text.
+    pub fn set_deltas(&mut self, deltas: UnboundedSender<String>) {
+        self.deltas = Some(deltas);
+    }
+
+    /// Sets the faults to inject. Request failures replace single attempts,
+    /// so they go through the retry logic; `MalformedJson` replaces a reply
+    /// of `chat`.
+    ///
+    /// # Arguments
+    /// - `faults`: The pending faults, usually shared with the session.
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = faults;
+    }
+
+    /// Takes the failure of the last request that gave up, so callers can
+    /// tell rate limits, overflows and the like apart without parsing the
+    /// error text. Requests that succeed leave it alone.
+    ///
+    /// # Returns
+    /// The failure, or `None` if no request failed since the last call.
+    pub fn take_failure(&self) -> Option<Failure> {
+        self.failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+    }
+
+    /// Takes the usage of calls made by chats that failed, e.g. a corrective
+    /// retry whose request errored, so it can still be recorded.
+    ///
+    /// # Returns
+    /// The usage summed since the last call; zero if no chat failed.
+    pub fn take_spent(&self) -> Usage {
+        std::mem::take(&mut *self.spent.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Sends a conversation to the LLM and returns the structured response with token usage.
+    ///
+    /// The response is requested according to the configured `protocol`:
+    /// as a JSON Schema `response_format`, a `json_object` or free text
+    /// repaired by `repair::parse`, or as calls to the tools from
+    /// `tools::definitions()`. A reply that cannot be parsed is sent back
+    /// with a corrective observation, up to `PARSE_RETRIES` times; these
+    /// extra messages only live in the request, not in the context. If the
+    /// chat fails, the usage of its calls is kept for `take_spent`.
+    ///
+    /// # Arguments
+    /// - `messages`: The conversation history as JSON values following OpenAI message format.
+    ///
+    /// # Returns
+    /// A tuple of the parsed `LLMResponse` and the token usage summed over
+    /// all calls, including the corrective retries.
+    pub async fn chat(&self, messages: &[Value]) -> Result<(LLMResponse, Usage), String> {
+        let mut messages = messages.to_vec();
+        let mut retries = 0;
+        let mut usage = Usage::default();
+        loop {
+            let message = match self.faults.take(|f| f == Fault::MalformedJson) {
+                Some(_) => self.malformed_reply(),
+                None => match self.request(&messages).await {
+                    Ok((message, next)) => {
+                        usage.add(&next);
+                        message
+                    }
+                    Err(e) => {
+                        self.spend(&usage);
+                        return Err(e);
+                    }
+                },
+            };
+            let error = match self.parse(&message) {
+                Ok(response) => return Ok((response, usage)),
+                Err(e) => e,
+            };
+
+            if retries == PARSE_RETRIES {
+                self.spend(&usage);
+                return Err(format!("{}\nraw: {}", error, raw_reply(&message)));
+            }
+            retries += 1;
+            messages.extend(self.retry_messages(&message, error));
+        }
+    }
+
+    /// Adds the usage of a failed chat to `spent`.
+    fn spend(&self, usage: &Usage) {
+        self.spent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .add(usage);
+    }
+
+    /// Sends one structured request and returns the reply message.
+    ///
+    /// # Arguments
+    /// - `messages`: The messages in OpenAI message format.
+    ///
+    /// # Returns
+    /// A tuple of the reply message (`content` and `tool_calls`) and the token usage.
+    async fn request(&self, messages: &[Value]) -> Result<(Value, Usage), String> {
+        let request = Request {
+            messages,
+            protocol: Some(self.config.protocol),
+            stream: self.config.stream,
+        };
+        let body = self.provider.body(&self.config, &request);
+        if self.config.stream {
+            self.send_stream(&body).await
+        } else {
+            let data = self.send_request(&body).await?;
+            self.provider.reply(data, request.protocol)
+        }
+    }
+
+    /// Parses the agent response from a reply message.
+    fn parse(&self, message: &Value) -> Result<LLMResponse, String> {
+        match self.config.protocol {
+            Protocol::Tools => tools::parse(message),
+            _ => {
+                let content = message["content"]
+                    .as_str()
+                    .ok_or("LLM response missing choices[0].message.content")?;
+                repair::parse(content)
+            }
+        }
+    }
+
+    /// Returns a reply that does not parse under the configured protocol,
+    /// injected by `Fault::MalformedJson`.
+    fn malformed_reply(&self) -> Value {
+        let text = "simulated malformed reply";
+        match self.config.protocol {
+            Protocol::Tools => serde_json::json!({
+                "content": "",
+                "tool_calls": [{
+                    "id": "call_0",
+                    "type": "function",
+                    "function": { "name": "execute", "arguments": text },
+                }],
+            }),
+            _ => serde_json::json!({ "content": text }),
+        }
+    }
+
+    /// Builds the messages that send an unparsable reply back with a
+    /// correction. Under `Protocol::Tools` the reply keeps its tool calls,
+    /// and each call is answered with the correction as its result.
+    ///
+    /// # Arguments
+    /// - `message`: The reply message that could not be parsed.
+    /// - `error`: Why the reply could not be parsed.
+    fn retry_messages(&self, message: &Value, error: String) -> Vec<Value> {
+        let correction = self.correction(error).to_json();
+        let calls = message["tool_calls"].as_array().filter(|c| !c.is_empty());
+        match calls {
+            Some(calls) if self.config.protocol == Protocol::Tools => {
+                let mut messages = vec![serde_json::json!({
+                    "role": "assistant",
+                    "content": message["content"].as_str().unwrap_or_default(),
+                    "tool_calls": calls,
+                })];
+                messages.extend(calls.iter().map(|call| {
+                    serde_json::json!({
+                        "role": "tool",
+                        "tool_call_id": call["id"],
+                        "content": correction["content"],
+                    })
+                }));
+                messages
+            }
+            _ => vec![
+                serde_json::json!({ "role": "assistant", "content": raw_reply(message) }),
+                correction,
+            ],
+        }
+    }
+
+    /// Builds the observation that asks the LLM to fix an unparsable reply.
+    ///
+    /// # Arguments
+    /// - `error`: Why the reply could not be parsed.
+    fn correction(&self, error: String) -> Message {
+        let hint = match self.config.protocol {
+            Protocol::Tools => {
+                "Call one of the tools with valid arguments, or reply with text only to keep thinking."
+            }
+            _ => "Reply with a single JSON object matching the response schema, and nothing else.",
+        };
+        let error = format!("{}\n{}", error, hint);
+        Message::Observation {
+            skill: "response_format".to_string(),
+            content: error.clone(),
+            outcome: Outcome::Failure { error },
+        }
+    }
+
+    /// Sends a conversation to the LLM and returns free-form text.
+    ///
+    /// Unlike `chat`, no response format is enforced. Used for auxiliary
+    /// calls such as history compaction.
+    ///
+    /// # Arguments
+    /// - `messages`: The messages in OpenAI message format.
+    ///
+    /// # Returns
+    /// A tuple of the response text and the token usage from the API.
+    pub async fn complete(&self, messages: &[Value]) -> Result<(String, Usage), String> {
+        let request = Request {
+            messages,
+            protocol: None,
+            stream: false,
+        };
+        let body = self.provider.body(&self.config, &request);
+        let data = self.send_request(&body).await?;
+        let (message, usage) = self.provider.reply(data, None)?;
+
+        let content = message["content"]
+            .as_str()
+            .ok_or("LLM response missing choices[0].message.content")?;
+
+        Ok((content.to_string(), usage))
+    }
+
+    /// Sends a request body to the LLM API and returns the parsed JSON response.
+    ///
+    /// # Arguments
+    /// - `body`: The JSON request body.
+    ///
+    /// # Returns
+    /// The response JSON value.
+    async fn send_request(&self, body: &Value) -> Result<Value, String> {
+        let resp = self.post(body).await?;
+        resp.json()
+            .await
+            .map_err(|e| format!("failed to parse LLM response: {}", e))
+    }
+
+    /// Sends a streaming request and assembles the response from its
+    /// events, forwarding thought text to `deltas` on the way.
+    ///
+    /// The thought is read from the message content, or from the arguments
+    /// of the first tool call. With the `tools` protocol, plain content is a
+    /// thought of its own and is forwarded as is.
+    ///
+    /// # Arguments
+    /// - `body`: The JSON request body, with streaming enabled.
+    ///
+    /// # Returns
+    /// A tuple of the assembled message (`content` and `tool_calls`) and the token usage.
+    async fn send_stream(&self, body: &Value) -> Result<(Value, Usage), String> {
+        let tools = self.config.protocol == Protocol::Tools;
+        let mut resp = self.post(body).await?;
+        let framing = self.provider.framing();
+        let mut sse = SseParser::default();
+        let mut lines = LineParser::default();
+        let mut thought = ThoughtStream::default();
+        let mut content = String::new();
+        let mut calls: Vec<StreamedCall> = Vec::new();
+        let mut streamed = false;
+        let mut usage = Usage::default();
+
+        'read: loop {
+            let chunk = resp
+                .chunk()
+                .await
+                .map_err(|e| format!("LLM stream failed: {}", e))?;
+            let end = chunk.is_none();
+            let events = match (framing, chunk) {
+                (Framing::Sse, Some(bytes)) => sse.feed(&bytes),
+                (Framing::Lines, Some(bytes)) => lines.feed(&bytes),
+                (Framing::Sse, None) => Vec::new(),
+                // The last line may lack its newline
+                (Framing::Lines, None) => lines.finish(),
+            };
+            for data in events {
+                for delta in self.provider.deltas(&data)? {
+                    let text = match delta {
+                        Delta::Done => break 'read,
+                        Delta::Usage(next) => {
+                            usage.merge(&next);
+                            None
+                        }
+                        Delta::Content(text) => {
+                            content.push_str(&text);
+                            if tools {
+                                Some(text)
+                            } else {
+                                thought.push(&text)
+                            }
+                        }
+                        Delta::ToolCall {
+                            index,
+                            id,
+                            name,
+                            arguments,
+                        } => {
+                            let first = calls.first().is_none_or(|call| call.index == index);
+                            let call = match calls.iter().position(|call| call.index == index) {
+                                Some(i) => &mut calls[i],
+                                None => {
+                                    calls.push(StreamedCall {
+                                        index,
+                                        ..Default::default()
+                                    });
+                                    calls.last_mut().unwrap()
+                                }
+                            };
+                            call.id.push_str(&id);
+                            call.name.push_str(&name);
+                            call.arguments.push_str(&arguments);
+                            if first {
+                                thought.push(&arguments)
+                            } else {
+                                None
+                            }
+                        }
+                    };
+                    if let Some(text) = text.filter(|t| !t.is_empty()) {
+                        streamed = true;
+                        self.send_delta(text);
+                    }
+                }
+            }
+            if end {
+                break;
+            }
+        }
+
+        if streamed {
+            self.send_delta("\n".to_string());
+        }
+        let mut message = serde_json::json!({ "content": content });
+        if !calls.is_empty() {
+            let calls = calls
+                .into_iter()
+                .map(|call| {
+                    serde_json::json!({
+                        "id": call.id,
+                        "type": "function",
+                        "function": { "name": call.name, "arguments": call.arguments },
+                    })
+                })
+                .collect();
+            message["tool_calls"] = Value::Array(calls);
+        }
+        Ok((
+            self.provider.finish(message, Some(self.config.protocol)),
+            usage,
+        ))
+    }
+
+    /// Forwards thought text to the delta channel, if one is set.
+    fn send_delta(&self, text: String) {
+        if let Some(deltas) = &self.deltas {
+            let _ = deltas.send(text);
+        }
+    }
+
+    /// Posts a request body to the LLM API, retrying failed requests.
+    ///
+    /// Each attempt uses the current API key profile. A rate limited,
+    /// rejected or timed out profile cools down for the configured time,
+    /// or for as long as `Retry-After` asks, and the next profile that is
+    /// not cooling down takes over. After a rate limit or rejection, a
+    /// ready profile is tried right away; otherwise the retry waits for the
+    /// backoff delay, and at least `Retry-After`. Overflow and other request
+    /// errors are returned at once. A stream that fails after its response
+    /// arrived is not retried, as its deltas were already forwarded.
+    ///
+    /// # Returns
+    /// The response, or an error starting with `LLM <failure> error` once
+    /// the request cannot be retried. The failure is kept for `take_failure`.
+    async fn post(&self, body: &Value) -> Result<Response, String> {
+        let mut attempt = 1;
+        loop {
+            let (index, key) = self.profiles().pick(Instant::now());
+            let (failure, error, retry_after) = match self.send(body, key.as_deref()).await {
+                Ok(resp) => return Ok(resp),
+                Err(rejection) => rejection,
+            };
+
+            let Some(delay) = self.schedule(failure, index, attempt, retry_after) else {
+                *self.failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(failure);
+                return Err(match attempt {
+                    1 => format!("LLM {} error: {}", failure, error),
+                    n => format!("LLM {} error ({} attempts): {}", failure, n, error),
+                });
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends one request with the given API key.
+    ///
+    /// # Returns
+    /// The response, or the failure, error message and `Retry-After` wait
+    /// if the request failed or the status is not a success.
+    async fn send(
+        &self,
+        body: &Value,
+        api_key: Option<&str>,
+    ) -> Result<Response, (Failure, String, Option<Duration>)> {
+        if let Some(failure) = self
+            .faults
+            .take(|f| f.failure().is_some())
+            .and_then(|f| f.failure())
+        {
+            return Err((failure, simulated(failure), None));
+        }
+        let local = match &self.cassette {
+            Some(cassette) if cassette.replays() => Some(cassette.replay(body)),
+            _ => self.provider.respond(body),
+        };
+        if let Some(reply) = local {
+            return reply
+                .map(|text| Response::from(http::Response::new(text)))
+                .map_err(|e| (Failure::Request, e, None));
+        }
+
+        let resp = self
+            .provider
+            .authorize(self.client.post(&self.config.base_url), api_key)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| {
+                let failure = Failure::from_error(&e);
+                (failure, format!("LLM request failed: {}", e), None)
+            })?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = retry::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            let failure = Failure::classify(status, &text);
+            return Err((
+                failure,
+                format!("LLM returned {}: {}", status, text),
+                retry_after,
+            ));
+        }
+        let Some(cassette) = &self.cassette else {
+            return Ok(resp);
+        };
+
+        // Recording reads the whole body, so streams arrive at once
+        let text = resp.text().await.map_err(|e| {
+            let failure = Failure::from_error(&e);
+            (failure, format!("LLM request failed: {}", e), None)
+        })?;
+        cassette
+            .record(body, &text)
+            .map_err(|e| (Failure::Request, e, None))?;
+        Ok(Response::from(http::Response::new(text)))
+    }
+
+    /// Records a failed attempt and decides whether to retry it.
+    ///
+    /// # Arguments
+    /// - `failure`: Why the attempt failed.
+    /// - `index`: The key profile the attempt used.
+    /// - `attempt`: The number of the attempt, starting at 1.
+    /// - `retry_after`: How long the API asked to wait, if it did.
+    ///
+    /// # Returns
+    /// The delay before the next attempt, or `None` to give up.
+    fn schedule(
+        &self,
+        failure: Failure,
+        index: usize,
+        attempt: u32,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        let now = Instant::now();
+        let mut profiles = self.profiles();
+        if let Some(cooldown) = failure.cooldown(&self.retry) {
+            profiles.cool_down(index, retry_after.unwrap_or(cooldown), now);
+        } else if failure != Failure::Server {
+            return None;
+        }
+        if attempt >= self.retry.max_attempts {
+            return None;
+        }
+
+        let rotates = matches!(failure, Failure::RateLimit | Failure::Auth);
+        if rotates && profiles.any_available(now) {
+            return Some(Duration::ZERO);
+        }
+        if failure == Failure::Auth {
+            // Every key was rejected; waiting will not change that
+            return None;
+        }
+        let delay = retry::backoff(attempt, &self.retry).max(retry_after.unwrap_or_default());
+        (delay.as_millis() <= self.retry.max_backoff_ms as u128).then_some(delay)
+    }
+
+    /// Locks the API key profiles.
+    fn profiles(&self) -> std::sync::MutexGuard<'_, Profiles> {
+        self.profiles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Builds the HTTP client, with the read timeout of the retry settings.
+fn http_client(retry: &RetryConfig) -> Client {
+    Client::builder()
+        .read_timeout(Duration::from_secs(retry.read_timeout_secs))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Returns the error message of an injected request failure, worded like
+/// the real one so it is classified the same way.
+fn simulated(failure: Failure) -> String {
+    match failure {
+        Failure::RateLimit => "LLM returned 429 Too Many Requests: simulated rate limit",
+        Failure::Auth => "LLM returned 401 Unauthorized: simulated invalid API key",
+        Failure::Timeout => "LLM request failed: simulated timeout",
+        Failure::Overflow => "LLM returned 400 Bad Request: simulated context_length_exceeded",
+        Failure::Server | Failure::Request => "LLM request failed: simulated failure",
+    }
+    .to_string()
+}
+
+/// Returns the text of a reply as the model produced it: the content, or
+/// the tool calls when there is none.
+fn raw_reply(message: &Value) -> String {
+    match message["content"].as_str().filter(|c| !c.is_empty()) {
+        Some(content) => content.to_string(),
+        None => message["tool_calls"].to_string(),
+    }
+}
+
+/// A tool call assembled from stream deltas.
+///
+/// # Fields
+/// - `index`: Position of the call in the provider's stream.
+/// - `id`, `name`, `arguments`: The call's fields, concatenated from all fragments.
+#[derive(Default)]
+struct StreamedCall {
+    index: usize,
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+#[async_trait]
+impl Node for LLMClient {
+    /// Builds the full message list from context via the prompt engine.
+    ///
+    /// # Arguments
+    /// - `shared`: The conversation context.
+    ///
+    /// # Returns
+    /// A JSON array of messages ready for the LLM API.
+    async fn prep(&mut self, shared: &Context) -> Result<Value, String> {
+        let messages = self.prompt_engine.build(shared);
+        if messages.len() <= 1 {
+            return Err("context has no messages".to_string());
+        }
+        Ok(Value::Array(messages))
+    }
+
+    /// Calls the LLM chat API with the prepared messages.
+    ///
+    /// # Arguments
+    /// - `prep_res`: JSON array of messages from `prep`.
+    ///
+    /// # Returns
+    /// A JSON object with `response` and `usage`.
+    async fn exec(&mut self, prep_res: Value) -> Result<Value, String> {
+        let messages = prep_res.as_array().ok_or("prep_res is not an array")?;
+        let (response, usage) = self.chat(messages).await?;
+        let response_value = serde_json::to_value(&response)
+            .map_err(|e| format!("failed to serialize LLMResponse: {}", e))?;
+        Ok(serde_json::json!({
+            "response": response_value,
+            "usage": usage,
+        }))
+    }
+
+    /// Writes the LLM response into context and returns the action for flow control.
+    ///
+    /// # Arguments
+    /// - `shared`: The conversation context to update.
+    /// - `_prep_res`: Unused.
+    /// - `exec_res`: JSON object containing `response` and `usage`.
+    ///
+    /// # Returns
+    /// The `Action` from the LLM response.
+    async fn post(&mut self, shared: &mut Context, _prep_res: Value, exec_res: Value) -> Action {
+        let response: LLMResponse = match serde_json::from_value(exec_res["response"].clone()) {
+            Ok(r) => r,
+            Err(e) => return Action::Completed {
+                answer: format!("failed to parse exec result: {}", e),
+            },
+        };
+        let usage: Usage = serde_json::from_value(exec_res["usage"].clone()).unwrap_or_default();
+
+        shared.record_usage(&usage);
+        shared.add_assistant_message(response.clone());
+
+        response.action
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{CassetteMode, ProviderKind};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Serves one successful HTTP response per body, in order, on a local port.
+    ///
+    /// # Returns
+    /// The URL to post to, and a receiver for the request heads and JSON bodies.
+    async fn serve(
+        content_type: &'static str,
+        bodies: Vec<String>,
+    ) -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+        let responses = bodies
+            .into_iter()
+            .map(|body| http_response("200 OK", content_type, "", &body))
+            .collect();
+        serve_responses(responses).await
+    }
+
+    /// Formats an HTTP response with the given status, extra header lines and body.
+    pub(crate) fn http_response(
+        status: &str,
+        content_type: &str,
+        headers: &str,
+        body: &str,
+    ) -> String {
+        format!(
+            "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\n{}connection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            headers,
+            body
+        )
+    }
+
+    /// Serves the given raw HTTP responses, in order, on a local port.
+    pub(crate) async fn serve_responses(
+        responses: Vec<String>,
+    ) -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/v1/chat/completions",
+            listener.local_addr().unwrap()
+        );
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                // Read the whole request so closing the socket does not reset it
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let start = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            break end + 4;
+                        }
+                    }
+                };
+                let head = String::from_utf8_lossy(&request[..start]).to_lowercase();
+                let sent = serde_json::from_slice(&request[start..]).unwrap_or_default();
+                let _ = tx.send((head, sent));
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    pub(crate) fn client(base_url: String, stream: bool) -> LLMClient {
+        LLMClient {
+            client: Client::new(),
+            provider: Box::new(provider::openai::OpenAI),
+            config: LLMConfig {
+                name: "test".to_string(),
+                model: "test-model".to_string(),
+                base_url,
+                api_key_env: Some("UNUSED".to_string()),
+                api_key_envs: Vec::new(),
+                max_tokens: 1024,
+                context_window: 25_600,
+                reasoning_effort: None,
+                backend: None,
+                stream,
+                protocol: Protocol::JsonSchema,
+                provider: ProviderKind::Openai,
+            },
+            profiles: Mutex::new(Profiles::new(vec!["key".to_string()])),
+            retry: RetryConfig {
+                backoff_ms: 1,
+                max_backoff_ms: 100,
+                ..RetryConfig::default()
+            },
+            prompt_engine: PromptEngine::new(String::new()),
+            deltas: None,
+            faults: Faults::default(),
+            cassette: None,
+            failure: Mutex::new(None),
+            spent: Mutex::new(Usage::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let json = r#"{"thought":{"thought_type":"planning","content":"List the files."},"action":{"action":"execute","command":"ls"}}"#;
+        let mut body = String::new();
+        for piece in [&json[..40], &json[40..52], &json[52..]] {
+            let event = serde_json::json!({ "choices": [{ "delta": { "content": piece } }] });
+            body.push_str(&format!("data: {}\n\n", event));
+        }
+        body.push_str(
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":3}}\n\n",
+        );
+        body.push_str("data: [DONE]\n\n");
+
+        let mut llm = client(serve("text/event-stream", vec![body]).await.0, true);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        llm.set_deltas(tx);
+        let (response, usage) = llm.chat(&[]).await.unwrap();
+
+        assert_eq!(response.thought.content, "List the files.");
+        assert!(matches!(response.action, Action::Execute { command } if command == "ls"));
+        assert_eq!(usage.total_tokens, 10);
+
+        let mut deltas = Vec::new();
+        while let Ok(delta) = rx.try_recv() {
+            deltas.push(delta);
+        }
+        assert!(deltas.len() > 2);
+        assert_eq!(deltas.concat(), "List the files.\n");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_error_event() {
+        let body = "data: {\"error\":{\"message\":\"overloaded\"}}\n\n".to_string();
+        let llm = client(serve("text/event-stream", vec![body]).await.0, true);
+        let err = llm.chat(&[]).await.unwrap_err();
+        assert!(err.starts_with("LLM stream error:"), "{}", err);
+        assert!(err.contains("overloaded"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_tools() {
+        let message = serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": {
+                    "name": "completed",
+                    "arguments": r#"{"thought":{"thought_type":"analysis","content":"Done."},"answer":"42"}"#,
+                },
+            }],
+        });
+        let body = serde_json::json!({
+            "choices": [{ "message": message }],
+            "usage": { "prompt_tokens": 5, "completion_tokens": 2 },
+        });
+
+        let mut llm = client(
+            serve("application/json", vec![body.to_string()]).await.0,
+            false,
+        );
+        llm.config.protocol = Protocol::Tools;
+        let (response, usage) = llm.chat(&[]).await.unwrap();
+        assert_eq!(response.thought.content, "Done.");
+        assert!(matches!(response.action, Action::Completed { answer } if answer == "42"));
+        assert_eq!(usage.total_tokens, 7);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_tools() {
+        let args = r#"{"thought":{"thought_type":"planning","content":"Check the disk."},"command":"df -h"}"#;
+        let mut body = String::new();
+        for (i, piece) in [&args[..30], &args[30..50], &args[50..]].iter().enumerate() {
+            let mut call = serde_json::json!({ "index": 0, "function": { "arguments": piece } });
+            if i == 0 {
+                call["id"] = Value::from("call_1");
+                call["function"]["name"] = Value::from("execute");
+            }
+            let event = serde_json::json!({ "choices": [{ "delta": { "tool_calls": [call] } }] });
+            body.push_str(&format!("data: {}\n\n", event));
+        }
+        body.push_str("data: [DONE]\n\n");
+
+        let mut llm = client(serve("text/event-stream", vec![body]).await.0, true);
+        llm.config.protocol = Protocol::Tools;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        llm.set_deltas(tx);
+        let (response, _) = llm.chat(&[]).await.unwrap();
+
+        assert_eq!(response.thought.content, "Check the disk.");
+        assert!(matches!(response.action, Action::Execute { command } if command == "df -h"));
+        let mut deltas = String::new();
+        while let Ok(delta) = rx.try_recv() {
+            deltas.push_str(&delta);
+        }
+        assert_eq!(deltas, "Check the disk.\n");
+    }
+
+    #[tokio::test]
+    async fn test_chat_retries_unparsable_reply() {
+        let reply = |content: &str, tokens: u32| {
+            serde_json::json!({
+                "choices": [{ "message": { "role": "assistant", "content": content } }],
+                "usage": { "prompt_tokens": tokens, "completion_tokens": 1 },
+            })
+            .to_string()
+        };
+        let bodies = vec![
+            reply("I think I should run ls.", 10),
+            reply(
+                r#"{"thought": {"thought_type": "planning", "content": "ok"}, "action": {"action": "continue"}}"#,
+                20,
+            ),
+        ];
+        let (url, mut requests) = serve("application/json", bodies).await;
+        let mut llm = client(url, false);
+        llm.config.protocol = Protocol::Text;
+
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+        let (response, usage) = llm.chat(&messages).await.unwrap();
+        assert!(matches!(response.action, Action::Continue));
+        assert_eq!(usage.prompt_tokens, 30);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 32);
+
+        let (_, first) = requests.recv().await.unwrap();
+        assert!(first.get("response_format").is_none());
+        let (_, retry) = requests.recv().await.unwrap();
+        let sent = retry["messages"].as_array().unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[1]["content"], "I think I should run ls.");
+        let correction = sent[2]["content"].as_str().unwrap();
+        assert!(correction.contains("no JSON object"), "{}", correction);
+        assert!(
+            correction.contains("\"skill\":\"response_format\""),
+            "{}",
+            correction
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_keeps_usage_when_retry_fails() {
+        let reply = serde_json::json!({
+            "choices": [{ "message": { "content": "I think I should run ls." } }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 1 },
+        });
+        let responses = vec![
+            http_response("200 OK", "application/json", "", &reply.to_string()),
+            http_response("400 Bad Request", "application/json", "", "invalid model"),
+        ];
+        let (url, _requests) = serve_responses(responses).await;
+        let mut llm = client(url, false);
+        llm.config.protocol = Protocol::Text;
+
+        let err = llm.chat(&[]).await.unwrap_err();
+        assert!(err.contains("invalid model"), "{}", err);
+        assert_eq!(llm.take_spent().total_tokens, 11);
+        assert_eq!(llm.take_spent().total_tokens, 0);
+    }
+
+    #[tokio::test]
+    async fn test_chat_retries_tool_call_with_result() {
+        let reply = |name: &str| {
+            let args = r#"{"thought":{"thought_type":"planning","content":"ok"},"command":"ls"}"#;
+            serde_json::json!({
+                "choices": [{ "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_x",
+                        "type": "function",
+                        "function": { "name": name, "arguments": args },
+                    }],
+                } }],
+            })
+            .to_string()
+        };
+        let (url, mut requests) =
+            serve("application/json", vec![reply("explode"), reply("execute")]).await;
+        let mut llm = client(url, false);
+        llm.config.protocol = Protocol::Tools;
+
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+        let (response, _) = llm.chat(&messages).await.unwrap();
+        assert!(matches!(response.action, Action::Execute { command } if command == "ls"));
+
+        requests.recv().await.unwrap();
+        let (_, retry) = requests.recv().await.unwrap();
+        let sent = retry["messages"].as_array().unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[1]["role"], "assistant");
+        assert_eq!(sent[1]["tool_calls"][0]["id"], "call_x");
+        assert_eq!(sent[1]["tool_calls"][0]["function"]["name"], "explode");
+        assert_eq!(sent[2]["role"], "tool");
+        assert_eq!(sent[2]["tool_call_id"], "call_x");
+        let correction = sent[2]["content"].as_str().unwrap();
+        assert!(
+            correction.contains("unknown tool 'explode'"),
+            "{}",
+            correction
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_gives_up_after_retries() {
+        let body = serde_json::json!({ "choices": [{ "message": { "content": "nope" } }] });
+        let bodies = vec![body.to_string(); PARSE_RETRIES as usize + 1];
+        let (url, _requests) = serve("application/json", bodies).await;
+        let mut llm = client(url, false);
+        llm.config.protocol = Protocol::JsonObject;
+
+        let err = llm.chat(&[]).await.unwrap_err();
+        assert!(
+            err.starts_with("LLM output contains no JSON object"),
+            "{}",
+            err
+        );
+        assert!(err.ends_with("raw: nope"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_chat_anthropic() {
+        let body = serde_json::json!({
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_1",
+                "name": "agent_response",
+                "input": {
+                    "thought": { "thought_type": "planning", "content": "Say hi." },
+                    "action": { "action": "completed", "answer": "hi" },
+                },
+            }],
+            "usage": { "input_tokens": 12, "output_tokens": 4 },
+        });
+        let (url, mut requests) = serve("application/json", vec![body.to_string()]).await;
+        let mut llm = client(url, false);
+        llm.config.provider = ProviderKind::Anthropic;
+        llm.provider = provider::build(&llm.config).unwrap();
+
+        let messages = [
+            serde_json::json!({ "role": "system", "content": "Be brief." }),
+            serde_json::json!({ "role": "user", "content": "hello" }),
+        ];
+        let (response, usage) = llm.chat(&messages).await.unwrap();
+        assert!(matches!(response.action, Action::Completed { answer } if answer == "hi"));
+        assert_eq!(usage.total_tokens, 16);
+
+        let (head, sent) = requests.recv().await.unwrap();
+        assert!(head.contains("x-api-key: key"), "{}", head);
+        assert!(head.contains("anthropic-version:"), "{}", head);
+        assert!(!head.contains("authorization"), "{}", head);
+        assert_eq!(sent["system"], "Be brief.");
+        assert_eq!(sent["messages"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_anthropic() {
+        let json = r#"{"thought":{"thought_type":"planning","content":"Count."},"action":{"action":"execute","command":"wc -l"}}"#;
+        let events = [
+            serde_json::json!({ "type": "message_start", "message": { "usage": { "input_tokens": 9, "output_tokens": 1 } } }),
+            serde_json::json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            serde_json::json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": &json[1..30] } }),
+            serde_json::json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": &json[30..] } }),
+            serde_json::json!({ "type": "message_delta", "usage": { "output_tokens": 20 } }),
+            serde_json::json!({ "type": "message_stop" }),
+        ];
+        let body: String = events
+            .iter()
+            .map(|event| {
+                format!(
+                    "event: {}\ndata: {}\n\n",
+                    event["type"].as_str().unwrap(),
+                    event
+                )
+            })
+            .collect();
+
+        let mut llm = client(serve("text/event-stream", vec![body]).await.0, true);
+        llm.config.provider = ProviderKind::Anthropic;
+        llm.provider = provider::build(&llm.config).unwrap();
+        llm.config.protocol = Protocol::JsonObject;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        llm.set_deltas(tx);
+        let (response, usage) = llm.chat(&[]).await.unwrap();
+
+        assert_eq!(response.thought.content, "Count.");
+        assert!(matches!(response.action, Action::Execute { command } if command == "wc -l"));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (9, 20));
+        let mut deltas = String::new();
+        while let Ok(delta) = rx.try_recv() {
+            deltas.push_str(&delta);
+        }
+        assert_eq!(deltas, "Count.\n");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_ollama() {
+        let json = r#"{"thought":{"thought_type":"analysis","content":"Check disk."},"action":{"action":"execute","command":"df -h"}}"#;
+        let lines = [
+            serde_json::json!({ "message": { "role": "assistant", "content": &json[..25] }, "done": false }),
+            serde_json::json!({ "message": { "role": "assistant", "content": &json[25..] }, "done": false }),
+            serde_json::json!({ "message": { "role": "assistant", "content": "" }, "done": true, "prompt_eval_count": 12, "eval_count": 30 }),
+        ];
+        // The last line has no trailing newline
+        let body = lines.map(|line| line.to_string()).join("\n");
+
+        let (url, mut requests) = serve("application/x-ndjson", vec![body]).await;
+        let mut llm = client(url, true);
+        llm.config.provider = ProviderKind::Ollama;
+        llm.provider = provider::build(&llm.config).unwrap();
+        llm.profiles = Mutex::new(Profiles::new(Vec::new()));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        llm.set_deltas(tx);
+        let (response, usage) = llm.chat(&[]).await.unwrap();
+
+        assert!(matches!(response.action, Action::Execute { command } if command == "df -h"));
+        assert_eq!(usage.total_tokens, 42);
+        let (head, sent) = requests.recv().await.unwrap();
+        assert!(!head.contains("authorization"), "{}", head);
+        assert_eq!(sent["stream"], true);
+        assert_eq!(sent["format"], response_schema());
+        let mut deltas = String::new();
+        while let Ok(delta) = rx.try_recv() {
+            deltas.push_str(&delta);
+        }
+        assert_eq!(deltas, "Check disk.\n");
+    }
+
+    /// A successful reply with a `continue` action.
+    fn continue_reply() -> String {
+        let content = r#"{"thought": {"thought_type": "planning", "content": "ok"}, "action": {"action": "continue"}}"#;
+        serde_json::json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] })
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_chat_rotates_key_profiles() {
+        let responses = vec![
+            http_response(
+                "429 Too Many Requests",
+                "application/json",
+                "retry-after: 3600\r\n",
+                "{}",
+            ),
+            http_response("200 OK", "application/json", "", &continue_reply()),
+            http_response("200 OK", "application/json", "", &continue_reply()),
+        ];
+        let (url, mut requests) = serve_responses(responses).await;
+        let mut llm = client(url, false);
+        llm.profiles = Mutex::new(Profiles::new(vec!["a".to_string(), "b".to_string()]));
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+
+        llm.chat(&messages).await.unwrap();
+        // The next call stays on the working key
+        llm.chat(&messages).await.unwrap();
+
+        let keys: Vec<String> = [(); 3]
+            .iter()
+            .map(|_| {
+                let (head, _) = requests.try_recv().unwrap();
+                head.lines()
+                    .find_map(|l| l.strip_prefix("authorization: bearer "))
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(keys, ["a", "b", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_chat_retries_server_errors() {
+        let unavailable = || http_response("503 Service Unavailable", "text/plain", "", "busy");
+        let responses = vec![
+            unavailable(),
+            http_response("200 OK", "application/json", "", &continue_reply()),
+            unavailable(),
+            unavailable(),
+        ];
+        let (url, _requests) = serve_responses(responses).await;
+        let mut llm = client(url, false);
+        llm.retry.max_attempts = 2;
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+
+        llm.chat(&messages).await.unwrap();
+        assert_eq!(llm.take_failure(), None);
+        let err = llm.chat(&messages).await.unwrap_err();
+        assert!(
+            err.starts_with("LLM server error (2 attempts): LLM returned 503"),
+            "{}",
+            err
+        );
+        assert_eq!(llm.take_failure(), Some(Failure::Server));
+        assert_eq!(llm.take_failure(), None);
+    }
+
+    #[tokio::test]
+    async fn test_chat_does_not_retry_overflow_or_long_waits() {
+        let overflow = r#"{"error":{"code":"context_length_exceeded"}}"#;
+        let responses = vec![
+            http_response("400 Bad Request", "application/json", "", overflow),
+            http_response(
+                "429 Too Many Requests",
+                "application/json",
+                "retry-after: 60\r\n",
+                "{}",
+            ),
+        ];
+        let (url, _requests) = serve_responses(responses).await;
+        let llm = client(url, false);
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+
+        let err = llm.chat(&messages).await.unwrap_err();
+        assert!(err.starts_with("LLM overflow error: "), "{}", err);
+        assert_eq!(llm.take_failure(), Some(Failure::Overflow));
+        // Retry-After asks for longer than max_backoff_ms
+        llm.chat(&messages).await.unwrap_err();
+        assert_eq!(llm.take_failure(), Some(Failure::RateLimit));
+    }
+
+    #[tokio::test]
+    async fn test_chat_injected_faults() {
+        let responses = vec![http_response(
+            "200 OK",
+            "application/json",
+            "",
+            &continue_reply(),
+        )];
+        let (url, mut requests) = serve_responses(responses).await;
+        let llm = client(url, false);
+        llm.faults.inject(Fault::MalformedJson, 1);
+        llm.faults.inject(Fault::RateLimit, 1);
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+
+        // The malformed reply is corrected, the rate limit retried
+        llm.chat(&messages).await.unwrap();
+        let (_, sent) = requests.try_recv().unwrap();
+        assert_eq!(sent["messages"][1]["content"], "simulated malformed reply");
+        assert!(requests.try_recv().is_err());
+
+        llm.faults.inject(Fault::Overflow, 1);
+        let err = llm.chat(&messages).await.unwrap_err();
+        assert!(err.starts_with("LLM overflow error: "), "{}", err);
+        assert!(crate::core::guard::is_context_overflow(&err));
+        assert!(llm.faults.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chat_records_and_replays_cassette() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        let config = |mode| crate::config::CassetteConfig {
+            mode,
+            path: Some(dir.join("cassette.jsonl").display().to_string()),
+        };
+        let responses = vec![http_response(
+            "200 OK",
+            "application/json",
+            "",
+            &continue_reply(),
+        )];
+        let (url, _requests) = serve_responses(responses).await;
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+
+        let recorder = client(url, false)
+            .with_cassette(Cassette::open(&config(CassetteMode::Record)).unwrap());
+        let (recorded, _) = recorder.chat(&messages).await.unwrap();
+
+        // Nothing listens here; the response comes from the cassette
+        let player = client("http://127.0.0.1:1".to_string(), false)
+            .with_cassette(Cassette::open(&config(CassetteMode::Replay)).unwrap());
+        let (replayed, _) = player.chat(&messages).await.unwrap();
+        assert_eq!(replayed.thought.content, recorded.thought.content);
+
+        let other = [serde_json::json!({ "role": "user", "content": "bye" })];
+        let err = player.chat(&other).await.unwrap_err();
+        assert!(
+            err.starts_with("LLM request error: no response recorded"),
+            "{}",
+            err
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
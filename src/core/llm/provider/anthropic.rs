@@ -0,0 +1,424 @@
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use super::{Delta, Provider, Request, parse_event};
+use crate::config::{LLMConfig, Protocol};
+use crate::core::llm::{Usage, response_schema, tools};
+
+/// API version sent in the `anthropic-version` header.
+const API_VERSION: &str = "2023-06-01";
+
+/// Name of the tool that carries the response with the `json_schema` protocol.
+const RESPONSE_TOOL: &str = "agent_response";
+
+/// Anthropic Messages API, authenticated with `x-api-key`.
+///
+/// System messages move to the top-level `system` field, tool calls and
+/// their results become `tool_use` and `tool_result` blocks, and
+/// consecutive messages of one role are merged, as the API requires
+/// alternating turns. Structured output is requested per protocol:
+/// - `json_schema`: a single forced tool whose input schema is the
+///   response schema; its input becomes the reply content.
+/// - `json_object` and `text`: the reply is prefilled with `{`, which is
+///   prepended to the content again.
+/// - `tools`: the agent's tools, converted to Anthropic's format.
+pub struct Anthropic;
+
+impl Provider for Anthropic {
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value {
+        let (system, mut messages) = convert_messages(request.messages);
+        let mut body = serde_json::json!({
+            "model": config.model,
+            "max_tokens": config.max_tokens,
+        });
+        if !system.is_empty() {
+            body["system"] = Value::String(system);
+        }
+
+        match request.protocol {
+            Some(Protocol::JsonSchema) => {
+                body["tools"] = serde_json::json!([{
+                    "name": RESPONSE_TOOL,
+                    "description": "Respond with your thought and next action.",
+                    "input_schema": response_schema(),
+                }]);
+                body["tool_choice"] = serde_json::json!({ "type": "tool", "name": RESPONSE_TOOL });
+            }
+            Some(Protocol::JsonObject | Protocol::Text) => {
+                messages.push(serde_json::json!({ "role": "assistant", "content": "{" }));
+            }
+            Some(Protocol::Tools) => {
+                let definitions = tools::definitions();
+                let tools: Vec<Value> = definitions
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|tool| {
+                        let function = &tool["function"];
+                        serde_json::json!({
+                            "name": function["name"],
+                            "description": function["description"],
+                            "input_schema": function["parameters"],
+                        })
+                    })
+                    .collect();
+                body["tools"] = Value::Array(tools);
+            }
+            None => {}
+        }
+        body["messages"] = Value::Array(messages);
+        if request.stream {
+            body["stream"] = Value::Bool(true);
+        }
+        body
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+        let builder = builder.header("anthropic-version", API_VERSION);
+        match api_key {
+            Some(key) => builder.header("x-api-key", key),
+            None => builder,
+        }
+    }
+
+    fn reply(&self, data: Value, protocol: Option<Protocol>) -> Result<(Value, Usage), String> {
+        let blocks = data["content"]
+            .as_array()
+            .ok_or("LLM response missing content")?;
+
+        let mut content = String::new();
+        let mut calls = Vec::new();
+        for block in blocks {
+            match block["type"].as_str() {
+                Some("text") => content.push_str(block["text"].as_str().unwrap_or_default()),
+                Some("tool_use") => calls.push(serde_json::json!({
+                    "id": block["id"],
+                    "type": "function",
+                    "function": {
+                        "name": block["name"],
+                        "arguments": block["input"].to_string(),
+                    },
+                })),
+                _ => {}
+            }
+        }
+
+        let mut message = serde_json::json!({ "content": content });
+        if !calls.is_empty() {
+            message["tool_calls"] = Value::Array(calls);
+        }
+        Ok((self.finish(message, protocol), usage(&data["usage"])))
+    }
+
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String> {
+        let event = parse_event(data)?;
+        let index = event["index"].as_u64().unwrap_or(0) as usize;
+        let delta = match event["type"].as_str().unwrap_or_default() {
+            "message_start" => Delta::Usage(usage(&event["message"]["usage"])),
+            "content_block_start" => {
+                let block = &event["content_block"];
+                match block["type"].as_str() {
+                    Some("tool_use") => Delta::ToolCall {
+                        index,
+                        id: block["id"].as_str().unwrap_or_default().to_string(),
+                        name: block["name"].as_str().unwrap_or_default().to_string(),
+                        arguments: String::new(),
+                    },
+                    Some("text") => {
+                        Delta::Content(block["text"].as_str().unwrap_or_default().to_string())
+                    }
+                    _ => return Ok(Vec::new()),
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        Delta::Content(delta["text"].as_str().unwrap_or_default().to_string())
+                    }
+                    Some("input_json_delta") => Delta::ToolCall {
+                        index,
+                        id: String::new(),
+                        name: String::new(),
+                        arguments: delta["partial_json"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                    },
+                    _ => return Ok(Vec::new()),
+                }
+            }
+            "message_delta" => Delta::Usage(usage(&event["usage"])),
+            "message_stop" => Delta::Done,
+            "error" => return Err(format!("LLM stream error: {}", event["error"])),
+            _ => return Ok(Vec::new()),
+        };
+        Ok(vec![delta])
+    }
+
+    /// Undoes the structured-output workarounds of `body`: the input of
+    /// the response tool becomes the content, and the prefilled `{` is put
+    /// back in front of the content.
+    fn finish(&self, mut message: Value, protocol: Option<Protocol>) -> Value {
+        match protocol {
+            Some(Protocol::JsonSchema) => {
+                let call = &message["tool_calls"][0]["function"];
+                if call["name"] == RESPONSE_TOOL {
+                    let arguments = call["arguments"].clone();
+                    message = serde_json::json!({ "content": arguments });
+                }
+            }
+            Some(Protocol::JsonObject | Protocol::Text) => {
+                let content = message["content"].as_str().unwrap_or_default();
+                message["content"] = Value::String(format!("{{{}", content));
+            }
+            _ => {}
+        }
+        message
+    }
+}
+
+/// Converts OpenAI-format messages into the Messages API layout.
+///
+/// # Returns
+/// The joined system prompt, and the conversation with consecutive
+/// messages of one role merged into one list of content blocks.
+fn convert_messages(messages: &[Value]) -> (String, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut turns: Vec<(&str, Vec<Value>)> = Vec::new();
+
+    for message in messages {
+        let text = message["content"].as_str().unwrap_or_default();
+        let (role, mut blocks) = match message["role"].as_str().unwrap_or_default() {
+            "system" => {
+                system.push(text);
+                continue;
+            }
+            "assistant" => {
+                let mut blocks = text_block(text);
+                for call in message["tool_calls"].as_array().into_iter().flatten() {
+                    let arguments = call["function"]["arguments"].as_str().unwrap_or("{}");
+                    blocks.push(serde_json::json!({
+                        "type": "tool_use",
+                        "id": call["id"],
+                        "name": call["function"]["name"],
+                        "input": serde_json::from_str::<Value>(arguments).unwrap_or_default(),
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            "tool" => {
+                let mut result = serde_json::json!({
+                    "type": "tool_result",
+                    "tool_use_id": message["tool_call_id"],
+                });
+                if !text.is_empty() {
+                    result["content"] = Value::from(text);
+                }
+                ("user", vec![result])
+            }
+            _ => ("user", text_block(text)),
+        };
+
+        match turns.last_mut() {
+            Some((last, previous)) if *last == role => previous.append(&mut blocks),
+            _ => turns.push((role, blocks)),
+        }
+    }
+
+    let messages = turns
+        .into_iter()
+        .map(|(role, blocks)| {
+            // A lone text block is sent as plain content
+            let content = match blocks.as_slice() {
+                [block] if block["type"] == "text" => block["text"].clone(),
+                _ => Value::Array(blocks),
+            };
+            serde_json::json!({ "role": role, "content": content })
+        })
+        .collect();
+    (system.join("\n\n"), messages)
+}
+
+/// Returns a text block for non-empty text; the API rejects empty ones.
+fn text_block(text: &str) -> Vec<Value> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    vec![serde_json::json!({ "type": "text", "text": text })]
+}
+
+/// Reads token usage from a Messages API `usage` object.
+///
+/// Cached input tokens are counted as prompt tokens, since they occupy
+/// the context window all the same.
+fn usage(usage: &Value) -> Usage {
+    let field = |name: &str| usage[name].as_u64().unwrap_or(0) as usize;
+    let prompt_tokens = field("input_tokens")
+        + field("cache_creation_input_tokens")
+        + field("cache_read_input_tokens");
+    let completion_tokens = field("output_tokens");
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LLMConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": "claude",
+            "model": "claude-test",
+            "base_url": "http://localhost/v1/messages",
+            "api_key_env": "UNUSED",
+            "provider": "anthropic",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_convert_messages() {
+        let messages = [
+            serde_json::json!({ "role": "system", "content": "Be brief." }),
+            serde_json::json!({ "role": "user", "content": "hi" }),
+            serde_json::json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{
+                    "id": "call_2",
+                    "type": "function",
+                    "function": { "name": "execute", "arguments": "{\"command\":\"ls\"}" },
+                }],
+            }),
+            serde_json::json!({ "role": "tool", "tool_call_id": "call_2", "content": "a.txt" }),
+            serde_json::json!({ "role": "user", "content": "thanks" }),
+        ];
+        let (system, messages) = convert_messages(&messages);
+
+        assert_eq!(system, "Be brief.");
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0],
+            serde_json::json!({ "role": "user", "content": "hi" })
+        );
+        assert_eq!(
+            messages[1]["content"],
+            serde_json::json!([{
+                "type": "tool_use",
+                "id": "call_2",
+                "name": "execute",
+                "input": { "command": "ls" },
+            }])
+        );
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["content"], "a.txt");
+        assert_eq!(messages[2]["content"][1]["text"], "thanks");
+    }
+
+    #[test]
+    fn test_body_per_protocol() {
+        let messages = [serde_json::json!({ "role": "user", "content": "hi" })];
+        let request = |protocol| Request {
+            messages: &messages,
+            protocol,
+            stream: false,
+        };
+
+        let body = Anthropic.body(&config(), &request(Some(Protocol::JsonSchema)));
+        assert_eq!(body["tool_choice"]["name"], RESPONSE_TOOL);
+        assert!(body.get("system").is_none());
+
+        let body = Anthropic.body(&config(), &request(Some(Protocol::Text)));
+        assert_eq!(
+            body["messages"][1],
+            serde_json::json!({ "role": "assistant", "content": "{" })
+        );
+
+        let body = Anthropic.body(&config(), &request(Some(Protocol::Tools)));
+        let names: Vec<&str> = body["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["use_skill", "execute", "completed"]);
+        assert!(body["tools"][0]["input_schema"]["properties"]["thought"].is_object());
+    }
+
+    #[test]
+    fn test_reply() {
+        let data = serde_json::json!({
+            "content": [
+                { "type": "text", "text": "Let me look." },
+                { "type": "tool_use", "id": "toolu_1", "name": RESPONSE_TOOL, "input": { "a": 1 } },
+            ],
+            "usage": { "input_tokens": 10, "cache_read_input_tokens": 5, "output_tokens": 3 },
+        });
+        let (message, usage) = Anthropic
+            .reply(data.clone(), Some(Protocol::JsonSchema))
+            .unwrap();
+        assert_eq!(message, serde_json::json!({ "content": "{\"a\":1}" }));
+        assert_eq!((usage.prompt_tokens, usage.total_tokens), (15, 18));
+
+        let (message, _) = Anthropic.reply(data, Some(Protocol::Tools)).unwrap();
+        assert_eq!(message["content"], "Let me look.");
+        assert_eq!(message["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"a\":1}"
+        );
+
+        let data = serde_json::json!({ "content": [{ "type": "text", "text": "\"a\": 1}" }] });
+        let (message, _) = Anthropic.reply(data, Some(Protocol::JsonObject)).unwrap();
+        assert_eq!(message["content"], "{\"a\": 1}");
+    }
+
+    #[test]
+    fn test_deltas() {
+        let deltas = |event: Value| Anthropic.deltas(&event.to_string());
+        assert_eq!(
+            deltas(serde_json::json!({
+                "type": "content_block_start",
+                "index": 1,
+                "content_block": { "type": "tool_use", "id": "toolu_1", "name": "execute", "input": {} },
+            }))
+            .unwrap(),
+            [Delta::ToolCall {
+                index: 1,
+                id: "toolu_1".to_string(),
+                name: "execute".to_string(),
+                arguments: String::new(),
+            }]
+        );
+        assert_eq!(
+            deltas(serde_json::json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": "Hi" },
+            }))
+            .unwrap(),
+            [Delta::Content("Hi".to_string())]
+        );
+        assert_eq!(
+            deltas(serde_json::json!({ "type": "message_delta", "usage": { "output_tokens": 7 } }))
+                .unwrap(),
+            [Delta::Usage(Usage {
+                prompt_tokens: 0,
+                completion_tokens: 7,
+                total_tokens: 7,
+            })]
+        );
+        assert_eq!(deltas(serde_json::json!({ "type": "ping" })).unwrap(), []);
+        let err = deltas(serde_json::json!({
+            "type": "error",
+            "error": { "type": "overloaded_error", "message": "Overloaded" },
+        }))
+        .unwrap_err();
+        assert!(err.contains("overloaded_error"), "{}", err);
+    }
+}
@@ -0,0 +1,102 @@
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use super::openai::OpenAI;
+use super::{Delta, Provider, Request};
+use crate::config::{LLMConfig, Protocol};
+use crate::core::llm::Usage;
+
+/// GBNF grammar for the agent response, in the style of llama.cpp's
+/// `grammars/json.gbnf`. Keys appear in a fixed order, which keeps the
+/// grammar small and the sampled output predictable.
+const RESPONSE_GRAMMAR: &str = r#"root ::= "{" ws "\"thought\":" ws thought "," ws "\"action\":" ws action ws "}"
+thought ::= "{" ws "\"thought_type\":" ws thought-type "," ws "\"content\":" ws string ws "}"
+thought-type ::= "\"planning\"" | "\"analysis\"" | "\"decision_making\"" | "\"problem_solving\"" | "\"memory_integration\"" | "\"self_reflection\"" | "\"goal_setting\"" | "\"prioritization\""
+action ::= use-skill | execute | continue | completed
+use-skill ::= "{" ws "\"action\":" ws "\"use_skill\"" "," ws "\"skills\":" ws "[" ws (string ("," ws string)*)? ws "]" ws "}"
+execute ::= "{" ws "\"action\":" ws "\"execute\"" "," ws "\"command\":" ws string ws "}"
+continue ::= "{" ws "\"action\":" ws "\"continue\"" ws "}"
+completed ::= "{" ws "\"action\":" ws "\"completed\"" "," ws "\"answer\":" ws string ws "}"
+string ::= "\"" ([^"\\\x7F\x00-\x1F] | "\\" (["\\bfnrt/] | "u" [0-9a-fA-F]{4}))* "\""
+ws ::= [ \t\n]{0,20}
+"#;
+
+/// llama.cpp's `llama-server`, through its OpenAI-compatible endpoint.
+///
+/// Requests and replies are those of `OpenAI`, except that `json_schema`
+/// sends the response as a GBNF `grammar` instead of a `response_format`:
+/// the server's schema converter rejects the `oneOf` used for actions on
+/// older builds, while a grammar is supported by all of them. No key is
+/// needed unless the server was started with `--api-key`.
+pub struct LlamaCpp;
+
+impl Provider for LlamaCpp {
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value {
+        let mut body = OpenAI.body(config, request);
+        if request.protocol == Some(Protocol::JsonSchema)
+            && let Some(fields) = body.as_object_mut()
+        {
+            fields.remove("response_format");
+            fields.insert("grammar".to_string(), Value::from(RESPONSE_GRAMMAR));
+        }
+        body
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+        OpenAI.authorize(builder, api_key)
+    }
+
+    fn reply(&self, data: Value, protocol: Option<Protocol>) -> Result<(Value, Usage), String> {
+        OpenAI.reply(data, protocol)
+    }
+
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String> {
+        OpenAI.deltas(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::llm::response_schema;
+
+    #[test]
+    fn test_body_uses_grammar() {
+        let config: LLMConfig = serde_json::from_value(serde_json::json!({
+            "name": "local",
+            "model": "qwen3-8b",
+            "base_url": "http://localhost:8080/v1/chat/completions",
+            "provider": "llama_cpp",
+        }))
+        .unwrap();
+        let request = |protocol| Request {
+            messages: &[],
+            protocol,
+            stream: false,
+        };
+
+        let body = LlamaCpp.body(&config, &request(Some(Protocol::JsonSchema)));
+        assert!(body.get("response_format").is_none());
+        assert_eq!(body["grammar"], RESPONSE_GRAMMAR);
+        let body = LlamaCpp.body(&config, &request(Some(Protocol::JsonObject)));
+        assert_eq!(body["response_format"]["type"], "json_object");
+        assert!(body.get("grammar").is_none());
+
+        // The grammar must stay in sync with the schema
+        let schema = response_schema();
+        let thought_types = schema["properties"]["thought"]["properties"]["thought_type"]["enum"]
+            .as_array()
+            .unwrap();
+        for thought_type in thought_types {
+            let literal = format!(r#""\"{}\"""#, thought_type.as_str().unwrap());
+            assert!(RESPONSE_GRAMMAR.contains(&literal), "{}", literal);
+        }
+        for action in schema["properties"]["action"]["oneOf"].as_array().unwrap() {
+            let literal = format!(
+                r#""\"{}\"""#,
+                action["properties"]["action"]["const"].as_str().unwrap()
+            );
+            assert!(RESPONSE_GRAMMAR.contains(&literal), "{}", literal);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+pub mod anthropic;
+pub mod llama_cpp;
+pub mod ollama;
+pub mod openai;
+pub mod script;
+
+use std::path::Path;
+
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use super::Usage;
+use crate::config::{LLMConfig, Protocol, ProviderKind};
+
+/// One call to the LLM, independent of the API it is sent to.
+///
+/// Messages use the OpenAI chat format, as built by `PromptEngine`;
+/// providers for other APIs translate them.
+///
+/// # Fields
+/// - `messages`: The conversation in OpenAI message format.
+/// - `protocol`: How the structured response is requested, or `None` for free text.
+/// - `stream`: Whether the response should be streamed.
+pub struct Request<'a> {
+    pub messages: &'a [Value],
+    pub protocol: Option<Protocol>,
+    pub stream: bool,
+}
+
+/// A piece of a streamed response, independent of the API it came from.
+///
+/// # Variants
+/// - `Content`: Text to append to the message content.
+/// - `ToolCall`: Fragments of the tool call identified by `index`, appended to what arrived before.
+/// - `Usage`: Token counts so far; the largest value of each field wins.
+/// - `Done`: The response is complete.
+#[derive(Debug, PartialEq)]
+pub enum Delta {
+    Content(String),
+    ToolCall {
+        index: usize,
+        id: String,
+        name: String,
+        arguments: String,
+    },
+    Usage(Usage),
+    Done,
+}
+
+/// How a streamed response body is split into events.
+///
+/// # Variants
+/// - `Sse`: Server-sent events; each event's `data` is one event.
+/// - `Lines`: Newline-delimited JSON; each line is one event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    Sse,
+    Lines,
+}
+
+/// Wire format of an LLM API.
+///
+/// `LLMClient` owns the HTTP client, streaming and parsing of the agent
+/// response; a provider only translates between the API and the OpenAI
+/// chat format used everywhere else. Replies are returned as an OpenAI
+/// `choices[0].message` object, with `content` and optional `tool_calls`.
+pub trait Provider: Send + Sync {
+    /// Builds the request body.
+    ///
+    /// # Arguments
+    /// - `config`: Model, token limit and other settings of the LLM.
+    /// - `request`: The call to encode.
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value;
+
+    /// Adds authentication and API headers to an HTTP request.
+    ///
+    /// # Arguments
+    /// - `builder`: The request to the configured `base_url`.
+    /// - `api_key`: The resolved API key, if one is configured.
+    fn authorize(&self, builder: RequestBuilder, api_key: Option<&str>) -> RequestBuilder;
+
+    /// Answers a request without sending it, for providers that are not
+    /// backed by an API. The default returns `None`, so the request is sent.
+    ///
+    /// # Arguments
+    /// - `body`: The request body built by `body`.
+    ///
+    /// # Returns
+    /// The response body, or an error that rejects the request.
+    fn respond(&self, _body: &Value) -> Option<Result<String, String>> {
+        None
+    }
+
+    /// Converts a response body into a reply message and token usage.
+    ///
+    /// # Arguments
+    /// - `data`: The JSON response body.
+    /// - `protocol`: The protocol of the request, or `None` for free text.
+    fn reply(&self, data: Value, protocol: Option<Protocol>) -> Result<(Value, Usage), String>;
+
+    /// Returns how a streamed response body is split into events.
+    /// The default is server-sent events.
+    fn framing(&self) -> Framing {
+        Framing::Sse
+    }
+
+    /// Converts one event of a streamed response into deltas.
+    ///
+    /// # Arguments
+    /// - `data`: The event: an SSE `data` payload, or one line of NDJSON.
+    ///
+    /// # Returns
+    /// The deltas in the event, or an error if the API reported one.
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String>;
+
+    /// Rewrites a reply assembled from deltas, the way `reply` does for a
+    /// complete response. The default returns it unchanged.
+    ///
+    /// # Arguments
+    /// - `message`: The assembled message.
+    /// - `protocol`: The protocol of the request.
+    fn finish(&self, message: Value, _protocol: Option<Protocol>) -> Value {
+        message
+    }
+}
+
+/// Creates the provider for an LLM config.
+///
+/// # Arguments
+/// - `config`: The LLM config; `provider` selects the API, and for
+///   `script` the script is read from `base_url`.
+///
+/// # Returns
+/// The provider, or an error if the script cannot be read.
+pub fn build(config: &LLMConfig) -> Result<Box<dyn Provider>, String> {
+    Ok(match config.provider {
+        ProviderKind::Openai => Box::new(openai::OpenAI),
+        ProviderKind::Anthropic => Box::new(anthropic::Anthropic),
+        ProviderKind::Ollama => Box::new(ollama::Ollama),
+        ProviderKind::LlamaCpp => Box::new(llama_cpp::LlamaCpp),
+        ProviderKind::Script => Box::new(script::Script::load(Path::new(&config.base_url))?),
+    })
+}
+
+/// Parses a stream event as JSON.
+fn parse_event(data: &str) -> Result<Value, String> {
+    serde_json::from_str(data).map_err(|e| format!("failed to parse LLM stream event: {}", e))
+}
@@ -0,0 +1,227 @@
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use super::{Delta, Framing, Provider, Request, parse_event};
+use crate::config::{LLMConfig, Protocol};
+use crate::core::llm::{Usage, response_schema, tools};
+
+/// Ollama's native chat API (`/api/chat`).
+///
+/// Structured output uses the `format` field: the response schema for
+/// `json_schema`, or `"json"` for `json_object`. `max_tokens` and
+/// `context_window` are passed as `num_predict` and `num_ctx`, since
+/// Ollama otherwise truncates the prompt to its small default context.
+/// Tool call arguments are objects rather than JSON strings, and streamed
+/// responses are newline-delimited JSON. No key is needed; one is sent as
+/// a bearer token if configured, e.g. for a server behind a proxy.
+pub struct Ollama;
+
+impl Provider for Ollama {
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value {
+        let messages: Vec<Value> = request.messages.iter().map(convert_message).collect();
+        let mut body = serde_json::json!({
+            "model": config.model,
+            "messages": messages,
+            "stream": request.stream,
+            "options": {
+                "num_predict": config.max_tokens,
+                "num_ctx": config.context_window,
+            },
+        });
+        match request.protocol {
+            Some(Protocol::JsonSchema) => body["format"] = response_schema(),
+            Some(Protocol::JsonObject) => body["format"] = Value::from("json"),
+            Some(Protocol::Tools) => body["tools"] = tools::definitions(),
+            Some(Protocol::Text) | None => {}
+        }
+        body
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+        match api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    fn reply(&self, data: Value, _protocol: Option<Protocol>) -> Result<(Value, Usage), String> {
+        if !data["message"].is_object() {
+            return Err("LLM response missing message".to_string());
+        }
+        Ok((convert_reply(&data["message"]), usage(&data)))
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Lines
+    }
+
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String> {
+        let event = parse_event(data)?;
+        if let Some(error) = event.get("error") {
+            return Err(format!("LLM stream error: {}", error));
+        }
+
+        let mut deltas = Vec::new();
+        let message = &event["message"];
+        if let Some(text) = message["content"].as_str().filter(|t| !t.is_empty()) {
+            deltas.push(Delta::Content(text.to_string()));
+        }
+        // Ollama sends each tool call whole, in a single event
+        for (index, call) in message["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let function = &call["function"];
+            deltas.push(Delta::ToolCall {
+                index: function["index"].as_u64().map_or(index, |i| i as usize),
+                id: String::new(),
+                name: function["name"].as_str().unwrap_or_default().to_string(),
+                arguments: arguments(&function["arguments"]),
+            });
+        }
+        if event["done"] == true {
+            deltas.push(Delta::Usage(usage(&event)));
+            deltas.push(Delta::Done);
+        }
+        Ok(deltas)
+    }
+}
+
+/// Converts an OpenAI-format message for Ollama: tool call arguments
+/// become objects.
+fn convert_message(message: &Value) -> Value {
+    let mut message = message.clone();
+    for call in message["tool_calls"].as_array_mut().into_iter().flatten() {
+        let arguments = &mut call["function"]["arguments"];
+        if let Some(raw) = arguments.as_str() {
+            *arguments = serde_json::from_str(raw).unwrap_or_default();
+        }
+    }
+    message
+}
+
+/// Converts an Ollama reply message into the OpenAI format.
+fn convert_reply(message: &Value) -> Value {
+    let mut reply =
+        serde_json::json!({ "content": message["content"].as_str().unwrap_or_default() });
+    if let Some(calls) = message["tool_calls"].as_array() {
+        let calls = calls
+            .iter()
+            .map(|call| {
+                serde_json::json!({
+                    "id": "",
+                    "type": "function",
+                    "function": {
+                        "name": call["function"]["name"],
+                        "arguments": arguments(&call["function"]["arguments"]),
+                    },
+                })
+            })
+            .collect();
+        reply["tool_calls"] = Value::Array(calls);
+    }
+    reply
+}
+
+/// Returns tool call arguments as a JSON string.
+fn arguments(arguments: &Value) -> String {
+    match arguments {
+        Value::String(raw) => raw.clone(),
+        arguments => arguments.to_string(),
+    }
+}
+
+/// Reads token usage from the final response object.
+fn usage(data: &Value) -> Usage {
+    let field = |name: &str| data[name].as_u64().unwrap_or(0) as usize;
+    let prompt_tokens = field("prompt_eval_count");
+    let completion_tokens = field("eval_count");
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body() {
+        let config: LLMConfig = serde_json::from_value(serde_json::json!({
+            "name": "local",
+            "model": "qwen3:8b",
+            "base_url": "http://localhost:11434/api/chat",
+            "provider": "ollama",
+            "context_window": 32768,
+        }))
+        .unwrap();
+        assert_eq!(config.api_keys(), Ok(Vec::new()));
+
+        let messages = [serde_json::json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "execute", "arguments": "{\"command\":\"ls\"}" },
+            }],
+        })];
+        let request = |protocol| Request {
+            messages: &messages,
+            protocol,
+            stream: false,
+        };
+
+        let body = Ollama.body(&config, &request(Some(Protocol::JsonSchema)));
+        assert_eq!(body["format"], response_schema());
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["options"]["num_ctx"], 32768);
+        assert_eq!(
+            body["messages"][0]["tool_calls"][0]["function"]["arguments"],
+            serde_json::json!({ "command": "ls" })
+        );
+        let body = Ollama.body(&config, &request(Some(Protocol::JsonObject)));
+        assert_eq!(body["format"], "json");
+        let body = Ollama.body(&config, &request(None));
+        assert!(body.get("format").is_none());
+    }
+
+    #[test]
+    fn test_reply_and_deltas() {
+        let data = serde_json::json!({
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{ "function": { "name": "execute", "arguments": { "command": "ls" } } }],
+            },
+            "done": true,
+            "prompt_eval_count": 30,
+            "eval_count": 8,
+        });
+        let (message, usage) = Ollama.reply(data.clone(), Some(Protocol::Tools)).unwrap();
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"command\":\"ls\"}"
+        );
+        assert_eq!(usage.total_tokens, 38);
+
+        let deltas = Ollama.deltas(&data.to_string()).unwrap();
+        assert_eq!(deltas.len(), 3);
+        assert!(matches!(&deltas[0], Delta::ToolCall { name, .. } if name == "execute"));
+        assert_eq!(deltas[2], Delta::Done);
+
+        let chunk = serde_json::json!({ "message": { "content": "{\"th" }, "done": false });
+        assert_eq!(
+            Ollama.deltas(&chunk.to_string()).unwrap(),
+            [Delta::Content("{\"th".to_string())]
+        );
+        let err = Ollama
+            .deltas(r#"{"error": "model not found"}"#)
+            .unwrap_err();
+        assert!(err.contains("model not found"), "{}", err);
+    }
+}
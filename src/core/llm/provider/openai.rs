@@ -0,0 +1,99 @@
+use reqwest::RequestBuilder;
+use serde_json::Value;
+
+use super::{Delta, Provider, Request, parse_event};
+use crate::config::{LLMConfig, Protocol};
+use crate::core::llm::{Usage, response_schema, tools};
+
+/// OpenAI-compatible chat completions API, with bearer authentication.
+///
+/// Also covers the many servers that mimic it (Mistral, vLLM, LiteLLM, ...).
+pub struct OpenAI;
+
+impl Provider for OpenAI {
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value {
+        let mut body = serde_json::json!({
+            "model": config.model,
+            "max_tokens": config.max_tokens,
+            "messages": request.messages,
+        });
+        let Some(protocol) = request.protocol else {
+            return body;
+        };
+
+        match protocol {
+            Protocol::JsonSchema => {
+                body["response_format"] = serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": "agent_response",
+                        "strict": true,
+                        "schema": response_schema(),
+                    }
+                });
+            }
+            Protocol::JsonObject => {
+                body["response_format"] = serde_json::json!({ "type": "json_object" });
+            }
+            Protocol::Text => {}
+            Protocol::Tools => body["tools"] = tools::definitions(),
+        }
+        if let Some(effort) = &config.reasoning_effort {
+            body["reasoning_effort"] = serde_json::json!(effort);
+        }
+        if request.stream {
+            body["stream"] = Value::Bool(true);
+            body["stream_options"] = serde_json::json!({ "include_usage": true });
+        }
+        body
+    }
+
+    fn authorize(&self, builder: RequestBuilder, api_key: Option<&str>) -> RequestBuilder {
+        match api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    fn reply(
+        &self,
+        mut data: Value,
+        _protocol: Option<Protocol>,
+    ) -> Result<(Value, Usage), String> {
+        let usage = Usage::from_response(&data);
+        let message = data["choices"][0]["message"].take();
+        if !message.is_object() {
+            return Err("LLM response missing choices[0].message".to_string());
+        }
+        Ok((message, usage))
+    }
+
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String> {
+        if data == "[DONE]" {
+            return Ok(vec![Delta::Done]);
+        }
+        let event = parse_event(data)?;
+        if let Some(error) = event.get("error") {
+            return Err(format!("LLM stream error: {}", error));
+        }
+
+        let mut deltas = Vec::new();
+        if event["usage"].is_object() {
+            deltas.push(Delta::Usage(Usage::from_response(&event)));
+        }
+        let delta = &event["choices"][0]["delta"];
+        if let Some(text) = delta["content"].as_str() {
+            deltas.push(Delta::Content(text.to_string()));
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+            deltas.push(Delta::ToolCall {
+                index: call["index"].as_u64().unwrap_or(0) as usize,
+                id: text(&call["id"]),
+                name: text(&call["function"]["name"]),
+                arguments: text(&call["function"]["arguments"]),
+            });
+        }
+        Ok(deltas)
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use reqwest::RequestBuilder;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{Delta, Framing, Provider, Request, parse_event};
+use crate::config::{LLMConfig, Protocol};
+use crate::core::llm::{LLMResponse, Usage, tools};
+
+/// One scripted LLM call.
+///
+/// # Fields
+/// - `expect`: Substrings the last message of the request must contain.
+/// - `reply`: What the call returns.
+/// - `usage`: Token usage reported for the call.
+#[derive(Debug, Deserialize)]
+struct Step {
+    #[serde(default)]
+    expect: Vec<String>,
+    #[serde(flatten)]
+    reply: Reply,
+    #[serde(default)]
+    usage: Usage,
+}
+
+/// The reply of a scripted call.
+///
+/// # Variants
+/// - `Response`: An agent response (`thought` and `action`), for `chat`.
+/// - `Text`: Free text, for auxiliary calls such as compaction.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Reply {
+    Response(LLMResponse),
+    Text { text: String },
+}
+
+/// Replays a script of replies instead of calling an API, so whole agent
+/// loops can be tested offline.
+///
+/// The script is a YAML or JSON list of steps, read from the file named by
+/// `base_url`. Each call takes the next step; a call that does not match it
+/// (missing expected text, a text step for an agent call or the other way
+/// round, or no steps left) fails as a rejected request.
+///
+/// # Fields
+/// - `steps`: The scripted calls, in order.
+/// - `next`: Index of the step for the next call.
+pub struct Script {
+    steps: Vec<Step>,
+    next: Mutex<usize>,
+}
+
+impl Script {
+    /// Reads a script file.
+    ///
+    /// # Arguments
+    /// - `path`: The YAML or JSON file with the list of steps.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read script {}: {}", path.display(), e))?;
+        let steps = serde_yaml::from_str(&data)
+            .map_err(|e| format!("failed to parse script {}: {}", path.display(), e))?;
+        Ok(Self {
+            steps,
+            next: Mutex::new(0),
+        })
+    }
+
+    /// Takes the next step and checks the request against it.
+    ///
+    /// # Returns
+    /// The reply message and usage in the format returned by `respond`.
+    fn play(&self, body: &Value) -> Result<Value, String> {
+        let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+        let index = *next;
+        let step = self
+            .steps
+            .get(index)
+            .ok_or(format!("script ended after {} steps", self.steps.len()))?;
+        *next += 1;
+        let fail = |error: String| format!("script step {}: {}", index + 1, error);
+
+        let last = body["messages"]
+            .as_array()
+            .and_then(|messages| messages.last())
+            .map(|message| match &message["content"] {
+                Value::String(content) => content.clone(),
+                content => content.to_string(),
+            })
+            .unwrap_or_default();
+        if let Some(missing) = step.expect.iter().find(|e| !last.contains(e.as_str())) {
+            return Err(fail(format!(
+                "expected the last message to contain '{}', got: {}",
+                missing, last
+            )));
+        }
+
+        let protocol: Option<Protocol> =
+            serde_json::from_value(body["protocol"].clone()).unwrap_or_default();
+        let message = match (&step.reply, protocol) {
+            (Reply::Response(response), Some(protocol)) => reply_message(response, protocol),
+            (Reply::Text { text }, None) => serde_json::json!({ "content": text }),
+            (Reply::Response(_), None) => {
+                return Err(fail(
+                    "expected a text reply, the script has a response".to_string(),
+                ));
+            }
+            (Reply::Text { .. }, Some(_)) => {
+                return Err(fail(
+                    "expected a response, the script has a text reply".to_string(),
+                ));
+            }
+        };
+        Ok(serde_json::json!({ "message": message, "usage": step.usage }))
+    }
+}
+
+impl Provider for Script {
+    fn body(&self, config: &LLMConfig, request: &Request) -> Value {
+        serde_json::json!({
+            "model": config.model,
+            "messages": request.messages,
+            "protocol": request.protocol,
+            "stream": request.stream,
+        })
+    }
+
+    fn authorize(&self, builder: RequestBuilder, _api_key: Option<&str>) -> RequestBuilder {
+        builder
+    }
+
+    fn respond(&self, body: &Value) -> Option<Result<String, String>> {
+        Some(self.play(body).map(|reply| reply.to_string()))
+    }
+
+    fn reply(
+        &self,
+        mut data: Value,
+        _protocol: Option<Protocol>,
+    ) -> Result<(Value, Usage), String> {
+        let usage = serde_json::from_value(data["usage"].take()).unwrap_or_default();
+        Ok((data["message"].take(), usage))
+    }
+
+    fn framing(&self) -> Framing {
+        Framing::Lines
+    }
+
+    /// Streams the whole reply as one event.
+    fn deltas(&self, data: &str) -> Result<Vec<Delta>, String> {
+        let event = parse_event(data)?;
+        let message = &event["message"];
+        let mut deltas = Vec::new();
+        if let Some(content) = message["content"].as_str().filter(|c| !c.is_empty()) {
+            deltas.push(Delta::Content(content.to_string()));
+        }
+        for (index, call) in message["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+            deltas.push(Delta::ToolCall {
+                index,
+                id: text(&call["id"]),
+                name: text(&call["function"]["name"]),
+                arguments: text(&call["function"]["arguments"]),
+            });
+        }
+        let usage = serde_json::from_value(event["usage"].clone()).unwrap_or_default();
+        deltas.push(Delta::Usage(usage));
+        deltas.push(Delta::Done);
+        Ok(deltas)
+    }
+}
+
+/// Encodes a scripted response as the reply message of the protocol:
+/// a tool call with `tools`, JSON content otherwise.
+fn reply_message(response: &LLMResponse, protocol: Protocol) -> Value {
+    if protocol == Protocol::Tools {
+        return match tools::call(response) {
+            Some((name, args)) => serde_json::json!({
+                "content": "",
+                "tool_calls": [{
+                    "id": "call_0",
+                    "type": "function",
+                    "function": { "name": name, "arguments": args.to_string() },
+                }],
+            }),
+            None => serde_json::json!({ "content": response.thought.content }),
+        };
+    }
+    let content = serde_json::to_string(response).unwrap_or_default();
+    serde_json::json!({ "content": content })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(yaml: &str) -> Script {
+        Script {
+            steps: serde_yaml::from_str(yaml).unwrap(),
+            next: Mutex::new(0),
+        }
+    }
+
+    fn body(protocol: Option<Protocol>, last: &str) -> Value {
+        serde_json::json!({
+            "messages": [{ "role": "user", "content": last }],
+            "protocol": protocol,
+        })
+    }
+
+    #[test]
+    fn test_play_script() {
+        let script = script(
+            r#"
+- expect: ["list"]
+  thought: { thought_type: planning, content: "List them." }
+  action: { action: execute, command: ls }
+  usage: { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15 }
+- text: "a summary"
+"#,
+        );
+
+        let reply = script
+            .play(&body(Some(Protocol::Tools), "list the files"))
+            .unwrap();
+        let (message, usage) = script.reply(reply, None).unwrap();
+        assert_eq!(usage.total_tokens, 15);
+        let response = tools::parse(&message).unwrap();
+        assert!(
+            matches!(response.action, crate::core::Action::Execute { command } if command == "ls")
+        );
+
+        let err = script
+            .play(&body(Some(Protocol::JsonSchema), "hi"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "script step 2: expected a response, the script has a text reply"
+        );
+        assert_eq!(
+            script.play(&body(None, "hi")).unwrap_err(),
+            "script ended after 2 steps"
+        );
+    }
+
+    #[test]
+    fn test_play_checks_expectations() {
+        let script = script(
+            r#"[{"expect": ["Cargo.toml"], "thought": {"thought_type": "analysis", "content": "Done."}, "action": {"action": "completed", "answer": "ok"}}]"#,
+        );
+        let err = script
+            .play(&body(Some(Protocol::JsonSchema), "README.md"))
+            .unwrap_err();
+        assert!(
+            err.starts_with("script step 1: expected the last message to contain 'Cargo.toml'")
+        );
+    }
+}
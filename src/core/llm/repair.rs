@@ -0,0 +1,204 @@
+use serde_json::Value;
+
+use super::{LLMResponse, ThoughtType};
+
+/// Parses an agent response from free-form model output.
+///
+/// Output that is valid as is parses directly. Otherwise the first JSON
+/// object is extracted, which skips markdown fences and surrounding prose,
+/// and common mistakes are repaired:
+/// - trailing commas before `}` or `]`;
+/// - raw newlines, tabs and other control characters inside strings;
+/// - output cut off before its closing quotes and brackets;
+/// - `thought` given as a plain string, or with an unknown `thought_type`;
+/// - `action` given as a plain string, with its fields at the top level;
+/// - `skills` given as a single string, or as `skill`.
+///
+/// # Arguments
+/// - `text`: The message content returned by the LLM.
+///
+/// # Returns
+/// The parsed response, or the error from parsing the repaired object.
+pub fn parse(text: &str) -> Result<LLMResponse, String> {
+    if let Ok(response) = serde_json::from_str(text) {
+        return Ok(response);
+    }
+
+    let json = extract_object(text).ok_or("LLM output contains no JSON object")?;
+    let mut value: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("failed to parse LLM JSON output: {}", e))?;
+    normalize(&mut value);
+    serde_json::from_value(value).map_err(|e| format!("invalid LLM response: {}", e))
+}
+
+/// Extracts the first JSON object from `text`, repairing its syntax.
+///
+/// Scans from the first `{` to its matching `}`, escaping control
+/// characters inside strings and dropping trailing commas on the way. If
+/// the text ends first, the open string and brackets are closed.
+///
+/// # Returns
+/// The repaired object text, or `None` if `text` contains no `{`.
+fn extract_object(text: &str) -> Option<String> {
+    let start = text.find('{')?;
+    let mut out = String::with_capacity(text.len() - start);
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in text[start..].chars() {
+        if in_string {
+            match c {
+                _ if escaped => {
+                    escaped = false;
+                    out.push(c);
+                }
+                '\\' => {
+                    escaped = true;
+                    out.push(c);
+                }
+                '"' => {
+                    in_string = false;
+                    out.push(c);
+                }
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                open.push(if c == '{' { '}' } else { ']' });
+                out.push(c);
+            }
+            '}' | ']' => {
+                drop_trailing_comma(&mut out);
+                open.pop();
+                out.push(c);
+                if open.is_empty() {
+                    return Some(out);
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    // The output was cut off: close what is still open
+    if in_string {
+        if escaped {
+            out.pop();
+        }
+        out.push('"');
+    }
+    while let Some(close) = open.pop() {
+        drop_trailing_comma(&mut out);
+        out.push(close);
+    }
+    Some(out)
+}
+
+/// Removes a trailing comma (and the whitespace after it) from `out`.
+fn drop_trailing_comma(out: &mut String) {
+    let trimmed = out.trim_end().len();
+    if out[..trimmed].ends_with(',') {
+        out.truncate(trimmed - 1);
+    }
+}
+
+/// Rewrites near-miss response shapes into the `LLMResponse` layout.
+fn normalize(value: &mut Value) {
+    let Some(fields) = value.as_object_mut() else {
+        return;
+    };
+
+    let thought = fields.entry("thought").or_insert(Value::Null);
+    if !thought.is_object() {
+        let content = thought.as_str().unwrap_or_default().to_string();
+        *thought = serde_json::json!({ "content": content });
+    }
+    let thought_type = &mut thought["thought_type"];
+    if serde_json::from_value::<ThoughtType>(thought_type.clone()).is_err() {
+        *thought_type = Value::from("analysis");
+    }
+
+    if let Some(name) = fields.get("action").and_then(Value::as_str) {
+        let mut action = serde_json::json!({ "action": name });
+        for key in ["skills", "skill", "command", "answer"] {
+            if let Some(field) = fields.remove(key) {
+                action[key] = field;
+            }
+        }
+        fields.insert("action".to_string(), action);
+    }
+    if let Some(action) = fields.get_mut("action").and_then(Value::as_object_mut) {
+        if let Some(skill) = action.remove("skill") {
+            action.entry("skills").or_insert(skill);
+        }
+        if let Some(skills) = action.get_mut("skills")
+            && skills.is_string()
+        {
+            *skills = Value::Array(vec![skills.take()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Action;
+
+    #[test]
+    fn test_parse_with_prose_and_fences() {
+        let text = "Sure! Here is my response:\n```json\n{\"thought\": {\"thought_type\": \"planning\", \"content\": \"Use {braces} and \\\"quotes\\\".\"}, \"action\": {\"action\": \"execute\", \"command\": \"ls\"}}\n```\nLet me know if you need more.";
+        let response = parse(text).unwrap();
+        assert_eq!(response.thought.content, "Use {braces} and \"quotes\".");
+        assert!(matches!(response.action, Action::Execute { command } if command == "ls"));
+    }
+
+    #[test]
+    fn test_extract_object_repairs_syntax() {
+        let json = extract_object("{\"a\": \"line\none\ttab\", \"b\": [1, 2,],}").unwrap();
+        assert_eq!(json, r#"{"a": "line\none\ttab", "b": [1, 2]}"#);
+
+        // Cut off inside a string, after an escape
+        let json = extract_object(r#"{"a": {"b": ["x", "y\"#).unwrap();
+        assert_eq!(json, r#"{"a": {"b": ["x", "y"]}}"#);
+        let json = extract_object(r#"{"a": 1, "#).unwrap();
+        assert_eq!(json, r#"{"a": 1}"#);
+
+        assert!(extract_object("no json here").is_none());
+    }
+
+    #[test]
+    fn test_parse_normalizes_shape() {
+        let response =
+            parse(r#"{"thought": "Load it.", "action": "use_skill", "skill": "git", "extra": 1}"#)
+                .unwrap();
+        assert_eq!(response.thought.content, "Load it.");
+        assert!(matches!(
+            response.thought.thought_type,
+            ThoughtType::Analysis
+        ));
+        assert!(matches!(response.action, Action::UseSkill { skills } if skills == ["git"]));
+
+        let response = parse(
+            r#"{"thought": {"thought_type": "musing", "content": "ok"}, "action": {"action": "completed", "answer": "done"},}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            response.thought.thought_type,
+            ThoughtType::Analysis
+        ));
+        assert!(matches!(response.action, Action::Completed { answer } if answer == "done"));
+
+        let err = parse(r#"{"thought": "hm", "action": {"action": "fly"}}"#).unwrap_err();
+        assert!(err.starts_with("invalid LLM response"), "{}", err);
+    }
+}
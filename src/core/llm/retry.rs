@@ -0,0 +1,298 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+
+use crate::config::RetryConfig;
+use crate::core::guard::is_context_overflow;
+
+/// Share of a backoff delay added at random, so clients that failed
+/// together do not retry together.
+const JITTER: f64 = 0.2;
+
+/// Why an LLM request failed, which decides how the call is retried.
+///
+/// # Variants
+/// - `RateLimit`: 429; the key profile cools down and the next one is tried.
+/// - `Auth`: 401 or 403; the key profile cools down and the next one is tried.
+/// - `Timeout`: 408, or no response data within `read_timeout_secs`; the key
+///   profile cools down and the call is retried after a backoff.
+/// - `Overflow`: The request exceeds the context window; left to `ContextGuard`.
+/// - `Server`: 5xx or a connection failure; retried after a backoff.
+/// - `Request`: Any other error, such as a rejected request body; not retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    RateLimit,
+    Auth,
+    Timeout,
+    Overflow,
+    Server,
+    Request,
+}
+
+impl Failure {
+    /// Classifies an error response of the API.
+    ///
+    /// # Arguments
+    /// - `status`: The HTTP status.
+    /// - `body`: The response body, checked for context overflow messages.
+    pub fn classify(status: StatusCode, body: &str) -> Self {
+        match status.as_u16() {
+            429 => Self::RateLimit,
+            401 | 403 => Self::Auth,
+            408 => Self::Timeout,
+            500.. => Self::Server,
+            _ if is_context_overflow(body) => Self::Overflow,
+            _ => Self::Request,
+        }
+    }
+
+    /// Classifies a request that got no usable response. Only timeouts and
+    /// failures to reach or talk to the server are transient; errors such as
+    /// an invalid request or an undecodable body are not retried.
+    pub fn from_error(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_connect() || error.is_request() {
+            Self::Server
+        } else {
+            Self::Request
+        }
+    }
+
+    /// Returns the name of the failure, as used in error messages.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::Auth => "auth",
+            Self::Timeout => "timeout",
+            Self::Overflow => "overflow",
+            Self::Server => "server",
+            Self::Request => "request",
+        }
+    }
+
+    /// Returns `true` if another LLM may succeed where this one failed
+    /// for good: rate limits, rejected keys, timeouts and server errors.
+    pub fn falls_back(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimit | Self::Auth | Self::Timeout | Self::Server
+        )
+    }
+
+    /// Returns how long a key profile is skipped after this failure, or
+    /// `None` if the failure does not depend on the key.
+    ///
+    /// # Arguments
+    /// - `config`: The configured cooldowns.
+    pub fn cooldown(&self, config: &RetryConfig) -> Option<Duration> {
+        let secs = match self {
+            Self::RateLimit => config.rate_limit_cooldown_secs,
+            Self::Auth => config.auth_cooldown_secs,
+            Self::Timeout => config.timeout_cooldown_secs,
+            Self::Overflow | Self::Server | Self::Request => return None,
+        };
+        Some(Duration::from_secs(secs))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The API keys of one LLM, each a profile that is skipped for a while
+/// after it failed.
+///
+/// # Fields
+/// - `keys`: The key of each profile; a single `None` for an API without authentication.
+/// - `cooling`: Per profile, the time until which it is skipped.
+/// - `current`: The profile used last; calls stay on it while it works.
+#[derive(Debug)]
+pub struct Profiles {
+    keys: Vec<Option<String>>,
+    cooling: Vec<Option<Instant>>,
+    current: usize,
+}
+
+impl Profiles {
+    /// Creates one profile per key, or a single keyless profile if there are none.
+    pub fn new(keys: Vec<String>) -> Self {
+        let keys: Vec<Option<String>> = if keys.is_empty() {
+            vec![None]
+        } else {
+            keys.into_iter().map(Some).collect()
+        };
+        Self {
+            cooling: vec![None; keys.len()],
+            keys,
+            current: 0,
+        }
+    }
+
+    /// Picks the profile for the next attempt: the current one, or else the
+    /// next one in order that is not cooling down. If all are, the one
+    /// whose cooldown ends first.
+    ///
+    /// # Returns
+    /// The index of the profile and its key.
+    pub fn pick(&mut self, now: Instant) -> (usize, Option<String>) {
+        let len = self.keys.len();
+        let index = (0..len)
+            .map(|offset| (self.current + offset) % len)
+            .find(|&i| self.is_available(i, now))
+            .unwrap_or_else(|| {
+                (0..len)
+                    .min_by_key(|&i| self.cooling[i])
+                    .unwrap_or_default()
+            });
+        self.current = index;
+        (index, self.keys[index].clone())
+    }
+
+    /// Skips a profile until `now + duration`.
+    ///
+    /// # Arguments
+    /// - `index`: The profile that failed.
+    /// - `duration`: How long to skip it.
+    /// - `now`: The current time.
+    pub fn cool_down(&mut self, index: usize, duration: Duration, now: Instant) {
+        self.cooling[index] = Some(now + duration);
+    }
+
+    /// Returns `true` if any profile is not cooling down.
+    pub fn any_available(&self, now: Instant) -> bool {
+        (0..self.keys.len()).any(|i| self.is_available(i, now))
+    }
+
+    fn is_available(&self, index: usize, now: Instant) -> bool {
+        self.cooling[index].is_none_or(|until| until <= now)
+    }
+}
+
+/// Returns the delay before a retry: `backoff_ms`, doubled for each
+/// earlier retry, plus up to 20% jitter, and at most `max_backoff_ms`.
+///
+/// # Arguments
+/// - `retry`: The number of the retry, starting at 1.
+/// - `config`: The configured delays.
+pub fn backoff(retry: u32, config: &RetryConfig) -> Duration {
+    let base = config
+        .backoff_ms
+        .saturating_mul(1 << retry.saturating_sub(1).min(20));
+    let jittered = base as f64 * (1.0 + rand::random_range(0.0..=JITTER));
+    Duration::from_millis((jittered as u64).min(config.max_backoff_ms))
+}
+
+/// Reads how long the API asks the client to wait before retrying.
+///
+/// Understands `retry-after-ms`, and `retry-after` as seconds or as an
+/// HTTP date.
+///
+/// # Arguments
+/// - `headers`: The headers of an error response.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(ms / 1000.0).ok();
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::llm::tests::{http_response, serve_responses};
+
+    #[test]
+    fn test_classify() {
+        let classify = |status: u16, body: &str| {
+            Failure::classify(StatusCode::from_u16(status).unwrap(), body)
+        };
+        assert_eq!(
+            classify(429, "too many tokens per minute"),
+            Failure::RateLimit
+        );
+        assert_eq!(classify(401, ""), Failure::Auth);
+        assert_eq!(classify(403, ""), Failure::Auth);
+        assert_eq!(classify(408, ""), Failure::Timeout);
+        assert_eq!(classify(503, ""), Failure::Server);
+        assert_eq!(
+            classify(400, r#"{"error":{"code":"context_length_exceeded"}}"#),
+            Failure::Overflow
+        );
+        assert_eq!(classify(400, "invalid model"), Failure::Request);
+    }
+
+    #[tokio::test]
+    async fn test_from_error() {
+        let http = reqwest::Client::new();
+        let error = http.get("not a url").build().unwrap_err();
+        assert_eq!(Failure::from_error(&error), Failure::Request);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let error = http.get(&url).send().await.unwrap_err();
+        assert_eq!(Failure::from_error(&error), Failure::Server);
+
+        let response = http_response("200 OK", "application/json", "", "not json");
+        let (url, _requests) = serve_responses(vec![response]).await;
+        let response = http.get(&url).send().await.unwrap();
+        let error = response.json::<serde_json::Value>().await.unwrap_err();
+        assert_eq!(Failure::from_error(&error), Failure::Request);
+    }
+
+    #[test]
+    fn test_profiles_rotate_and_cool_down() {
+        let now = Instant::now();
+        let mut profiles = Profiles::new(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(profiles.pick(now), (0, Some("a".to_string())));
+
+        profiles.cool_down(0, Duration::from_secs(120), now);
+        assert_eq!(profiles.pick(now), (1, Some("b".to_string())));
+        // Calls stay on the working profile
+        let later = now + Duration::from_secs(200);
+        assert_eq!(profiles.pick(later).0, 1);
+
+        profiles.cool_down(1, Duration::from_secs(300), now);
+        assert!(!profiles.any_available(now));
+        assert_eq!(profiles.pick(now).0, 0);
+        assert!(profiles.any_available(later));
+
+        let mut keyless = Profiles::new(Vec::new());
+        assert_eq!(keyless.pick(now), (0, None));
+    }
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let config = RetryConfig::default();
+        let first = backoff(1, &config);
+        assert!(first >= Duration::from_millis(1000) && first <= Duration::from_millis(1200));
+        let third = backoff(3, &config);
+        assert!(third >= Duration::from_millis(4000) && third <= Duration::from_millis(4800));
+        assert_eq!(backoff(30, &config), Duration::from_millis(30_000));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert("retry-after-ms", "250".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+
+        let mut headers = HeaderMap::new();
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        headers.insert("retry-after", date.parse().unwrap());
+        let wait = retry_after(&headers).unwrap();
+        assert!(wait > Duration::from_secs(50) && wait <= Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,246 @@
+/// Incremental parser for server-sent events (`text/event-stream`).
+///
+/// Bytes are fed as they arrive; complete events come out as their joined
+/// `data` payloads. Other fields (`event`, `id`, comments) are ignored.
+///
+/// # Fields
+/// - `buf`: Bytes of the current, unterminated line.
+/// - `data`: `data` lines of the event being read.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feeds received bytes into the parser.
+    ///
+    /// # Arguments
+    /// - `bytes`: The next bytes of the response body.
+    ///
+    /// # Returns
+    /// The payloads of all events completed by these bytes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+        }
+        events
+    }
+}
+
+/// Incremental splitter for newline-delimited JSON (`application/x-ndjson`).
+///
+/// # Fields
+/// - `buf`: Bytes of the current, unterminated line.
+#[derive(Debug, Default)]
+pub struct LineParser {
+    buf: Vec<u8>,
+}
+
+impl LineParser {
+    /// Feeds received bytes into the parser.
+    ///
+    /// # Arguments
+    /// - `bytes`: The next bytes of the response body.
+    ///
+    /// # Returns
+    /// All non-empty lines completed by these bytes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    /// Returns the unterminated last line, at the end of the body.
+    pub fn finish(&mut self) -> Vec<String> {
+        let line = String::from_utf8_lossy(&self.buf).trim().to_string();
+        self.buf.clear();
+        if line.is_empty() {
+            Vec::new()
+        } else {
+            vec![line]
+        }
+    }
+}
+
+/// Extracts `thought.content` from a structured response while it streams.
+///
+/// The response JSON arrives in arbitrary fragments. After each fragment,
+/// the partial string value of `thought.content` is decoded as far as it
+/// is complete, and only the text not returned before is handed out.
+///
+/// # Fields
+/// - `json`: The response text received so far.
+/// - `emitted`: Bytes of decoded content already returned.
+/// - `done`: Whether the closing quote of the content was seen.
+#[derive(Debug, Default)]
+pub struct ThoughtStream {
+    json: String,
+    emitted: usize,
+    done: bool,
+}
+
+impl ThoughtStream {
+    /// Appends a fragment of the response.
+    ///
+    /// # Arguments
+    /// - `fragment`: The next piece of the response JSON.
+    ///
+    /// # Returns
+    /// Newly decoded thought text, if any.
+    pub fn push(&mut self, fragment: &str) -> Option<String> {
+        self.json.push_str(fragment);
+        if self.done {
+            return None;
+        }
+
+        let start = self.content_start()?;
+        let (raw, done) = partial_string(&self.json[start..]);
+        let decoded: String = serde_json::from_str(&format!("\"{}\"", raw)).ok()?;
+        self.done = done;
+
+        let text = decoded.get(self.emitted..)?.to_string();
+        self.emitted = decoded.len();
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Returns `true` once any thought text was returned.
+    pub fn started(&self) -> bool {
+        self.emitted > 0
+    }
+
+    /// Returns the byte offset just past the opening quote of
+    /// `thought.content`, once it has arrived.
+    fn content_start(&self) -> Option<usize> {
+        let thought = self.json.find("\"thought\"")?;
+        let key = thought + self.json[thought..].find("\"content\"")? + "\"content\"".len();
+        let rest = self.json[key..].trim_start();
+        let rest = rest.strip_prefix(':')?.trim_start();
+        rest.starts_with('"')
+            .then(|| self.json.len() - rest.len() + 1)
+    }
+}
+
+/// Splits the raw (still escaped) body of a JSON string that may be cut
+/// off anywhere.
+///
+/// # Returns
+/// The body up to the closing quote, or up to the last complete escape
+/// sequence, and whether the closing quote was found.
+fn partial_string(s: &str) -> (&str, bool) {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return (&s[..i], true),
+            b'\\' => {
+                let len = if bytes.get(i + 1) == Some(&b'u') {
+                    6
+                } else {
+                    2
+                };
+                if i + len > bytes.len() {
+                    return (&s[..i], false);
+                }
+                // A high surrogate needs its low half before it can be decoded
+                if len == 6
+                    && matches!(bytes[i + 2], b'd' | b'D')
+                    && matches!(bytes[i + 3], b'8' | b'9' | b'a' | b'b' | b'A' | b'B')
+                    && i + 12 > bytes.len()
+                {
+                    return (&s[..i], false);
+                }
+                i += len;
+            }
+            _ => i += 1,
+        }
+    }
+    (s, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_parser_split_events() {
+        let mut parser = SseParser::default();
+        assert!(parser.feed(b"data: {\"a\"").is_empty());
+        assert_eq!(
+            parser.feed(b":1}\r\n\r\n: keep-alive\n\ndata: [DONE]\n"),
+            ["{\"a\":1}"]
+        );
+        assert_eq!(parser.feed(b"\n"), ["[DONE]"]);
+        assert_eq!(parser.feed(b"event: x\ndata:a\ndata: b\n\n"), ["a\nb"]);
+    }
+
+    #[test]
+    fn test_line_parser() {
+        let mut parser = LineParser::default();
+        assert!(parser.feed(b"{\"a\":").is_empty());
+        assert_eq!(
+            parser.feed(b"1}\n\n{\"b\":2}\r\n{\"c\""),
+            ["{\"a\":1}", "{\"b\":2}"]
+        );
+        assert_eq!(parser.feed(b":3}\n"), ["{\"c\":3}"]);
+        assert!(parser.feed(b"{\"d\":4}").is_empty());
+        assert_eq!(parser.finish(), ["{\"d\":4}"]);
+        assert!(parser.finish().is_empty());
+    }
+
+    #[test]
+    fn test_thought_stream_fragments() {
+        let json = r#"{"thought": {"thought_type": "planning", "content": "Line \"one\"\nnext é 😀 \ud83d\ude00 \\done"}, "action": {"action": "continue"}}"#;
+        let expected = "Line \"one\"\nnext é 😀 😀 \\done";
+
+        // Feed one byte-sized piece at a time, as a worst case
+        let mut stream = ThoughtStream::default();
+        let mut text = String::new();
+        let mut start = 0;
+        for end in (1..=json.len()).filter(|i| json.is_char_boundary(*i)) {
+            if let Some(delta) = stream.push(&json[start..end]) {
+                text.push_str(&delta);
+            }
+            start = end;
+        }
+        assert_eq!(text, expected);
+        assert!(stream.started());
+
+        // Large fragments give the same result
+        let mut stream = ThoughtStream::default();
+        let (a, b) = json.split_at(60);
+        let text = stream.push(a).unwrap_or_default() + &stream.push(b).unwrap();
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn test_thought_stream_ignores_other_content() {
+        let mut stream = ThoughtStream::default();
+        assert_eq!(
+            stream.push(r#"{"thought": {"content": "hi"}, "#),
+            Some("hi".to_string())
+        );
+        assert_eq!(stream.push(r#""action": {"content": "no"}}"#), None);
+    }
+}
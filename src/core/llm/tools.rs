@@ -0,0 +1,307 @@
+use serde_json::Value;
+
+use super::{LLMResponse, Thought, ThoughtType, thought_schema};
+use crate::core::Action;
+use crate::core::context::Message;
+
+/// Names of the tools offered to the LLM, one per action that acts.
+///
+/// `continue` has no tool: a reply without a tool call is a thinking step.
+const TOOLS: [&str; 3] = ["use_skill", "execute", "completed"];
+
+/// Returns the OpenAI `tools` definitions for the agent's actions.
+///
+/// Every tool takes the `thought` alongside its own argument, so the
+/// response carries the same content as with `response_format`.
+pub fn definitions() -> Value {
+    let tool = |name: &str, description: &str, field: &str, schema: Value| {
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": name,
+                "description": description,
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "thought": thought_schema(),
+                        field: schema,
+                    },
+                    "required": ["thought", field],
+                    "additionalProperties": false
+                }
+            }
+        })
+    };
+    serde_json::json!([
+        tool(
+            "use_skill",
+            "Load skills by name for instructions.",
+            "skills",
+            serde_json::json!({ "type": "array", "items": { "type": "string" } }),
+        ),
+        tool(
+            "execute",
+            "Run a shell command.",
+            "command",
+            serde_json::json!({ "type": "string" }),
+        ),
+        tool(
+            "completed",
+            "Return the final answer.",
+            "answer",
+            serde_json::json!({ "type": "string" }),
+        ),
+    ])
+}
+
+/// Reads the agent's response from a chat completion message.
+///
+/// The first tool call becomes the action; further calls are ignored. A
+/// message without tool calls is a `Continue` step whose text is the
+/// thought. A tool call without a `thought` argument falls back to the
+/// message text as well.
+///
+/// # Arguments
+/// - `message`: The `choices[0].message` object of the response.
+///
+/// # Returns
+/// The parsed response, or an error describing the malformed call.
+pub fn parse(message: &Value) -> Result<LLMResponse, String> {
+    let content = message["content"].as_str().unwrap_or_default().trim();
+    let text_thought = || Thought {
+        thought_type: ThoughtType::Analysis,
+        content: content.to_string(),
+    };
+
+    let Some(call) = message["tool_calls"].get(0) else {
+        if content.is_empty() {
+            return Err("LLM response has neither content nor tool calls".to_string());
+        }
+        return Ok(LLMResponse {
+            thought: text_thought(),
+            action: Action::Continue,
+        });
+    };
+
+    let name = call["function"]["name"]
+        .as_str()
+        .ok_or("LLM tool call missing function name")?;
+    if !TOOLS.contains(&name) {
+        return Err(format!("LLM called unknown tool '{}'", name));
+    }
+    // Some servers send the arguments as an object instead of a JSON string
+    let mut args = match &call["function"]["arguments"] {
+        Value::String(raw) => serde_json::from_str(raw)
+            .map_err(|e| format!("failed to parse arguments of tool '{}': {}", name, e))?,
+        args => args.clone(),
+    };
+    let fields = args
+        .as_object_mut()
+        .ok_or(format!("arguments of tool '{}' are not an object", name))?;
+
+    let thought = match fields.remove("thought") {
+        Some(thought) => serde_json::from_value(thought)
+            .map_err(|e| format!("invalid thought in tool '{}': {}", name, e))?,
+        None => text_thought(),
+    };
+    fields.insert("action".to_string(), Value::String(name.to_string()));
+    let action = serde_json::from_value(args)
+        .map_err(|e| format!("invalid arguments for tool '{}': {}", name, e))?;
+
+    Ok(LLMResponse { thought, action })
+}
+
+/// Converts conversation history into tool-calling messages.
+///
+/// Assistant steps that act become `tool_calls`, with ids derived from
+/// their position so they stay stable between calls. The observations that
+/// follow a call are joined into its `tool` message; the API requires a
+/// result for every call, so one without observations (e.g. `completed`)
+/// gets an empty one. Everything else is exported as with `response_format`.
+///
+/// # Arguments
+/// - `messages`: The conversation history.
+pub fn history(messages: &[Message]) -> Vec<Value> {
+    let mut out = Vec::with_capacity(messages.len());
+    let mut open: Option<(String, Vec<String>)> = None;
+
+    for (i, message) in messages.iter().enumerate() {
+        let json = message.to_json();
+        if let (Message::Observation { .. }, Some((_, results))) = (message, &mut open) {
+            results.push(json["content"].as_str().unwrap_or_default().to_string());
+            continue;
+        }
+        if let Some((id, results)) = open.take() {
+            out.push(result(id, results));
+        }
+
+        match message {
+            Message::Assistant { raw, .. } => match call(raw) {
+                Some((name, args)) => {
+                    let id = format!("call_{}", i);
+                    out.push(serde_json::json!({
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "id": id,
+                            "type": "function",
+                            "function": { "name": name, "arguments": args.to_string() },
+                        }],
+                    }));
+                    open = Some((id, Vec::new()));
+                }
+                None => out.push(serde_json::json!({
+                    "role": "assistant",
+                    "content": raw.thought.content,
+                })),
+            },
+            _ => out.push(json),
+        }
+    }
+    if let Some((id, results)) = open {
+        out.push(result(id, results));
+    }
+    out
+}
+
+/// Returns the tool name and arguments for a response, or `None` for a
+/// `Continue` step.
+pub fn call(response: &LLMResponse) -> Option<(String, Value)> {
+    let mut args = serde_json::to_value(&response.action).ok()?;
+    let name = args.as_object_mut()?.remove("action")?;
+    let name = name.as_str().filter(|name| TOOLS.contains(name))?;
+    args["thought"] = serde_json::to_value(&response.thought).ok()?;
+    Some((name.to_string(), args))
+}
+
+/// Builds the `tool` message answering the call `id`.
+fn result(id: String, results: Vec<String>) -> Value {
+    serde_json::json!({
+        "role": "tool",
+        "tool_call_id": id,
+        "content": results.join("\n"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::context::Outcome;
+
+    fn tool_call(name: &str, arguments: Value) -> Value {
+        serde_json::json!({
+            "content": null,
+            "tool_calls": [{
+                "id": "call_abc",
+                "type": "function",
+                "function": { "name": name, "arguments": arguments },
+            }],
+        })
+    }
+
+    #[test]
+    fn test_parse_tool_call() {
+        let args = r#"{"thought": {"thought_type": "planning", "content": "Look around."}, "command": "ls"}"#;
+        let response = parse(&tool_call("execute", Value::from(args))).unwrap();
+        assert_eq!(response.thought.content, "Look around.");
+        assert!(matches!(response.action, Action::Execute { command } if command == "ls"));
+
+        // Arguments as an object, thought taken from the message text
+        let mut message = tool_call("use_skill", serde_json::json!({ "skills": ["git"] }));
+        message["content"] = Value::from("Need git help.");
+        let response = parse(&message).unwrap();
+        assert_eq!(response.thought.content, "Need git help.");
+        assert!(matches!(response.action, Action::UseSkill { skills } if skills == ["git"]));
+    }
+
+    #[test]
+    fn test_parse_text_and_errors() {
+        let response = parse(&serde_json::json!({ "content": "Hmm, let me think." })).unwrap();
+        assert_eq!(response.thought.content, "Hmm, let me think.");
+        assert!(matches!(response.action, Action::Continue));
+
+        assert!(parse(&serde_json::json!({ "content": "" })).is_err());
+        let err = parse(&tool_call("continue", serde_json::json!({}))).unwrap_err();
+        assert!(err.contains("unknown tool 'continue'"), "{}", err);
+        let err = parse(&tool_call("execute", Value::from("{\"command\":"))).unwrap_err();
+        assert!(
+            err.starts_with("failed to parse arguments of tool 'execute'"),
+            "{}",
+            err
+        );
+        let err = parse(&tool_call("completed", serde_json::json!({}))).unwrap_err();
+        assert!(
+            err.starts_with("invalid arguments for tool 'completed'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let assistant = |action: Action| {
+            let thought = Thought {
+                thought_type: ThoughtType::Planning,
+                content: "thinking".to_string(),
+            };
+            Message::Assistant {
+                thought: thought.clone(),
+                raw: LLMResponse { thought, action },
+            }
+        };
+        let observation = |skill: &str| Message::Observation {
+            skill: skill.to_string(),
+            outcome: Outcome::Success {
+                output: "body".to_string(),
+            },
+            content: "body".to_string(),
+        };
+        let messages = vec![
+            Message::User {
+                content: "hi".to_string(),
+            },
+            assistant(Action::Continue),
+            assistant(Action::UseSkill {
+                skills: vec!["a".to_string(), "b".to_string()],
+            }),
+            observation("a"),
+            observation("b"),
+            assistant(Action::Completed {
+                answer: "done".to_string(),
+            }),
+            Message::User {
+                content: "again".to_string(),
+            },
+        ];
+
+        let out = history(&messages);
+        let roles: Vec<&str> = out.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(
+            roles,
+            [
+                "user",
+                "assistant",
+                "assistant",
+                "tool",
+                "assistant",
+                "tool",
+                "user"
+            ]
+        );
+        assert_eq!(out[1]["content"], "thinking");
+
+        let call = &out[2]["tool_calls"][0];
+        assert_eq!(call["id"], "call_2");
+        assert_eq!(call["function"]["name"], "use_skill");
+        let args: Value =
+            serde_json::from_str(call["function"]["arguments"].as_str().unwrap()).unwrap();
+        assert_eq!(args["skills"], serde_json::json!(["a", "b"]));
+        assert_eq!(args["thought"]["content"], "thinking");
+        assert!(args.get("action").is_none());
+
+        assert_eq!(out[3]["tool_call_id"], "call_2");
+        assert_eq!(out[3]["content"].as_str().unwrap().lines().count(), 2);
+        assert_eq!(out[5]["tool_call_id"], "call_5");
+        assert_eq!(out[5]["content"], "");
+    }
+}
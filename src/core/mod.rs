@@ -1,7 +1,10 @@
 pub mod agent;
 pub mod context;
+pub mod fault;
+pub mod guard;
 pub mod harness;
 pub mod llm;
+pub mod policy;
 pub mod prompt;
 
 use async_trait::async_trait;
@@ -10,6 +13,12 @@ use serde_json::Value;
 
 use crate::core::context::Context;
 
+/// Error and answer text used when the user cancels a turn.
+pub const INTERRUPTED: &str = "interrupted by user";
+
+/// Answer returned when the agent runs out of steps.
+pub const MAX_STEPS: &str = "max steps reached";
+
 /// Control flow action signal used throughout the pipeline.
 ///
 /// Returned by `Node::post()` to determine the next step in the agent loop.
@@ -0,0 +1,555 @@
+use std::path::{Component, Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+use crate::config::{Decision, PolicyConfig, PolicyRule};
+
+/// Programs that run their arguments as another command. Segments starting
+/// with one of these are also checked with the wrapper stripped.
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "nice", "time", "timeout", "xargs", "exec", "command",
+];
+
+/// Shells whose `-c` argument is parsed as a nested command line.
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh"];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// The user's answer to an `ask` verdict.
+///
+/// # Variants
+/// - `Approve`: Run the command once.
+/// - `Deny`: Do not run the command.
+/// - `AlwaysAllow`: Run the command and stop asking for it in this session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    Approve,
+    Deny,
+    AlwaysAllow,
+}
+
+/// Result of checking a command against the policy.
+///
+/// # Fields
+/// - `decision`: The most restrictive decision over all simple commands.
+/// - `reason`: Why the decision was made, for display to the user and LLM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub decision: Decision,
+    pub reason: String,
+}
+
+/// A `PolicyRule` with its patterns compiled.
+struct Rule {
+    action: Decision,
+    words: Vec<Pattern>,
+    pattern: Option<Regex>,
+    paths: Vec<Pattern>,
+    reason: String,
+}
+
+impl Rule {
+    /// Compiles a rule from config.
+    ///
+    /// # Arguments
+    /// - `index`: Position of the rule, used in the default reason.
+    /// - `rule`: The configured rule.
+    fn new(index: usize, rule: &PolicyRule) -> Result<Self, String> {
+        let glob = |p: &String| {
+            Pattern::new(&expand_home(p))
+                .map_err(|e| format!("policy rule {}: invalid pattern '{}': {}", index, p, e))
+        };
+        let pattern = rule
+            .pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("policy rule {}: invalid regex: {}", index, e))?;
+
+        Ok(Self {
+            action: rule.action,
+            words: rule.words.iter().map(glob).collect::<Result<_, _>>()?,
+            pattern,
+            paths: rule.paths.iter().map(glob).collect::<Result<_, _>>()?,
+            reason: rule
+                .reason
+                .clone()
+                .unwrap_or_else(|| format!("policy rule {}", index)),
+        })
+    }
+
+    /// Returns `true` if every condition set on the rule matches the segment.
+    ///
+    /// # Arguments
+    /// - `words`: Words of one simple command.
+    /// - `cwd`: Directory relative paths are resolved against.
+    fn matches(&self, words: &[String], cwd: &Path) -> bool {
+        if words.len() < self.words.len() {
+            return false;
+        }
+        let positional = self.words.iter().zip(words).enumerate().all(|(i, (p, w))| {
+            let word = if i == 0 { program_name(w) } else { w.as_str() };
+            p.matches_with(word, MATCH_OPTIONS)
+        });
+        if !positional {
+            return false;
+        }
+
+        if let Some(re) = &self.pattern
+            && !re.is_match(&words.join(" "))
+        {
+            return false;
+        }
+
+        if !self.paths.is_empty() {
+            let mut args = words[1..].iter().filter(|w| !w.starts_with('-'));
+            let hit = args.any(|arg| {
+                let path = resolve(arg, cwd);
+                let path = path.to_string_lossy();
+                self.paths
+                    .iter()
+                    .any(|p| p.matches_with(&path, MATCH_OPTIONS))
+            });
+            if !hit {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Decides whether a shell command may run.
+///
+/// Splits the command line into simple commands (across `;`, `&&`, `||`,
+/// pipes, subshells, `$(...)`, backticks and `sh -c`), then matches each
+/// against the ordered rules. The first matching rule decides for that
+/// simple command, falling back to the default. The most restrictive
+/// decision wins: deny over ask over allow.
+///
+/// # Fields
+/// - `default`: Decision for simple commands no rule matches.
+/// - `rules`: Compiled rules in config order.
+pub struct Policy {
+    default: Decision,
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Compiles the policy from config.
+    ///
+    /// # Arguments
+    /// - `config`: The default decision and ordered rules.
+    ///
+    /// # Returns
+    /// The policy, or an error naming the first invalid pattern.
+    pub fn new(config: &PolicyConfig) -> Result<Self, String> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| Rule::new(i, r))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            default: config.default,
+            rules,
+        })
+    }
+
+    /// Checks a command line against the policy.
+    ///
+    /// # Arguments
+    /// - `command`: The full shell command line.
+    /// - `cwd`: Working directory the command would run in.
+    pub fn check(&self, command: &str, cwd: &Path) -> Verdict {
+        let mut verdict = Verdict {
+            decision: Decision::Allow,
+            reason: "allowed".to_string(),
+        };
+
+        for words in segments(command) {
+            let (decision, reason) = self
+                .rules
+                .iter()
+                .find(|r| r.matches(&words, cwd))
+                .map_or((self.default, "default policy"), |r| {
+                    (r.action, r.reason.as_str())
+                });
+            if decision > verdict.decision {
+                verdict = Verdict {
+                    decision,
+                    reason: format!("{} ({})", reason, words.join(" ")),
+                };
+            }
+        }
+
+        verdict
+    }
+}
+
+/// Splits a command line into simple commands, each a list of words.
+///
+/// Leading `NAME=value` assignments are dropped. Segments that start with
+/// a wrapper (e.g. `sudo`) are returned both as-is and unwrapped.
+fn segments(command: &str) -> Vec<Vec<String>> {
+    let mut out = Vec::new();
+    for words in Tokenizer::new(command).run() {
+        push_segment(&mut out, words);
+    }
+    out
+}
+
+/// Adds a segment and the segments nested in it (wrappers, `sh -c`, `eval`).
+fn push_segment(out: &mut Vec<Vec<String>>, mut words: Vec<String>) {
+    let assignments = words.iter().take_while(|w| is_assignment(w)).count();
+    words.drain(..assignments);
+    let Some(first) = words.first() else {
+        return;
+    };
+    let program = program_name(first).to_lowercase();
+
+    if WRAPPERS.contains(&program.as_str()) {
+        let inner: Vec<String> = words[1..]
+            .iter()
+            .skip_while(|w| w.starts_with('-') || is_assignment(w) || is_number(w))
+            .cloned()
+            .collect();
+        push_segment(out, inner);
+    }
+    if let Some(shell) = words
+        .iter()
+        .position(|w| SHELLS.contains(&program_name(w).to_lowercase().as_str()))
+        && let Some(flag) = words[shell..].iter().position(|w| w == "-c")
+        && let Some(script) = words.get(shell + flag + 1)
+    {
+        out.extend(segments(script));
+    }
+    if program == "eval" {
+        out.extend(segments(&words[1..].join(" ")));
+    }
+
+    out.push(words);
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+fn is_number(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// Returns the file name of a program path (`/bin/rm` → `rm`).
+fn program_name(word: &str) -> &str {
+    word.rsplit('/').next().unwrap_or(word)
+}
+
+/// Expands a leading `~` to the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home.display(), rest)
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Resolves an argument to an absolute, lexically normalized path.
+fn resolve(arg: &str, cwd: &Path) -> PathBuf {
+    let path = cwd.join(expand_home(arg));
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Minimal POSIX shell tokenizer for policy checks.
+///
+/// Understands quoting, escapes, comments, control operators, redirections,
+/// subshells, `$(...)` and backticks. Command substitutions are emitted as
+/// separate segments. It does not expand variables or globs.
+struct Tokenizer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    segments: Vec<Vec<String>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(command: &'a str) -> Self {
+        Self {
+            chars: command.chars().peekable(),
+            segments: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Vec<String>> {
+        self.list(false);
+        self.segments
+    }
+
+    /// Reads segments until the end of input, or a closing `)` when `nested`.
+    fn list(&mut self, nested: bool) {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut in_word = false;
+
+        while let Some(c) = self.chars.next() {
+            match c {
+                ')' if nested => break,
+                ' ' | '\t' => end_word(&mut words, &mut word, &mut in_word),
+                ';' | '\n' | '&' | '|' | '(' | ')' => {
+                    end_word(&mut words, &mut word, &mut in_word);
+                    self.end_segment(&mut words);
+                }
+                '<' | '>' => {
+                    end_word(&mut words, &mut word, &mut in_word);
+                    while matches!(self.chars.peek(), Some('>' | '&' | '|')) {
+                        self.chars.next();
+                    }
+                }
+                '#' if !in_word => while self.chars.next_if(|c| *c != '\n').is_some() {},
+                '\'' => {
+                    in_word = true;
+                    while let Some(c) = self.chars.next_if(|c| *c != '\'') {
+                        word.push(c);
+                    }
+                    self.chars.next();
+                }
+                '"' => {
+                    in_word = true;
+                    self.double_quoted(&mut word);
+                }
+                '\\' => {
+                    in_word = true;
+                    if let Some(c) = self.chars.next() {
+                        word.push(c);
+                    }
+                }
+                '$' if self.chars.peek() == Some(&'(') => {
+                    in_word = true;
+                    self.chars.next();
+                    self.list(true);
+                }
+                '`' => {
+                    in_word = true;
+                    self.backticks();
+                }
+                c => {
+                    in_word = true;
+                    word.push(c);
+                }
+            }
+        }
+
+        end_word(&mut words, &mut word, &mut in_word);
+        self.end_segment(&mut words);
+    }
+
+    /// Reads a double-quoted string, extracting command substitutions.
+    fn double_quoted(&mut self, word: &mut String) {
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' => return,
+                '\\' => {
+                    if let Some(c) = self.chars.next() {
+                        word.push(c);
+                    }
+                }
+                '$' if self.chars.peek() == Some(&'(') => {
+                    self.chars.next();
+                    self.list(true);
+                }
+                '`' => self.backticks(),
+                c => word.push(c),
+            }
+        }
+    }
+
+    /// Reads a backtick substitution and tokenizes it as a nested command line.
+    fn backticks(&mut self) {
+        let mut inner = String::new();
+        while let Some(c) = self.chars.next_if(|c| *c != '`') {
+            inner.push(c);
+        }
+        self.chars.next();
+        self.segments.extend(Tokenizer::new(&inner).run());
+    }
+
+    fn end_segment(&mut self, words: &mut Vec<String>) {
+        if !words.is_empty() {
+            self.segments.push(std::mem::take(words));
+        }
+    }
+}
+
+fn end_word(words: &mut Vec<String>, word: &mut String, in_word: &mut bool) {
+    if *in_word {
+        words.push(std::mem::take(word));
+        *in_word = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_policy() -> Policy {
+        Policy::new(&PolicyConfig::default()).unwrap()
+    }
+
+    fn decide(policy: &Policy, command: &str) -> Decision {
+        policy
+            .check(command, Path::new("/home/user/project"))
+            .decision
+    }
+
+    #[test]
+    fn test_segments_split_operators() {
+        let segs = segments("cd src && FOO=1 cargo test | tee log; echo \"a b\" 'c'");
+        assert_eq!(
+            segs,
+            vec![
+                vec!["cd", "src"],
+                vec!["cargo", "test"],
+                vec!["tee", "log"],
+                vec!["echo", "a b", "c"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_segments_nested_commands() {
+        let segs =
+            segments("echo $(rm -rf /) `mkfs /dev/sda` && sudo -u root sh -c 'dd of=/dev/sda'");
+        assert!(segs.contains(&vec!["rm".to_string(), "-rf".to_string(), "/".to_string()]));
+        assert!(segs.contains(&vec!["mkfs".to_string(), "/dev/sda".to_string()]));
+        assert!(segs.contains(&vec!["dd".to_string(), "of=/dev/sda".to_string()]));
+    }
+
+    #[test]
+    fn test_segments_redirections() {
+        let segs = segments("cat x 2>&1 > /dev/sda");
+        assert_eq!(segs, vec![vec!["cat", "x", "2", "1", "/dev/sda"]]);
+    }
+
+    #[test]
+    fn test_default_policy_denies_destructive_commands() {
+        let policy = default_policy();
+        assert_eq!(decide(&policy, "rm -rf /"), Decision::Deny);
+        assert_eq!(decide(&policy, "RM -RF /"), Decision::Deny);
+        assert_eq!(decide(&policy, "rm -rf ~"), Decision::Deny);
+        assert_eq!(decide(&policy, "/bin/rm -r ../../.."), Decision::Deny);
+        assert_eq!(decide(&policy, "mkfs.ext4 /dev/sda1"), Decision::Deny);
+        assert_eq!(
+            decide(&policy, "dd if=/dev/zero of=/dev/sda"),
+            Decision::Deny
+        );
+        assert_eq!(decide(&policy, "echo x > /dev/sda"), Decision::Deny);
+        assert_eq!(
+            decide(&policy, "bash -c \"echo $(rm -rf /)\""),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn test_default_policy_asks_for_risky_commands() {
+        let policy = default_policy();
+        assert_eq!(decide(&policy, "rm -rf ./build"), Decision::Ask);
+        assert_eq!(decide(&policy, "rm --recursive ./build"), Decision::Ask);
+        assert_eq!(decide(&policy, "sudo apt install jq"), Decision::Ask);
+        assert_eq!(decide(&policy, "git push origin main"), Decision::Ask);
+        assert_eq!(decide(&policy, "git reset --hard HEAD~1"), Decision::Ask);
+    }
+
+    #[test]
+    fn test_default_policy_allows_safe_commands() {
+        let policy = default_policy();
+        assert_eq!(decide(&policy, "ls -la"), Decision::Allow);
+        assert_eq!(
+            decide(&policy, "echo hello && cat file.txt"),
+            Decision::Allow
+        );
+        assert_eq!(decide(&policy, "rm file.txt"), Decision::Allow);
+        assert_eq!(decide(&policy, "git status"), Decision::Allow);
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let config = PolicyConfig {
+            default: Decision::Ask,
+            rules: vec![
+                PolicyRule {
+                    action: Decision::Allow,
+                    words: vec!["git".to_string(), "status".to_string()],
+                    ..PolicyRule::default()
+                },
+                PolicyRule {
+                    action: Decision::Deny,
+                    words: vec!["git".to_string()],
+                    reason: Some("no git".to_string()),
+                    ..PolicyRule::default()
+                },
+            ],
+        };
+        let policy = Policy::new(&config).unwrap();
+        assert_eq!(decide(&policy, "git status"), Decision::Allow);
+        assert_eq!(decide(&policy, "cargo build"), Decision::Ask);
+
+        let verdict = policy.check("git status && git log", Path::new("/"));
+        assert_eq!(verdict.decision, Decision::Deny);
+        assert_eq!(verdict.reason, "no git (git log)");
+    }
+
+    #[test]
+    fn test_path_rules_resolve_relative_paths() {
+        let config = PolicyConfig {
+            default: Decision::Allow,
+            rules: vec![PolicyRule {
+                action: Decision::Deny,
+                paths: vec!["/etc/**".to_string()],
+                ..PolicyRule::default()
+            }],
+        };
+        let policy = Policy::new(&config).unwrap();
+        let cwd = Path::new("/etc/ssh");
+        assert_eq!(
+            policy.check("cat sshd_config", cwd).decision,
+            Decision::Deny
+        );
+        assert_eq!(
+            policy.check("cat ../../tmp/x", cwd).decision,
+            Decision::Allow
+        );
+    }
+
+    #[test]
+    fn test_invalid_rule() {
+        let config = PolicyConfig {
+            default: Decision::Allow,
+            rules: vec![PolicyRule {
+                action: Decision::Deny,
+                pattern: Some("(".to_string()),
+                ..PolicyRule::default()
+            }],
+        };
+        assert!(Policy::new(&config).is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use serde_json::Value;
 
+use crate::config::Protocol;
 use crate::core::context::{Context, Outcome};
+use crate::core::llm::{response_schema, tools};
 use crate::skill::{load_body, SkillMeta};
 
 const BASE_PROMPT: &str = "\
@@ -15,6 +17,17 @@ Respond in JSON with `thought` and `action` fields.
 - `continue`: Keep thinking without acting.
 - `completed`: Return the final answer. `{\"action\": \"completed\", \"answer\": \"...\"}`";
 
+const TOOLS_PROMPT: &str = "\
+You are a ReAct agent. Think step-by-step, then choose an action.
+
+## Tools
+Call one tool per step, passing your `thought` along with its arguments.
+- `use_skill`: Load skills by name for instructions.
+- `execute`: Run a shell command.
+- `completed`: Return the final answer.
+
+To keep thinking without acting, reply with text only.";
+
 /// Builds LLM API messages from a `Context` and loads skill instructions.
 ///
 /// Owns the system prompt and combines it with the skill catalog and
@@ -23,8 +36,10 @@ Respond in JSON with `thought` and `action` fields.
 ///
 /// # Fields
 /// - `system_prompt`: User-provided system instructions appended to the base prompt.
+/// - `protocol`: How the LLM is asked to respond; selects the base prompt and history format.
 pub struct PromptEngine {
     system_prompt: String,
+    protocol: Protocol,
 }
 
 impl PromptEngine {
@@ -33,13 +48,26 @@ impl PromptEngine {
     /// # Arguments
     /// - `system_prompt`: Additional system instructions beyond the base prompt.
     pub fn new(system_prompt: String) -> Self {
-        Self { system_prompt }
+        Self {
+            system_prompt,
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// Sets the response protocol the prompt is built for.
+    ///
+    /// # Arguments
+    /// - `protocol`: The protocol of the LLM the messages are sent to.
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
     }
 
     /// Builds the complete message list for the LLM API from context.
     ///
     /// Assembles the system message (base prompt + user instructions + skill
-    /// catalog) followed by the conversation messages.
+    /// catalog) followed by the conversation messages. With the `tools`
+    /// protocol, acting steps are exported as tool calls and their results.
     ///
     /// # Arguments
     /// - `ctx`: The conversation context to read from.
@@ -54,11 +82,22 @@ impl PromptEngine {
             "role": "system",
             "content": system,
         }));
-        out.extend(ctx.messages().iter().map(|m| m.to_json()));
+        match self.protocol {
+            Protocol::Tools => out.extend(tools::history(ctx.messages())),
+            _ => out.extend(ctx.messages().iter().map(|m| m.to_json())),
+        }
 
         out
     }
 
+    /// Returns the assembled system prompt for the given context.
+    ///
+    /// # Arguments
+    /// - `ctx`: The conversation context providing the skill catalog.
+    pub fn system_prompt(&self, ctx: &Context) -> String {
+        self.build_system_prompt(ctx.skills())
+    }
+
     /// Loads a skill's instruction body into the context.
     ///
     /// Looks up the skill in the context, reads its SKILL.md body,
@@ -89,7 +128,21 @@ impl PromptEngine {
     /// # Arguments
     /// - `skills`: Available skill metadata from context.
     fn build_system_prompt(&self, skills: &[SkillMeta]) -> String {
-        let mut prompt = BASE_PROMPT.to_string();
+        let mut prompt = match self.protocol {
+            Protocol::Tools => TOOLS_PROMPT,
+            _ => BASE_PROMPT,
+        }
+        .to_string();
+
+        // Without `json_schema`, the model only knows the schema from here
+        if matches!(self.protocol, Protocol::JsonObject | Protocol::Text) {
+            let schema = serde_json::to_string_pretty(&response_schema()).unwrap_or_default();
+            prompt.push_str(
+                "\n\n## Response Schema\n\
+                 Reply with a single JSON object matching this schema, without markdown fences or other text:\n",
+            );
+            prompt.push_str(&schema);
+        }
 
         if !self.system_prompt.is_empty() {
             prompt.push_str("\n\n## User Instructions\n");
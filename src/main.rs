@@ -1,23 +1,59 @@
 use minusagent::config::Config;
+use minusagent::transport::args::{self, Command};
 use minusagent::transport::cli::Cli;
+use minusagent::transport::run::{self, EXIT_FAILED, EXIT_USAGE};
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
+
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args::parse(&argv) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n", e);
+            eprintln!("{}", args::USAGE);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    if command == Command::Help {
+        println!("{}", args::USAGE);
+        return;
+    }
+
     let config = match Config::load() {
         Ok(c) => c,
         Err(_) => {
-            println!("No config found. Creating default at ~/.minusagent/config.json");
+            // Keep stdout for the answer in one-shot mode
+            let note = "No config found. Creating default at ~/.minusagent/config.json";
+            match command {
+                Command::Run(_) => eprintln!("{}", note),
+                _ => println!("{}", note),
+            }
             match Config::init() {
                 Ok(c) => c,
                 Err(e) => {
                     eprintln!("Failed to create config: {}", e);
-                    std::process::exit(1);
+                    std::process::exit(EXIT_FAILED);
                 }
             }
         }
     };
 
-    let mut cli = Cli::new(config);
-    cli.run().await;
+    match command {
+        Command::Run(run_args) => {
+            let cancel = run::interrupt_on_ctrl_c();
+            let (mut out, mut err) = (std::io::stdout(), std::io::stderr());
+            let code = run::run(config, run_args, &cancel, &mut out, &mut err).await;
+            std::process::exit(code);
+        }
+        Command::Repl { resume } => {
+            let mut cli = Cli::new(config);
+            if let Some(id) = resume {
+                cli.resume(id.as_deref());
+            }
+            cli.run().await;
+        }
+        Command::Help => {}
+    }
 }
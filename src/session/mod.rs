@@ -1,24 +1,78 @@
-use crate::config::Config;
+pub mod store;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Config, Decision, LLMConfig};
 use crate::core::agent::Agent;
 use crate::core::context::{Context, Message, Outcome};
-use crate::core::harness::Harness;
+use crate::core::fault::Faults;
+use crate::core::guard::ContextGuard;
+use crate::core::harness::{Harness, OutputChunk};
 use crate::core::llm::LLMClient;
+use crate::core::llm::cassette::Cassette;
+use crate::core::policy::{Approval, Policy};
 use crate::core::prompt::PromptEngine;
-use crate::core::{Action, Node};
+use crate::core::{Action, INTERRUPTED, MAX_STEPS, Node};
 use crate::skill::{SkillMeta, SkillRegistry};
+use store::{Entry, Parent, SessionStore};
 
 /// Progress events emitted during a session turn.
 ///
 /// Used by transports to display intermediate agent activity.
 ///
 /// # Variants
+/// - `ThinkingDelta`: More of a thought streamed from the LLM; `"\n"` ends
+///   each thought.
 /// - `Thinking`: Agent produced a chain-of-thought reasoning step.
 /// - `Executing`: Agent chose to run a shell command.
-/// - `Output`: Command produced output (content, success flag).
+/// - `OutputChunk`: A running command printed more output.
+/// - `Output`: Command finished; carries the observation content and success flag.
+/// - `Compacted`: History was proactively compacted (number of messages replaced).
+/// - `Degraded`: The active LLM failed and the named fallback LLM took over;
+///   carries the fallback's name and the error.
+/// - `Restored`: The named active LLM took over again from a fallback.
+/// - `Warning`: A non-fatal problem the user should know about (e.g. log write failed).
 pub enum Event {
+    ThinkingDelta(String),
     Thinking(String),
     Executing(String),
+    OutputChunk(OutputChunk),
     Output(String, bool),
+    Compacted(usize),
+    Degraded(String, String),
+    Restored(String),
+    Warning(String),
+}
+
+/// How a turn ended, for transports that report it (see `Session::ending`).
+///
+/// # Variants
+/// - `Answered`: The agent completed with an answer.
+/// - `MaxSteps`: The agent ran out of steps.
+/// - `Interrupted`: The turn was cancelled.
+/// - `Failed`: The turn stopped on an error, e.g. the LLM failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ending {
+    Answered,
+    MaxSteps,
+    Interrupted,
+    Failed,
+}
+
+/// The fallback LLM a session switched to.
+///
+/// # Fields
+/// - `position`: Index of the LLM in `fallback.llms`.
+/// - `since`: When the session switched to it.
+struct Degraded {
+    position: usize,
+    since: Instant,
 }
 
 /// Top-level orchestrator for a multi-turn conversation.
@@ -26,16 +80,44 @@ pub enum Event {
 /// Session owns the context, agent, and harness. Each call to `turn()`
 /// processes one user message: drives the agent loop and dispatches
 /// `Execute` actions to the harness until the agent completes.
-/// Context persists across turns for the lifetime of the session.
+/// Context persists across turns for the lifetime of the session. Once
+/// saved (or with `sessions.autosave`), every change to the history is
+/// appended to the session's JSONL log.
 ///
 /// # Fields
+/// - `id`: Unique session id (UUID v4), also the log file name.
+/// - `created_at`: When the session was created.
+/// - `parent`: The session this one was forked from, if any.
+/// - `config`: Configuration snapshot recorded in the log header.
 /// - `context`: The conversation message history (persists across turns).
 /// - `agent`: The ReAct agent that owns the reasoning loop.
+/// - `degraded`: The fallback LLM the agent runs on, if the active one failed.
 /// - `harness`: The command execution environment.
+/// - `faults`: Synthetic failures injected into the LLM and harness (see `faults()`).
+/// - `backend`: Harness backend chosen for this session, overriding the config.
+/// - `policy`: Decides whether a command may run, must be approved, or is denied.
+/// - `approved`: Commands the user chose to always allow in this session.
+/// - `store`: The session log directory.
+/// - `saved`: Whether the log has been created and is being appended to.
+/// - `persisted`: Number of messages already written to the log.
+/// - `revision`: Context revision at the last write.
 pub struct Session {
+    id: String,
+    created_at: DateTime<Utc>,
+    parent: Option<Parent>,
+    config: Config,
     context: Context,
     agent: Agent,
+    degraded: Option<Degraded>,
     harness: Harness,
+    faults: Faults,
+    backend: Option<String>,
+    policy: Policy,
+    approved: HashSet<String>,
+    store: SessionStore,
+    saved: bool,
+    persisted: usize,
+    revision: u64,
 }
 
 impl Session {
@@ -49,38 +131,255 @@ impl Session {
     /// # Returns
     /// A configured `Session` or an error string.
     pub fn new(config: &Config) -> Result<Self, String> {
-        let llm_config = config
-            .llm
-            .first()
-            .ok_or("no LLM configured")?
-            .clone();
-
         let registry = SkillRegistry::new(&config.skills.paths)?;
-
-        let prompt_engine = PromptEngine::new(String::new());
-        let llm = LLMClient::new(llm_config, prompt_engine)?;
-        let agent = Agent::new(llm, config.agent.max_steps);
+        let agent = build_agent(config)?;
 
         let mut context = Context::new();
         context.set_skills(registry.skills());
 
         Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            parent: None,
+            config: config.clone(),
+            revision: context.revision(),
             context,
             agent,
-            harness: Harness::new(),
+            degraded: None,
+            harness: build_harness(config, None)?,
+            faults: Faults::default(),
+            backend: None,
+            policy: Policy::new(&config.policy)?,
+            approved: HashSet::new(),
+            store: SessionStore::new(config.sessions.dir.as_deref()),
+            saved: false,
+            persisted: 0,
         })
     }
 
+    /// Restores a saved session from its JSONL log.
+    ///
+    /// The history is rebuilt by replaying the log; the agent uses the
+    /// active LLM from the current config. New changes keep appending to
+    /// the same log.
+    ///
+    /// # Arguments
+    /// - `config`: The application configuration.
+    /// - `id`: The session id or an unambiguous prefix of it.
+    ///
+    /// # Returns
+    /// The restored session and the name of the last LLM recorded in the log.
+    pub fn load(config: &Config, id: &str) -> Result<(Self, Option<String>), String> {
+        let mut session = Self::new(config)?;
+        let id = session.store.resolve(id)?;
+        let replay = session.store.load(&id)?;
+
+        *session.context.messages_mut() = replay.messages;
+        session.id = replay.id;
+        session.created_at = replay.created_at;
+        session.parent = replay.parent;
+        session.saved = true;
+        session.persisted = session.context.len();
+        session.revision = session.context.revision();
+        Ok((session, replay.model))
+    }
+
+    /// Creates a new session branching off this one.
+    ///
+    /// The fork gets a fresh id, its own agent and harness, and a copy of
+    /// the first `at` messages, so earlier command results are reused
+    /// instead of re-run. If this session is saved, the fork is saved too,
+    /// with its parent recorded in the log header.
+    ///
+    /// # Arguments
+    /// - `at`: Number of messages to keep (`0..=len`).
+    ///
+    /// # Returns
+    /// The forked session, or an error if `at` is out of range.
+    pub fn fork(&self, at: usize) -> Result<Self, String> {
+        if at > self.context.len() {
+            return Err(format!(
+                "message index {} out of range (0..={})",
+                at,
+                self.context.len()
+            ));
+        }
+
+        let mut context = self.context.clone();
+        context.messages_mut().truncate(at);
+        context.set_last_usage(Default::default());
+
+        let mut fork = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            parent: Some(Parent {
+                id: self.id.clone(),
+                at,
+            }),
+            config: self.config.clone(),
+            revision: context.revision(),
+            context,
+            agent: build_agent(&self.config)?,
+            degraded: None,
+            harness: build_harness(&self.config, self.backend.as_deref())?,
+            faults: self.faults.clone(),
+            backend: self.backend.clone(),
+            policy: Policy::new(&self.config.policy)?,
+            approved: self.approved.clone(),
+            store: self.store.clone(),
+            saved: false,
+            persisted: 0,
+        };
+        if self.saved {
+            fork.save()?;
+        }
+        Ok(fork)
+    }
+
+    /// Returns the session id.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the session this one was forked from, as `(id, message count)`.
+    pub fn parent(&self) -> Option<(&str, usize)> {
+        self.parent.as_ref().map(|p| (p.id.as_str(), p.at))
+    }
+
+    /// Returns `true` if the session is being written to its log.
+    pub fn is_saved(&self) -> bool {
+        self.saved
+    }
+
+    /// Starts persisting the session: writes the log header and the full
+    /// history, then keeps appending on every change.
+    ///
+    /// Calling it again on a saved session just flushes pending changes.
+    ///
+    /// # Returns
+    /// The path of the session log.
+    pub fn save(&mut self) -> Result<PathBuf, String> {
+        if self.saved {
+            self.sync()?;
+            return Ok(self.store.path(&self.id));
+        }
+
+        let mut entries = vec![Entry::Meta {
+            id: self.id.clone(),
+            created_at: self.created_at,
+            config: Box::new(self.config.clone()),
+            parent: self.parent.clone(),
+        }];
+        let now = Utc::now();
+        entries.extend(self.context.messages().iter().map(|m| Entry::Message {
+            at: now,
+            message: m.clone(),
+        }));
+        self.store.append(&self.id, &entries)?;
+
+        self.saved = true;
+        self.persisted = self.context.len();
+        self.revision = self.context.revision();
+        Ok(self.store.path(&self.id))
+    }
+
+    /// Appends history changes since the last write to the log.
+    ///
+    /// Appended messages are written as `Message` entries; if the history
+    /// was rewritten in place, a single `Rewrite` entry replaces it. Does
+    /// nothing for unsaved sessions unless autosave is enabled.
+    fn sync(&mut self) -> Result<(), String> {
+        if !self.saved {
+            if self.config.sessions.autosave && !self.context.is_empty() {
+                self.save()?;
+            }
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let entries: Vec<Entry> = if self.context.revision() != self.revision {
+            vec![Entry::Rewrite {
+                at: now,
+                messages: self.context.messages().to_vec(),
+            }]
+        } else {
+            self.context.messages()[self.persisted..]
+                .iter()
+                .map(|m| Entry::Message {
+                    at: now,
+                    message: m.clone(),
+                })
+                .collect()
+        };
+        self.store.append(&self.id, &entries)?;
+
+        self.persisted = self.context.len();
+        self.revision = self.context.revision();
+        Ok(())
+    }
+
+    /// Syncs the log, reporting failures as a warning event.
+    fn sync_or_warn(&mut self, on_event: &impl Fn(&Event)) {
+        if let Err(e) = self.sync() {
+            on_event(&Event::Warning(format!("failed to write session log: {}", e)));
+        }
+    }
+
     /// Returns the available skills for this session.
     pub fn skills(&self) -> &[SkillMeta] {
         self.context.skills()
     }
 
+    /// Returns the conversation context.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Classifies the answer of the last turn.
+    ///
+    /// The agent returns errors as answers too, so an answer only counts
+    /// as `Answered` if the last message is the agent's `completed` action.
+    ///
+    /// # Arguments
+    /// - `answer`: The string returned by `turn()`.
+    pub fn ending(&self, answer: &str) -> Ending {
+        let completed = match self.context.messages().last() {
+            Some(Message::Assistant { raw, .. }) => match &raw.action {
+                Action::Completed { answer: last } => last == answer,
+                _ => false,
+            },
+            _ => false,
+        };
+        match answer {
+            INTERRUPTED => Ending::Interrupted,
+            MAX_STEPS => Ending::MaxSteps,
+            _ if completed => Ending::Answered,
+            _ => Ending::Failed,
+        }
+    }
+
+    /// Returns the context window size of the active LLM.
+    pub fn context_window(&self) -> usize {
+        self.agent.guard().llm().context_window()
+    }
+
+    /// Returns the system prompt as it would be sent on the next LLM call.
+    pub fn system_prompt(&self) -> String {
+        self.agent
+            .guard()
+            .llm()
+            .prompt_engine()
+            .system_prompt(&self.context)
+    }
+
     /// Rebuilds the agent using the first LLM in config, preserving context.
     ///
     /// Call after `/switch` has moved the desired LLM to the top of the list.
-    /// Context is retained so conversation history carries over. This design
-    /// also allows context to be forked for multi-agent scenarios in the future.
+    /// Ends a fallback: the session runs on the first LLM again.
+    /// Context is retained so conversation history carries over. Harness
+    /// settings are updated too, so changed limits apply to the next command.
+    /// The harness is rebuilt, losing its shell state, only if the new LLM
+    /// or config selects a different backend or changes its settings.
     ///
     /// # Arguments
     /// - `config`: The configuration with the desired LLM at index 0.
@@ -88,34 +387,242 @@ impl Session {
     /// # Returns
     /// `Ok(())` on success, or an error string.
     pub fn extend(&mut self, config: &Config) -> Result<(), String> {
-        let llm_config = config
-            .llm
-            .first()
-            .ok_or("no LLM configured")?
-            .clone();
-
-        let prompt_engine = PromptEngine::new(String::new());
-        let llm = LLMClient::new(llm_config, prompt_engine)?;
-        self.agent = Agent::new(llm, config.agent.max_steps);
+        let previous = self.agent.guard().llm().config().name.clone();
+        let policy = Policy::new(&config.policy)?;
+        let agent = build_agent(config)?;
+        let backend = backend_name(config, self.backend.as_deref());
+        if backend != self.harness.backend()
+            || config.harness.workdir != self.config.harness.workdir
+            || config.harness.backends.get(&backend) != self.config.harness.backends.get(&backend)
+        {
+            self.harness = build_harness(config, self.backend.as_deref())?;
+        } else {
+            self.harness.set_config(config.harness.clone());
+        }
+        self.agent = agent;
+        self.degraded = None;
+        self.policy = policy;
+        self.config = config.clone();
+
+        if self.agent.guard().llm().config().name != previous {
+            self.record_model()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the configuration of the LLM the agent runs on, which is
+    /// a fallback LLM while the session is degraded.
+    pub fn llm(&self) -> &LLMConfig {
+        self.agent.guard().llm().config()
+    }
+
+    /// Appends the agent's current LLM to the log, if the session is saved.
+    fn record_model(&mut self) -> Result<(), String> {
+        if !self.saved {
+            return Ok(());
+        }
+        let current = self.agent.guard().llm().config();
+        self.store.append(
+            &self.id,
+            &[Entry::ModelSwitch {
+                at: Utc::now(),
+                name: current.name.clone(),
+                model: current.model.clone(),
+            }],
+        )
+    }
+
+    /// Moves the agent to the next fallback LLM after the current one failed.
+    ///
+    /// Tries the LLMs in `fallback.llms` after the current fallback, in
+    /// order; ones that are missing from the config, the active LLM itself,
+    /// or ones that cannot be built (e.g. an unset key variable) are skipped
+    /// with a warning. Context and harness carry over.
+    ///
+    /// # Arguments
+    /// - `error`: The error the current LLM failed with.
+    /// - `on_event`: Callback for the `Degraded` event and warnings.
+    ///
+    /// # Returns
+    /// `true` if a fallback LLM took over, `false` if none is left.
+    fn degrade(&mut self, error: &str, on_event: &impl Fn(&Event)) -> bool {
+        let start = self.degraded.as_ref().map_or(0, |d| d.position + 1);
+        let active = self.config.llm.first().map(|l| l.name.clone());
+        for position in start..self.config.fallback.llms.len() {
+            let name = &self.config.fallback.llms[position];
+            if active.as_ref() == Some(name) {
+                continue;
+            }
+            let agent = match self.config.llm.iter().find(|l| &l.name == name) {
+                Some(llm) => build_agent_for(&self.config, llm),
+                None => Err("not configured".to_string()),
+            };
+            match agent {
+                Ok(agent) => {
+                    self.agent = agent;
+                    self.degraded = Some(Degraded {
+                        position,
+                        since: Instant::now(),
+                    });
+                    on_event(&Event::Degraded(name.clone(), error.to_string()));
+                    if let Err(e) = self.record_model() {
+                        on_event(&Event::Warning(format!(
+                            "failed to write session log: {}",
+                            e
+                        )));
+                    }
+                    return true;
+                }
+                Err(e) => on_event(&Event::Warning(format!(
+                    "cannot fall back to '{}': {}",
+                    name, e
+                ))),
+            }
+        }
+        false
+    }
+
+    /// Moves the agent back to the active LLM once `fallback.restore_after_secs`
+    /// have passed since the session degraded.
+    ///
+    /// # Arguments
+    /// - `on_event`: Callback for the `Restored` event and warnings.
+    fn restore(&mut self, on_event: &impl Fn(&Event)) {
+        let (Some(degraded), Some(after)) =
+            (&self.degraded, self.config.fallback.restore_after_secs)
+        else {
+            return;
+        };
+        if degraded.since.elapsed() < Duration::from_secs(after) {
+            return;
+        }
+
+        match build_agent(&self.config) {
+            Ok(agent) => {
+                self.agent = agent;
+                self.degraded = None;
+                on_event(&Event::Restored(self.llm().name.clone()));
+                if let Err(e) = self.record_model() {
+                    on_event(&Event::Warning(format!(
+                        "failed to write session log: {}",
+                        e
+                    )));
+                }
+            }
+            Err(e) => on_event(&Event::Warning(format!(
+                "cannot restore the active LLM: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Returns the faults injected into this session's LLM calls and
+    /// commands, shared with its forks.
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Returns the name of the harness backend commands run on.
+    pub fn backend(&self) -> &str {
+        self.harness.backend()
+    }
+
+    /// Switches this session to another harness backend.
+    ///
+    /// The choice overrides the LLM's and the config's backend until the
+    /// session ends. Shell state does not carry over to the new backend.
+    ///
+    /// # Arguments
+    /// - `name`: `"local"` or a key of `harness.backends`.
+    pub fn set_backend(&mut self, name: &str) -> Result<(), String> {
+        self.harness = build_harness(&self.config, Some(name))?;
+        self.backend = Some(name.to_string());
         Ok(())
     }
 
+    /// Compacts older history into a summary message on demand.
+    ///
+    /// # Returns
+    /// The number of messages replaced by the summary.
+    pub async fn compact(&mut self) -> Result<usize, String> {
+        let replaced = self.agent.guard().compact(&mut self.context).await?;
+        self.sync()?;
+        Ok(replaced)
+    }
+
     /// Processes one user turn: drives the agent loop and dispatches
     /// `Execute` actions to the harness until the agent completes.
     ///
     /// Emits `Event`s via the callback so transports can display progress.
+    /// Before each agent run, compacts history if usage has crossed the
+    /// configured threshold. A failed proactive compaction is not fatal:
+    /// the context guard still recovers if the call then overflows.
+    /// History changes are appended to the session log around every step.
+    ///
+    /// If the LLM fails with an error that retries could not fix (see
+    /// `Failure::falls_back`), the step is run again on the next LLM in
+    /// `fallback.llms`. Once `fallback.restore_after_secs` have passed, the
+    /// next step goes back to the active LLM.
+    ///
+    /// Cancelling `cancel` aborts the in-flight LLM request or kills the
+    /// running command, records an `INTERRUPTED` failure observation, and
+    /// returns early with context intact.
+    ///
+    /// Every command is checked against the policy first. Denied commands
+    /// are not run and become failure observations; commands the policy
+    /// marks `ask` pause the turn until `approve` returns.
     ///
     /// # Arguments
     /// - `input`: The user's input text.
+    /// - `cancel`: Token signalling a user interrupt for this turn.
     /// - `on_event`: Callback invoked for each progress event.
+    /// - `approve`: Async callback asked to approve a command, given the
+    ///   command and the reason the policy flagged it.
     ///
     /// # Returns
-    /// The agent's final answer string.
-    pub async fn turn(&mut self, input: String, on_event: impl Fn(&Event)) -> String {
+    /// The agent's final answer string, or `INTERRUPTED`.
+    pub async fn turn(
+        &mut self,
+        input: String,
+        cancel: &CancellationToken,
+        on_event: impl Fn(&Event),
+        approve: impl AsyncFn(&str, &str) -> Approval,
+    ) -> String {
         self.context.add_user_message(input);
+        self.harness.set_cancel(cancel.clone());
+        self.harness.set_faults(self.faults.clone());
+        self.restore(&on_event);
 
         loop {
-            let action = self.agent.run(&mut self.context).await;
+            self.sync_or_warn(&on_event);
+
+            if self.agent.guard().needs_compaction(&self.context)
+                && let Ok(replaced) = self.agent.guard().compact(&mut self.context).await
+            {
+                on_event(&Event::Compacted(replaced));
+            }
+
+            let (deltas, mut received) = mpsc::unbounded_channel();
+            let llm = self.agent.guard_mut().llm_mut();
+            llm.set_deltas(deltas);
+            llm.set_faults(self.faults.clone());
+            let action = forward(
+                self.agent.run(&mut self.context, cancel),
+                &mut received,
+                |delta| on_event(&Event::ThinkingDelta(delta)),
+            )
+            .await;
+            if cancel.is_cancelled() {
+                return self.interrupt("agent".to_string(), &on_event);
+            }
+            self.sync_or_warn(&on_event);
+
+            if let Action::Completed { answer } = &action
+                && self.agent.guard().failure().is_some_and(|f| f.falls_back())
+                && self.degrade(answer, &on_event)
+            {
+                continue;
+            }
 
             if let Some(Message::Assistant { thought, .. }) = self.context.messages().last() {
                 on_event(&Event::Thinking(thought.content.clone()));
@@ -123,9 +630,30 @@ impl Session {
 
             match action {
                 Action::Execute { command } => {
+                    let authorized = self.authorize(&command, &approve).await;
+                    if cancel.is_cancelled() {
+                        return self.interrupt(command, &on_event);
+                    }
+                    if let Err(error) = authorized {
+                        on_event(&Event::Output(error.clone(), false));
+                        self.context
+                            .add_observation(command, Outcome::Failure { error });
+                        continue;
+                    }
+
                     on_event(&Event::Executing(command.clone()));
                     self.harness.set_command(command.clone());
-                    let result = self.harness.run(&mut self.context).await;
+                    let (output, mut chunks) = mpsc::unbounded_channel();
+                    self.harness.set_output(output);
+                    let result = forward(
+                        self.harness.run(&mut self.context),
+                        &mut chunks,
+                        |chunk| on_event(&Event::OutputChunk(chunk)),
+                    )
+                    .await;
+                    if cancel.is_cancelled() {
+                        return self.interrupt(command, &on_event);
+                    }
                     match result {
                         Action::Completed { answer } => {
                             on_event(&Event::Output(answer.clone(), false));
@@ -135,10 +663,11 @@ impl Session {
                             );
                         }
                         _ => {
-                            if let Some(Message::Observation { content, .. }) =
-                                self.context.messages().last()
+                            if let Some(Message::Observation {
+                                outcome, content, ..
+                            }) = self.context.messages().last()
                             {
-                                on_event(&Event::Output(content.clone(), true));
+                                on_event(&Event::Output(content.clone(), outcome.is_success()));
                             }
                         }
                     }
@@ -148,27 +677,157 @@ impl Session {
             }
         }
     }
+
+    /// Checks a command against the policy, asking for approval if needed.
+    ///
+    /// # Arguments
+    /// - `command`: The shell command the agent wants to run.
+    /// - `approve`: Callback that asks the user.
+    ///
+    /// # Returns
+    /// `Ok(())` if the command may run, or the reason it may not.
+    async fn authorize(
+        &mut self,
+        command: &str,
+        approve: &impl AsyncFn(&str, &str) -> Approval,
+    ) -> Result<(), String> {
+        let verdict = self.policy.check(command, self.harness.cwd());
+        match verdict.decision {
+            Decision::Allow => Ok(()),
+            Decision::Deny => Err(format!("command denied by policy: {}", verdict.reason)),
+            Decision::Ask if self.approved.contains(command) => Ok(()),
+            Decision::Ask => match approve(command, &verdict.reason).await {
+                Approval::Approve => Ok(()),
+                Approval::AlwaysAllow => {
+                    self.approved.insert(command.to_string());
+                    Ok(())
+                }
+                Approval::Deny => Err(format!("command denied by user: {}", verdict.reason)),
+            },
+        }
+    }
+
+    /// Records a user interrupt as a failure observation and syncs the log.
+    ///
+    /// # Arguments
+    /// - `skill`: The interrupted command, or `"agent"` for an LLM step.
+    /// - `on_event`: Callback for log write warnings.
+    ///
+    /// # Returns
+    /// The `INTERRUPTED` answer for the transport.
+    fn interrupt(&mut self, skill: String, on_event: &impl Fn(&Event)) -> String {
+        self.context.add_observation(
+            skill,
+            Outcome::Failure {
+                error: INTERRUPTED.to_string(),
+            },
+        );
+        self.sync_or_warn(on_event);
+        INTERRUPTED.to_string()
+    }
+}
+
+/// Awaits `future` while passing everything received on `rx` to `f`.
+///
+/// Items still queued when the future completes are passed on too, so
+/// none are lost to the race between the two.
+async fn forward<T>(
+    future: impl Future<Output = Action>,
+    rx: &mut mpsc::UnboundedReceiver<T>,
+    f: impl Fn(T),
+) -> Action {
+    tokio::pin!(future);
+    let action = loop {
+        tokio::select! {
+            action = &mut future => break action,
+            Some(item) = rx.recv() => f(item),
+        }
+    };
+    while let Ok(item) = rx.try_recv() {
+        f(item);
+    }
+    action
+}
+
+/// Builds an agent from the first LLM in config, wrapped in a context guard.
+///
+/// # Arguments
+/// - `config`: The application configuration.
+fn build_agent(config: &Config) -> Result<Agent, String> {
+    let llm_config = config.llm.first().ok_or("no LLM configured")?;
+    build_agent_for(config, llm_config)
+}
+
+/// Builds an agent on the given LLM, wrapped in a context guard.
+///
+/// # Arguments
+/// - `config`: The application configuration.
+/// - `llm_config`: The LLM the agent runs on.
+fn build_agent_for(config: &Config, llm_config: &LLMConfig) -> Result<Agent, String> {
+    let prompt_engine = PromptEngine::new(String::new());
+    let llm = LLMClient::new(llm_config.clone(), prompt_engine)?
+        .with_retry(config.retry.clone())
+        .with_cassette(Cassette::open(&config.cassette)?);
+    let guard = ContextGuard::new(llm, config.context.clone());
+    Ok(Agent::new(guard, config.agent.max_steps))
+}
+
+/// Resolves the harness backend: the session's choice, then the active
+/// LLM's, then `harness.backend`.
+///
+/// # Arguments
+/// - `config`: The application configuration.
+/// - `session`: Backend chosen for the session, if any.
+fn backend_name(config: &Config, session: Option<&str>) -> String {
+    session
+        .or_else(|| config.llm.first().and_then(|llm| llm.backend.as_deref()))
+        .unwrap_or(&config.harness.backend)
+        .to_string()
+}
+
+/// Builds a harness on the backend resolved by `backend_name`.
+///
+/// # Arguments
+/// - `config`: The application configuration.
+/// - `session`: Backend chosen for the session, if any.
+fn build_harness(config: &Config, session: Option<&str>) -> Result<Harness, String> {
+    Harness::build(config.harness.clone(), &backend_name(config, session))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{AgentConfig, LLMConfig, SkillsConfig};
+    use crate::config::{
+        AgentConfig, BackendConfig, CassetteConfig, ContextConfig, FallbackConfig, HarnessConfig,
+        LLMConfig, PolicyConfig, Protocol, ProviderKind, RetryConfig, SessionsConfig, SkillsConfig,
+    };
+    use crate::core::llm::tests::{http_response, serve_responses};
 
     fn test_config() -> Config {
-        dotenvy::dotenv().ok();
         Config {
             agent: AgentConfig { max_steps: 5 },
             llm: vec![LLMConfig {
                 name: "test".to_string(),
                 model: "test-model".to_string(),
                 base_url: "https://test.example.com".to_string(),
-                api_key_env: "LLM_API_KEY".to_string(),
+                api_key_env: None,
+                api_key_envs: Vec::new(),
                 max_tokens: 1024,
                 context_window: 25_600,
                 reasoning_effort: None,
+                backend: None,
+                stream: false,
+                protocol: Protocol::JsonSchema,
+                provider: ProviderKind::Openai,
             }],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         }
     }
 
@@ -181,11 +840,17 @@ mod tests {
 
     #[test]
     fn test_new_no_llm_configured() {
-        dotenvy::dotenv().ok();
         let config = Config {
             agent: AgentConfig { max_steps: 5 },
             llm: vec![],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         };
         let result = Session::new(&config);
         match result {
@@ -202,12 +867,24 @@ mod tests {
                 name: "test".to_string(),
                 model: "test-model".to_string(),
                 base_url: "https://test.example.com".to_string(),
-                api_key_env: "NONEXISTENT_KEY_99999".to_string(),
+                api_key_env: Some("NONEXISTENT_KEY_99999".to_string()),
+                api_key_envs: Vec::new(),
                 max_tokens: 1024,
                 context_window: 25_600,
                 reasoning_effort: None,
+                backend: None,
+                stream: false,
+                protocol: Protocol::JsonSchema,
+                provider: ProviderKind::Openai,
             }],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         };
         let result = Session::new(&config);
         assert!(result.is_err());
@@ -239,6 +916,87 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        let mut config = test_config();
+        config.sessions.dir = Some(dir.to_string_lossy().to_string());
+
+        let mut session = Session::new(&config).unwrap();
+        session.context.add_user_message("hello".to_string());
+        session.save().unwrap();
+        assert!(session.is_saved());
+
+        session.context.add_user_message("again".to_string());
+        session.sync().unwrap();
+        session.context.messages_mut().remove(0);
+        session.sync().unwrap();
+
+        let (loaded, model) = Session::load(&config, &session.id()[..8]).unwrap();
+        assert_eq!(loaded.id(), session.id());
+        assert_eq!(loaded.context.len(), 1);
+        assert_eq!(model.as_deref(), Some("test"));
+        if let Message::User { content } = &loaded.context.messages()[0] {
+            assert_eq!(content, "again");
+        } else {
+            panic!("expected User message");
+        }
+    }
+
+    #[test]
+    fn test_autosave_writes_on_sync() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        let mut config = test_config();
+        config.sessions.dir = Some(dir.to_string_lossy().to_string());
+
+        let mut session = Session::new(&config).unwrap();
+        session.sync().unwrap();
+        assert!(!session.is_saved());
+
+        config.sessions.autosave = true;
+        let mut session = Session::new(&config).unwrap();
+        session.sync().unwrap();
+        assert!(!session.is_saved());
+        session.context.add_user_message("hello".to_string());
+        session.sync().unwrap();
+        assert!(session.is_saved());
+        assert!(dir.join(format!("{}.jsonl", session.id())).exists());
+    }
+
+    #[test]
+    fn test_fork_copies_prefix() {
+        let config = test_config();
+        let mut session = Session::new(&config).unwrap();
+        session.context.add_user_message("one".to_string());
+        session.context.add_user_message("two".to_string());
+
+        let fork = session.fork(1).unwrap();
+        assert_ne!(fork.id(), session.id());
+        assert_eq!(fork.parent(), Some((session.id(), 1)));
+        assert_eq!(fork.context.len(), 1);
+        assert_eq!(session.context.len(), 2);
+        assert!(!fork.is_saved());
+
+        assert!(session.fork(3).is_err());
+    }
+
+    #[test]
+    fn test_fork_of_saved_session_is_saved() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        let mut config = test_config();
+        config.sessions.dir = Some(dir.to_string_lossy().to_string());
+
+        let mut session = Session::new(&config).unwrap();
+        session.context.add_user_message("one".to_string());
+        session.save().unwrap();
+
+        let fork = session.fork(1).unwrap();
+        assert!(fork.is_saved());
+        let (loaded, _) = Session::load(&config, fork.id()).unwrap();
+        assert_eq!(loaded.parent(), Some((session.id(), 1)));
+        assert_eq!(loaded.context.len(), 1);
+    }
+
     #[test]
     fn test_extend_no_llm_configured() {
         let config = test_config();
@@ -248,8 +1006,209 @@ mod tests {
             agent: AgentConfig { max_steps: 5 },
             llm: vec![],
             skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
         };
         let result = session.extend(&empty_config);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_backend_selection() {
+        let mut config = test_config();
+        config.harness.backends.insert(
+            "box".to_string(),
+            BackendConfig::Container {
+                runtime: "docker".to_string(),
+                image: "alpine:3".to_string(),
+                workspace: None,
+                mount: "/workspace".to_string(),
+                run_args: Vec::new(),
+            },
+        );
+        let mut session = Session::new(&config).unwrap();
+        assert_eq!(session.backend(), "local");
+
+        // The active LLM's backend overrides the config default
+        config.llm[0].backend = Some("box".to_string());
+        session.extend(&config).unwrap();
+        assert_eq!(session.backend(), "box");
+
+        // The session's choice overrides both, and survives extend and fork
+        session.set_backend("local").unwrap();
+        session.extend(&config).unwrap();
+        assert_eq!(session.backend(), "local");
+        assert_eq!(session.fork(0).unwrap().backend(), "local");
+
+        assert!(session.set_backend("missing").is_err());
+        assert_eq!(session.backend(), "local");
+    }
+
+    #[tokio::test]
+    async fn test_turn_runs_script() {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script.yaml");
+        std::fs::write(
+            &script,
+            r#"
+- expect: ["say hello"]
+  thought: { thought_type: planning, content: "Use echo." }
+  action: { action: execute, command: "echo hello" }
+- expect: ["hello"]
+  thought: { thought_type: analysis, content: "It printed hello." }
+  action: { action: completed, answer: "said hello" }
+"#,
+        )
+        .unwrap();
+        let mut config = test_config();
+        config.llm[0].provider = ProviderKind::Script;
+        config.llm[0].base_url = script.display().to_string();
+        config.llm[0].stream = true;
+
+        let mut session = Session::new(&config).unwrap();
+        let executed = std::cell::RefCell::new(Vec::new());
+        let on_event = |event: &Event| {
+            if let Event::Executing(command) = event {
+                executed.borrow_mut().push(command.clone());
+            }
+        };
+        let cancel = CancellationToken::new();
+        let answer = session
+            .turn("say hello".to_string(), &cancel, on_event, async |_, _| {
+                Approval::Deny
+            })
+            .await;
+        assert_eq!(answer, "said hello");
+        assert_eq!(session.ending(&answer), Ending::Answered);
+        assert_eq!(*executed.borrow(), ["echo hello"]);
+
+        // The script is used up
+        let answer = session
+            .turn(
+                "again".to_string(),
+                &cancel,
+                |_| {},
+                async |_, _| Approval::Deny,
+            )
+            .await;
+        assert_eq!(answer, "LLM request error: script ended after 2 steps");
+        assert_eq!(session.ending(&answer), Ending::Failed);
+        assert_eq!(session.ending(MAX_STEPS), Ending::MaxSteps);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_turn_falls_back_and_restores() {
+        // Nothing listens on this port, so every request fails with a server error
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut config = test_config();
+        config.llm[0].base_url = format!("http://127.0.0.1:{}/v1/chat/completions", port);
+        let mut backup = config.llm[0].clone();
+        backup.name = "backup".to_string();
+        config.llm.push(backup);
+        config.retry.max_attempts = 1;
+        config.fallback.llms = vec!["missing".to_string(), "backup".to_string()];
+
+        let mut session = Session::new(&config).unwrap();
+        let events = std::cell::RefCell::new(Vec::new());
+        let on_event = |event: &Event| match event {
+            Event::Degraded(name, error) => {
+                assert!(error.starts_with("LLM server error"), "{}", error);
+                events.borrow_mut().push(format!("degraded {}", name));
+            }
+            Event::Restored(name) => events.borrow_mut().push(format!("restored {}", name)),
+            Event::Warning(message) => events.borrow_mut().push(message.clone()),
+            _ => {}
+        };
+        let cancel = CancellationToken::new();
+
+        let answer = session
+            .turn("hi".to_string(), &cancel, on_event, async |_, _| {
+                Approval::Deny
+            })
+            .await;
+        assert!(answer.starts_with("LLM server error"), "{}", answer);
+        assert_eq!(session.llm().name, "backup");
+        assert_eq!(
+            *events.borrow(),
+            [
+                "cannot fall back to 'missing': not configured",
+                "degraded backup"
+            ]
+        );
+
+        events.borrow_mut().clear();
+        session.config.fallback.restore_after_secs = Some(0);
+        session
+            .turn("again".to_string(), &cancel, on_event, async |_, _| {
+                Approval::Deny
+            })
+            .await;
+        assert_eq!(session.llm().name, "backup");
+        assert_eq!(
+            events.borrow()[..2],
+            [
+                "restored test",
+                "cannot fall back to 'missing': not configured"
+            ]
+        );
+        assert_eq!(events.borrow()[2], "degraded backup");
+    }
+
+    #[tokio::test]
+    async fn test_turn_falls_back_on_token_rate_limit() {
+        // Worded like an overflow, but ContextGuard must pass it on as a rate limit
+        let response = http_response(
+            "429 Too Many Requests",
+            "text/plain",
+            "",
+            "too many tokens per minute",
+        );
+        let (url, _requests) = serve_responses(vec![response]).await;
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script.yaml");
+        std::fs::write(
+            &script,
+            r#"
+- thought: { thought_type: analysis, content: "Hi." }
+  action: { action: completed, answer: "hello" }
+"#,
+        )
+        .unwrap();
+
+        let mut config = test_config();
+        config.llm[0].base_url = url;
+        let mut backup = config.llm[0].clone();
+        backup.name = "backup".to_string();
+        backup.provider = ProviderKind::Script;
+        backup.base_url = script.display().to_string();
+        config.llm.push(backup);
+        config.retry.max_attempts = 1;
+        config.fallback.llms = vec!["backup".to_string()];
+
+        let mut session = Session::new(&config).unwrap();
+        let cancel = CancellationToken::new();
+        let answer = session
+            .turn(
+                "hi".to_string(),
+                &cancel,
+                |_| {},
+                async |_, _| Approval::Deny,
+            )
+            .await;
+        assert_eq!(answer, "hello");
+        assert_eq!(session.llm().name, "backup");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,460 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::core::context::Message;
+
+/// One line of a session's append-only JSONL event log.
+///
+/// # Variants
+/// - `Meta`: First line of every log: session id, creation time, config snapshot,
+///   and the parent session if this one was forked.
+/// - `Message`: A message appended to the conversation history.
+/// - `Rewrite`: The history was rewritten (compaction, truncation); replaces all messages.
+/// - `ModelSwitch`: The active LLM changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    Meta {
+        id: String,
+        created_at: DateTime<Utc>,
+        config: Box<Config>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent: Option<Parent>,
+    },
+    Message {
+        at: DateTime<Utc>,
+        message: Message,
+    },
+    Rewrite {
+        at: DateTime<Utc>,
+        messages: Vec<Message>,
+    },
+    ModelSwitch {
+        at: DateTime<Utc>,
+        name: String,
+        model: String,
+    },
+}
+
+/// Origin of a forked session.
+///
+/// # Fields
+/// - `id`: The session it was forked from.
+/// - `at`: Number of parent messages the fork started with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Parent {
+    pub id: String,
+    pub at: usize,
+}
+
+impl Entry {
+    /// Returns the timestamp of this entry.
+    pub fn at(&self) -> DateTime<Utc> {
+        match self {
+            Entry::Meta { created_at, .. } => *created_at,
+            Entry::Message { at, .. }
+            | Entry::Rewrite { at, .. }
+            | Entry::ModelSwitch { at, .. } => *at,
+        }
+    }
+}
+
+/// Summary of a saved session, used for listing.
+///
+/// # Fields
+/// - `id`: The session id.
+/// - `created_at`: When the session was created.
+/// - `updated_at`: Timestamp of the last log entry.
+/// - `messages`: Number of messages after replaying the log.
+/// - `title`: The first user message, if any.
+/// - `parent`: The session this one was forked from, if any.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: usize,
+    pub title: Option<String>,
+    pub parent: Option<Parent>,
+}
+
+/// A session log replayed from disk.
+///
+/// # Fields
+/// - `id`: The session id.
+/// - `created_at`: When the session was created.
+/// - `messages`: The conversation history after applying all entries.
+/// - `model`: Name of the last active LLM recorded in the log.
+/// - `parent`: The session this one was forked from, if any.
+pub struct Replay {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub messages: Vec<Message>,
+    pub model: Option<String>,
+    pub parent: Option<Parent>,
+}
+
+/// Directory of JSONL session logs, one `{id}.jsonl` file per session.
+///
+/// # Fields
+/// - `dir`: The directory holding the logs.
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Creates a store rooted at `dir`, or `~/.minusagent/sessions` if `None`.
+    ///
+    /// # Arguments
+    /// - `dir`: Optional override for the sessions directory.
+    pub fn new(dir: Option<&str>) -> Self {
+        let dir = match dir {
+            Some(d) => PathBuf::from(d),
+            None => dirs::home_dir()
+                .expect("cannot resolve home directory")
+                .join(".minusagent")
+                .join("sessions"),
+        };
+        Self { dir }
+    }
+
+    /// Returns the log file path for a session id.
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", id))
+    }
+
+    /// Appends entries to a session's log, creating the file if needed.
+    ///
+    /// # Arguments
+    /// - `id`: The session id.
+    /// - `entries`: Entries to append, in order.
+    pub fn append(&self, id: &str, entries: &[Entry]) -> Result<(), String> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("failed to create {}: {}", self.dir.display(), e))?;
+
+        let path = self.path(id);
+        let mut buf = String::new();
+        for entry in entries {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| format!("failed to serialize session entry: {}", e))?;
+            buf.push_str(&line);
+            buf.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        file.write_all(buf.as_bytes())
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    /// Resolves a full session id from a unique prefix.
+    ///
+    /// # Arguments
+    /// - `prefix`: A full id or an unambiguous prefix of one.
+    pub fn resolve(&self, prefix: &str) -> Result<String, String> {
+        let matches: Vec<String> = self
+            .ids()?
+            .into_iter()
+            .filter(|id| id.starts_with(prefix))
+            .collect();
+        match matches.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => Err(format!("session '{}' not found", prefix)),
+            _ => Err(format!("session id '{}' is ambiguous", prefix)),
+        }
+    }
+
+    /// Reads and replays a session log.
+    ///
+    /// # Arguments
+    /// - `id`: The full session id.
+    pub fn load(&self, id: &str) -> Result<Replay, String> {
+        replay(&self.path(id))
+    }
+
+    /// Lists saved sessions, most recently updated first.
+    ///
+    /// Logs that cannot be read or replayed are skipped.
+    pub fn list(&self) -> Result<Vec<SessionInfo>, String> {
+        let mut infos = Vec::new();
+        for id in self.ids()? {
+            let path = self.path(&id);
+            let Ok(entries) = read_entries(&path) else {
+                continue;
+            };
+            let Ok(replay) = apply(&path, &entries) else {
+                continue;
+            };
+            let updated_at = entries.last().map_or(replay.created_at, Entry::at);
+            let title = replay.messages.iter().find_map(|m| match m {
+                Message::User { content } => Some(content.clone()),
+                _ => None,
+            });
+            infos.push(SessionInfo {
+                id: replay.id,
+                created_at: replay.created_at,
+                updated_at,
+                messages: replay.messages.len(),
+                title,
+                parent: replay.parent,
+            });
+        }
+        infos.sort_by_key(|i| std::cmp::Reverse(i.updated_at));
+        Ok(infos)
+    }
+
+    /// Returns the ids of all logs in the store directory.
+    fn ids(&self) -> Result<Vec<String>, String> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| format!("failed to read {}: {}", self.dir.display(), e))?;
+        Ok(entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .collect())
+    }
+}
+
+/// Reads all entries from a JSONL log file.
+fn read_entries(path: &Path) -> Result<Vec<Entry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("{}:{}: invalid entry: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+/// Reads and replays a log file.
+fn replay(path: &Path) -> Result<Replay, String> {
+    let entries = read_entries(path)?;
+    apply(path, &entries)
+}
+
+/// Applies entries in order to rebuild the session state.
+fn apply(path: &Path, entries: &[Entry]) -> Result<Replay, String> {
+    let (id, created_at, config, parent) = match entries.first() {
+        Some(Entry::Meta {
+            id,
+            created_at,
+            config,
+            parent,
+        }) => (id.clone(), *created_at, config, parent.clone()),
+        _ => return Err(format!("{}: missing meta entry", path.display())),
+    };
+
+    let mut replay = Replay {
+        id,
+        created_at,
+        messages: Vec::new(),
+        model: config.llm.first().map(|l| l.name.clone()),
+        parent,
+    };
+    for entry in &entries[1..] {
+        match entry {
+            Entry::Meta { .. } => {}
+            Entry::Message { message, .. } => replay.messages.push(message.clone()),
+            Entry::Rewrite { messages, .. } => replay.messages = messages.clone(),
+            Entry::ModelSwitch { name, .. } => replay.model = Some(name.clone()),
+        }
+    }
+    Ok(replay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        AgentConfig, CassetteConfig, ContextConfig, FallbackConfig, HarnessConfig, PolicyConfig,
+        RetryConfig, SessionsConfig, SkillsConfig,
+    };
+    use crate::core::context::Outcome;
+
+    fn temp_store() -> SessionStore {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        SessionStore::new(Some(dir.to_str().unwrap()))
+    }
+
+    fn meta(id: &str) -> Entry {
+        Entry::Meta {
+            id: id.to_string(),
+            created_at: Utc::now(),
+            config: Box::new(Config {
+                agent: AgentConfig { max_steps: 5 },
+                llm: vec![],
+                skills: SkillsConfig::default(),
+                context: ContextConfig::default(),
+                sessions: SessionsConfig::default(),
+                harness: HarnessConfig::default(),
+                policy: PolicyConfig::default(),
+                retry: RetryConfig::default(),
+                fallback: FallbackConfig::default(),
+                cassette: CassetteConfig::default(),
+            }),
+            parent: None,
+        }
+    }
+
+    fn message(message: Message) -> Entry {
+        Entry::Message {
+            at: Utc::now(),
+            message,
+        }
+    }
+
+    #[test]
+    fn test_append_and_load() {
+        let store = temp_store();
+        store
+            .append(
+                "abc",
+                &[
+                    meta("abc"),
+                    message(Message::User {
+                        content: "hello".to_string(),
+                    }),
+                ],
+            )
+            .unwrap();
+        store
+            .append(
+                "abc",
+                &[message(Message::Observation {
+                    skill: "ls".to_string(),
+                    outcome: Outcome::Success {
+                        output: "a.txt".to_string(),
+                    },
+                    content: "a.txt".to_string(),
+                })],
+            )
+            .unwrap();
+
+        let replay = store.load("abc").unwrap();
+        assert_eq!(replay.id, "abc");
+        assert_eq!(replay.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_rewrite_and_model_switch() {
+        let store = temp_store();
+        store
+            .append(
+                "abc",
+                &[
+                    meta("abc"),
+                    message(Message::User {
+                        content: "one".to_string(),
+                    }),
+                    message(Message::User {
+                        content: "two".to_string(),
+                    }),
+                    Entry::Rewrite {
+                        at: Utc::now(),
+                        messages: vec![Message::Summary {
+                            content: "one and two".to_string(),
+                            replaced: 2,
+                        }],
+                    },
+                    Entry::ModelSwitch {
+                        at: Utc::now(),
+                        name: "b".to_string(),
+                        model: "model-b".to_string(),
+                    },
+                ],
+            )
+            .unwrap();
+
+        let replay = store.load("abc").unwrap();
+        assert_eq!(replay.messages.len(), 1);
+        assert!(matches!(replay.messages[0], Message::Summary { .. }));
+        assert_eq!(replay.model.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_list_and_resolve() {
+        let store = temp_store();
+        assert!(store.list().unwrap().is_empty());
+
+        store
+            .append(
+                "abc123",
+                &[
+                    meta("abc123"),
+                    message(Message::User {
+                        content: "first task".to_string(),
+                    }),
+                ],
+            )
+            .unwrap();
+        store.append("abd456", &[meta("abd456")]).unwrap();
+
+        let infos = store.list().unwrap();
+        assert_eq!(infos.len(), 2);
+        let info = infos.iter().find(|i| i.id == "abc123").unwrap();
+        assert_eq!(info.messages, 1);
+        assert_eq!(info.title.as_deref(), Some("first task"));
+
+        assert_eq!(store.resolve("abc").unwrap(), "abc123");
+        assert!(store.resolve("ab").is_err());
+        assert!(store.resolve("zzz").is_err());
+    }
+
+    #[test]
+    fn test_parent_roundtrip() {
+        let store = temp_store();
+        let mut entry = meta("child");
+        if let Entry::Meta { parent, .. } = &mut entry {
+            *parent = Some(Parent {
+                id: "root".to_string(),
+                at: 3,
+            });
+        }
+        store.append("child", &[entry]).unwrap();
+        store.append("root", &[meta("root")]).unwrap();
+
+        let replay = store.load("child").unwrap();
+        assert_eq!(
+            replay.parent,
+            Some(Parent {
+                id: "root".to_string(),
+                at: 3
+            })
+        );
+        assert!(store.load("root").unwrap().parent.is_none());
+    }
+
+    #[test]
+    fn test_load_missing_meta() {
+        let store = temp_store();
+        store
+            .append(
+                "bad",
+                &[message(Message::User {
+                    content: "hi".to_string(),
+                })],
+            )
+            .unwrap();
+        assert!(store.load("bad").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+/// Usage text printed for `--help` and after argument errors.
+pub const USAGE: &str = "\
+Usage:
+  minusagent [--resume [<id>]]       Start the REPL, optionally resuming a session
+  minusagent run [options] <task>    Run one task and print the answer
+
+Run options:
+  --llm <name>        Use this configured LLM instead of the active one
+  --max-steps <n>     Maximum LLM calls per agent run
+  --yes               Approve commands the policy would ask about (default: deny)
+  -q, --quiet         Do not print thoughts and commands to stderr
+
+The task is the rest of the arguments; `-` reads it from stdin. A task that
+starts with `-` goes after `--`.";
+
+/// What the command line asks for.
+///
+/// # Variants
+/// - `Repl`: The interactive REPL; `resume` is `Some` for `--resume`, with the session id if given.
+/// - `Run`: A single task, see `RunArgs`.
+/// - `Help`: Print the usage.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Repl { resume: Option<Option<String>> },
+    Run(RunArgs),
+    Help,
+}
+
+/// Options of `minusagent run`.
+///
+/// # Fields
+/// - `task`: The task for the agent; `-` to read it from stdin.
+/// - `llm`: Name of the LLM to use instead of the active one.
+/// - `max_steps`: Overrides `agent.max_steps`.
+/// - `yes`: Approve commands the policy would ask about.
+/// - `quiet`: Print only the answer.
+#[derive(Debug, Default, PartialEq)]
+pub struct RunArgs {
+    pub task: String,
+    pub llm: Option<String>,
+    pub max_steps: Option<u32>,
+    pub yes: bool,
+    pub quiet: bool,
+}
+
+/// Parses the command line, without the program name.
+///
+/// # Arguments
+/// - `args`: The arguments.
+///
+/// # Returns
+/// The command, or an error describing the invalid argument.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        None => Ok(Command::Repl { resume: None }),
+        Some("-h" | "--help") => Ok(Command::Help),
+        Some("--resume") if args.len() <= 2 => Ok(Command::Repl {
+            resume: Some(args.get(1).cloned()),
+        }),
+        Some("run") => parse_run(&args[1..]).map(Command::Run),
+        Some(arg) => Err(format!("unknown argument: {}", arg)),
+    }
+}
+
+/// Parses the arguments after `run`.
+fn parse_run(args: &[String]) -> Result<RunArgs, String> {
+    let mut run = RunArgs::default();
+    let mut rest = args.iter();
+    let mut task = Vec::new();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--llm" => run.llm = Some(value()?.clone()),
+            "--max-steps" => {
+                let n = value()?;
+                run.max_steps = Some(
+                    n.parse()
+                        .map_err(|_| format!("--max-steps must be a number, got '{}'", n))?,
+                );
+            }
+            "--yes" => run.yes = true,
+            "-q" | "--quiet" => run.quiet = true,
+            "--" => {
+                task.extend(rest.by_ref().cloned());
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option: {}", flag));
+            }
+            _ => task.push(arg.clone()),
+        }
+    }
+
+    if task.is_empty() {
+        return Err("run needs a task".to_string());
+    }
+    run.task = task.join(" ");
+    Ok(run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(line: &str) -> Result<Command, String> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn test_parse_repl() {
+        assert_eq!(parse_str(""), Ok(Command::Repl { resume: None }));
+        assert_eq!(
+            parse_str("--resume"),
+            Ok(Command::Repl { resume: Some(None) })
+        );
+        assert_eq!(
+            parse_str("--resume 3f2a"),
+            Ok(Command::Repl {
+                resume: Some(Some("3f2a".to_string()))
+            })
+        );
+        assert_eq!(parse_str("--help"), Ok(Command::Help));
+        assert!(parse_str("--verbose").is_err());
+    }
+
+    #[test]
+    fn test_parse_run() {
+        assert_eq!(
+            parse_str("run --llm local -q --max-steps 8 fix the tests"),
+            Ok(Command::Run(RunArgs {
+                task: "fix the tests".to_string(),
+                llm: Some("local".to_string()),
+                max_steps: Some(8),
+                quiet: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse_str("run --yes -- --explain this"),
+            Ok(Command::Run(RunArgs {
+                task: "--explain this".to_string(),
+                yes: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse_str("run -"),
+            Ok(Command::Run(RunArgs {
+                task: "-".to_string(),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse_str("run -y deploy"),
+            Err("unknown option: -y".to_string())
+        );
+        assert_eq!(
+            parse_str("run --force deploy"),
+            Err("unknown option: --force".to_string())
+        );
+        assert_eq!(parse_str("run"), Err("run needs a task".to_string()));
+        assert_eq!(
+            parse_str("run --max-steps many task"),
+            Err("--max-steps must be a number, got 'many'".to_string())
+        );
+        assert_eq!(
+            parse_str("run task --llm"),
+            Err("--llm needs a value".to_string())
+        );
+    }
+}
@@ -1,8 +1,25 @@
+use std::cell::Cell;
 use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
 
-use crate::config::{Config, LLMConfig};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{BackendConfig, Config, LLMConfig, Protocol, ProviderKind};
+use crate::core::Action;
+use crate::core::context::Message;
+use crate::core::fault::Fault;
+use crate::core::harness::{LOCAL, Stream};
+use crate::core::policy::Approval;
+use crate::session::store::SessionStore;
 use crate::session::{Event, Session};
 
+/// Width of the `/context` usage bar in characters.
+const USAGE_BAR_WIDTH: usize = 40;
+
+/// Number of largest observations listed by `/context`.
+const TOP_OBSERVATIONS: usize = 5;
+
 /// Interactive CLI REPL transport for the agent.
 ///
 /// Reads user input from stdin, drives the session, and displays
@@ -11,9 +28,16 @@ use crate::session::{Event, Session};
 /// # Fields
 /// - `config`: The live configuration, modifiable via `/config` commands.
 /// - `session`: The active conversation session (None if not yet initialized).
+/// - `branches`: Inactive sessions forked from or alongside the active one.
+/// - `interrupt`: Cancellation token of the running turn, if any; Ctrl+C cancels it.
+/// - `pending`: Line read left running by a cancelled approval prompt; the
+///   REPL takes its line as the next input.
 pub struct Cli {
     config: Config,
     session: Option<Session>,
+    branches: Vec<Session>,
+    interrupt: Arc<Mutex<Option<CancellationToken>>>,
+    pending: Mutex<Option<JoinHandle<Option<String>>>>,
 }
 
 impl Cli {
@@ -34,29 +58,55 @@ impl Cli {
                 None
             }
         };
-        Self { config, session }
+        Self {
+            config,
+            session,
+            branches: Vec::new(),
+            interrupt: Arc::new(Mutex::new(None)),
+            pending: Mutex::new(None),
+        }
     }
 
     /// Runs the interactive REPL loop.
     ///
     /// Reads lines from stdin, dispatches slash commands, and sends
-    /// user input to the session for agent processing.
+    /// user input to the session for agent processing. Ctrl+C cancels the
+    /// running turn and returns to the prompt; at the prompt it only
+    /// prints a hint.
     pub async fn run(&mut self) {
         println!("minusAgent v0.1.0");
         println!("Type /exit to quit, /help for available commands.\n");
 
+        let interrupt = self.interrupt.clone();
+        tokio::spawn(async move {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                match interrupt.lock().unwrap().as_ref() {
+                    Some(token) => {
+                        println!("\n\x1b[31m[interrupt] cancelling...\x1b[0m");
+                        token.cancel();
+                    }
+                    None => {
+                        print!("\n(Use /exit or Ctrl+D to quit)\n> ");
+                        io::stdout().flush().unwrap();
+                    }
+                }
+            }
+        });
+
         let stdin = io::stdin();
 
         loop {
             print!("> ");
             io::stdout().flush().unwrap();
 
-            let mut input = String::new();
-            match stdin.lock().read_line(&mut input) {
-                Ok(0) => break,
-                Ok(_) => {}
-                Err(_) => break,
-            }
+            let pending = self.pending.lock().unwrap().take();
+            let line = match pending {
+                Some(read) => read.await.ok().flatten(),
+                None => read_line(&stdin),
+            };
+            let Some(input) = line else {
+                break;
+            };
 
             let input = input.trim().to_string();
             if input.is_empty() {
@@ -78,24 +128,91 @@ impl Cli {
                 }
             };
 
+            let cancel = CancellationToken::new();
+            *self.interrupt.lock().unwrap() = Some(cancel.clone());
+            // Whether thoughts were streamed, and whether one is being typed out
+            let thought_streamed = Cell::new(false);
+            let in_thought = Cell::new(false);
+            // Whether the running command streamed output, and whether the
+            // last chunk ended mid-line
+            let streamed = Cell::new(false);
+            let open_line = Cell::new(false);
+
             let answer = session
-                .turn(input, |event| match event {
-                    Event::Thinking(content) => {
-                        println!("\x1b[2m[thinking] {}\x1b[0m", content);
-                    }
-                    Event::Executing(command) => {
-                        println!("\x1b[33m[executing] {}\x1b[0m", command);
-                    }
-                    Event::Output(content, success) => {
-                        if *success {
-                            println!("\x1b[2m{}\x1b[0m", content);
-                        } else {
-                            println!("\x1b[31m[error] {}\x1b[0m", content);
+                .turn(
+                    input,
+                    &cancel,
+                    |event| match event {
+                        Event::ThinkingDelta(delta) => {
+                            thought_streamed.set(true);
+                            if !in_thought.replace(delta != "\n") {
+                                print!("\x1b[2m[thinking] \x1b[0m");
+                            }
+                            print!("\x1b[2m{}\x1b[0m", delta);
+                            let _ = io::stdout().flush();
                         }
-                    }
-                })
+                        // Already typed out from the deltas
+                        Event::Thinking(_) if thought_streamed.replace(false) => {}
+                        Event::Thinking(content) => {
+                            println!("\x1b[2m[thinking] {}\x1b[0m", content);
+                        }
+                        Event::Executing(command) => {
+                            streamed.set(false);
+                            println!("\x1b[33m[executing] {}\x1b[0m", command);
+                        }
+                        Event::OutputChunk(chunk) => {
+                            streamed.set(true);
+                            open_line.set(!chunk.text.ends_with('\n'));
+                            let color = match chunk.stream {
+                                Stream::Stdout => "2",
+                                Stream::Stderr => "2;31",
+                            };
+                            print!("\x1b[{}m{}\x1b[0m", color, chunk.text);
+                            let _ = io::stdout().flush();
+                        }
+                        Event::Output(content, success) if streamed.get() => {
+                            // The body was already shown live; only the status line is new
+                            if open_line.replace(false) {
+                                println!();
+                            }
+                            let status = content.lines().last().unwrap_or_default();
+                            if *success {
+                                println!("\x1b[2m{}\x1b[0m", status);
+                            } else {
+                                println!("\x1b[31m[error] {}\x1b[0m", status);
+                            }
+                        }
+                        Event::Output(content, success) => {
+                            if *success {
+                                println!("\x1b[2m{}\x1b[0m", content);
+                            } else {
+                                println!("\x1b[31m[error] {}\x1b[0m", content);
+                            }
+                        }
+                        Event::Compacted(replaced) => {
+                            println!(
+                                "\x1b[36m[compacted] {} messages summarized\x1b[0m",
+                                replaced
+                            );
+                        }
+                        Event::Degraded(name, error) => {
+                            println!(
+                                "\x1b[35m[fallback] switched to '{}' after: {}\x1b[0m",
+                                name, error
+                            );
+                        }
+                        Event::Restored(name) => {
+                            println!("\x1b[36m[restored] back on '{}'\x1b[0m", name);
+                        }
+                        Event::Warning(message) => {
+                            println!("\x1b[35m[warning] {}\x1b[0m", message);
+                        }
+                    },
+                    async |command, reason| approve(command, reason, &cancel, &self.pending).await,
+                )
                 .await;
 
+            *self.interrupt.lock().unwrap() = None;
             println!("\n{}\n", answer);
         }
 
@@ -112,7 +229,17 @@ impl Cli {
             "/skills" => self.cmd_skills(),
             "/models" => self.cmd_models(),
             "/new" => self.cmd_new(),
+            "/compact" => self.cmd_compact().await,
+            "/context" => self.cmd_context(&parts[1..]),
+            "/save" => self.cmd_save(),
+            "/sessions" => self.cmd_sessions(),
+            "/load" => self.cmd_load(&parts[1..]),
+            "/resume" => self.resume(parts.get(1).copied()),
+            "/fork" => self.cmd_fork(&parts[1..]),
+            "/branches" => self.cmd_branches(&parts[1..]),
             "/switch" => self.cmd_switch(&parts[1..]),
+            "/harness" => self.cmd_harness(&parts[1..]),
+            "/simulate-failure" => self.cmd_simulate_failure(&parts[1..]),
             "/config" => self.cmd_config(&parts[1..]),
             _ => println!("Unknown command: {}", parts[0]),
         }
@@ -124,9 +251,25 @@ impl Cli {
         println!("Commands:");
         println!("  /exit                        Exit the REPL");
         println!("  /new                         Start a new session (fresh context)");
+        println!("  /compact                     Summarize older history to free context");
+        println!("  /context                     Show context usage and composition");
+        println!("  /context messages            List every message with its token estimate");
+        println!("  /context prompt              Show the assembled system prompt");
+        println!("  /save                        Save the session and keep logging changes");
+        println!("  /sessions                    List saved sessions");
+        println!("  /load <id>                   Load a saved session and show its history");
+        println!("  /resume [id]                 Resume a saved session (default: most recent)");
+        println!("  /fork [n]                    Branch off keeping the first n messages (default: all)");
+        println!("  /branches                    List branches of this conversation");
+        println!("  /branches <id>               Switch to another branch");
         println!("  /skills                      List available skills");
         println!("  /models                      List configured LLMs");
         println!("  /switch <name>               Switch LLM and rebuild session");
+        println!("  /harness                     List harness backends");
+        println!("  /harness <name>              Run this session's commands on another backend");
+        println!("  /simulate-failure            List injected failures");
+        println!("  /simulate-failure <kind> [n] Fail the next n calls (default: 1)");
+        println!("  /simulate-failure clear      Remove injected failures");
         println!("  /config                      View current configuration");
         println!("  /config set <key> <value>    Set a config field (dotted path)");
         println!("  /config add llm              Add an LLM (interactive)");
@@ -159,8 +302,19 @@ impl Cli {
             return;
         }
         println!("Models:");
+        let fallback = self
+            .session
+            .as_ref()
+            .map(|s| s.llm().name.as_str())
+            .filter(|name| Some(*name) != self.config.llm.first().map(|l| l.name.as_str()));
         for (i, llm) in self.config.llm.iter().enumerate() {
-            let marker = if i == 0 { " (active)" } else { "" };
+            let marker = if i == 0 {
+                " (active)"
+            } else if Some(llm.name.as_str()) == fallback {
+                " (fallback in use)"
+            } else {
+                ""
+            };
             println!("  - {} ({}){}", llm.name, llm.model, marker);
         }
     }
@@ -170,12 +324,246 @@ impl Cli {
         match Session::new(&self.config) {
             Ok(session) => {
                 self.session = Some(session);
+                self.branches.clear();
                 println!("New session started.");
             }
             Err(e) => eprintln!("Failed to create session: {}", e),
         }
     }
 
+    /// Compacts older history into a summary message.
+    async fn cmd_compact(&mut self) {
+        let session = match self.session.as_mut() {
+            Some(s) => s,
+            None => {
+                println!("No active session.");
+                return;
+            }
+        };
+        match session.compact().await {
+            Ok(replaced) => println!("Compacted {} messages into a summary.", replaced),
+            Err(e) => eprintln!("Failed to compact: {}", e),
+        }
+    }
+
+    /// Displays context usage, message counts, largest observations, and
+    /// optionally the per-message breakdown or the full system prompt.
+    fn cmd_context(&self, args: &[&str]) {
+        let session = match &self.session {
+            Some(s) => s,
+            None => {
+                println!("No active session.");
+                return;
+            }
+        };
+        match args.first().copied() {
+            None => context_overview(session),
+            Some("messages") => context_messages(session),
+            Some("prompt") => println!("{}", session.system_prompt()),
+            Some(sub) => println!("Unknown context subcommand: {}", sub),
+        }
+    }
+
+    /// Starts persisting the active session to its JSONL log.
+    fn cmd_save(&mut self) {
+        let session = match self.session.as_mut() {
+            Some(s) => s,
+            None => {
+                println!("No active session.");
+                return;
+            }
+        };
+        match session.save() {
+            Ok(path) => println!("Session {} saved to {}", session.id(), path.display()),
+            Err(e) => eprintln!("Failed to save session: {}", e),
+        }
+    }
+
+    /// Lists saved sessions, most recent first.
+    fn cmd_sessions(&self) {
+        let store = SessionStore::new(self.config.sessions.dir.as_deref());
+        let infos = match store.list() {
+            Ok(infos) => infos,
+            Err(e) => {
+                eprintln!("Failed to list sessions: {}", e);
+                return;
+            }
+        };
+        if infos.is_empty() {
+            println!("No saved sessions.");
+            return;
+        }
+        let active = self.session.as_ref().map(|s| s.id());
+        println!("Sessions:");
+        for info in infos {
+            let mut marker = match &info.parent {
+                Some(parent) => format!(" (fork of {} @ {})", short_id(&parent.id), parent.at),
+                None => String::new(),
+            };
+            if Some(info.id.as_str()) == active {
+                marker.push_str(" (active)");
+            }
+            println!(
+                "  - {}  {}  {:>3} msgs  {}{}",
+                short_id(&info.id),
+                info.updated_at.format("%Y-%m-%d %H:%M"),
+                info.messages,
+                preview(info.title.as_deref().unwrap_or("(empty)"), 50),
+                marker
+            );
+        }
+    }
+
+    /// Loads a saved session by id and prints its conversation so far.
+    fn cmd_load(&mut self, args: &[&str]) {
+        let id = match args.first() {
+            Some(id) => *id,
+            None => {
+                println!("Usage: /load <id>");
+                return;
+            }
+        };
+        self.resume(Some(id));
+        if let Some(session) = &self.session {
+            print_transcript(session);
+        }
+    }
+
+    /// Resumes a saved session, or the most recently updated one if `id`
+    /// is `None`. Replaces the active session on success.
+    ///
+    /// # Arguments
+    /// - `id`: A session id or unambiguous prefix.
+    pub fn resume(&mut self, id: Option<&str>) {
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => {
+                let store = SessionStore::new(self.config.sessions.dir.as_deref());
+                match store.list().map(|infos| infos.into_iter().next()) {
+                    Ok(Some(info)) => info.id,
+                    Ok(None) => {
+                        println!("No saved sessions.");
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to list sessions: {}", e);
+                        return;
+                    }
+                }
+            }
+        };
+
+        match Session::load(&self.config, &id) {
+            Ok((session, model)) => {
+                println!(
+                    "Resumed session {} ({} messages).",
+                    session.id(),
+                    session.context().len()
+                );
+                let active = self.config.llm.first().map(|l| l.name.as_str());
+                if let Some(model) = model.filter(|m| Some(m.as_str()) != active) {
+                    println!("Session last used '{}'; use /switch {} to continue with it.", model, model);
+                }
+                self.session = Some(session);
+                self.branches.clear();
+            }
+            Err(e) => eprintln!("Failed to resume session: {}", e),
+        }
+    }
+
+    /// Forks the active session at a message index and makes the fork
+    /// active. The previous session stays available via `/branches`.
+    fn cmd_fork(&mut self, args: &[&str]) {
+        let session = match &self.session {
+            Some(s) => s,
+            None => {
+                println!("No active session.");
+                return;
+            }
+        };
+        let at = match args.first().map(|a| a.parse::<usize>()) {
+            None => session.context().len(),
+            Some(Ok(n)) => n,
+            Some(Err(_)) => {
+                println!("Usage: /fork [n]  (n = messages to keep, see /context messages)");
+                return;
+            }
+        };
+        match session.fork(at) {
+            Ok(fork) => {
+                println!(
+                    "Forked {} at message {} -> {} (now active).",
+                    short_id(session.id()),
+                    at,
+                    short_id(fork.id())
+                );
+                if let Some(previous) = self.session.replace(fork) {
+                    self.branches.push(previous);
+                }
+            }
+            Err(e) => eprintln!("Failed to fork: {}", e),
+        }
+    }
+
+    /// Lists branches, or switches to the branch matching an id prefix.
+    fn cmd_branches(&mut self, args: &[&str]) {
+        let Some(prefix) = args.first() else {
+            let Some(active) = &self.session else {
+                println!("No active session.");
+                return;
+            };
+            println!("Branches:");
+            for (session, marker) in std::iter::once((active, " (active)"))
+                .chain(self.branches.iter().map(|b| (b, "")))
+            {
+                let origin = match session.parent() {
+                    Some((id, at)) => format!("from {} @ {}", short_id(id), at),
+                    None => "root".to_string(),
+                };
+                println!(
+                    "  - {}  {:>3} msgs  {}{}",
+                    short_id(session.id()),
+                    session.context().len(),
+                    origin,
+                    marker
+                );
+            }
+            return;
+        };
+
+        let matches: Vec<usize> = self
+            .branches
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.id().starts_with(prefix))
+            .map(|(i, _)| i)
+            .collect();
+        let idx = match matches.as_slice() {
+            [i] => *i,
+            [] => {
+                println!("No inactive branch matches '{}'.", prefix);
+                return;
+            }
+            _ => {
+                println!("Branch id '{}' is ambiguous.", prefix);
+                return;
+            }
+        };
+
+        let mut branch = self.branches.remove(idx);
+        if let Err(e) = branch.extend(&self.config) {
+            eprintln!("Failed to rebuild branch: {}", e);
+        }
+        println!(
+            "Switched to branch {} ({} messages).",
+            short_id(branch.id()),
+            branch.context().len()
+        );
+        if let Some(previous) = self.session.replace(branch) {
+            self.branches.push(previous);
+        }
+    }
+
     /// Switches to a different LLM by name: promotes it to the top of the
     /// config list, then rebuilds the session agent with context preserved.
     fn cmd_switch(&mut self, args: &[&str]) {
@@ -212,6 +600,88 @@ impl Cli {
         }
     }
 
+    /// Lists harness backends, or switches the session to one by name.
+    fn cmd_harness(&mut self, args: &[&str]) {
+        let Some(session) = &mut self.session else {
+            println!("No active session.");
+            return;
+        };
+
+        match args.first() {
+            Some(name) => match session.set_backend(name) {
+                Ok(()) => println!("Commands now run on '{}'.", name),
+                Err(e) => eprintln!("Failed: {}", e),
+            },
+            None => {
+                let backends = &self.config.harness.backends;
+                let local = (!backends.contains_key(LOCAL)).then_some(LOCAL);
+                println!("Backends:");
+                for name in local.into_iter().chain(backends.keys().map(String::as_str)) {
+                    let kind = backends.get(name).map_or(LOCAL, BackendConfig::kind);
+                    let marker = if name == session.backend() {
+                        " (active)"
+                    } else {
+                        ""
+                    };
+                    println!("  - {} ({}){}", name, kind, marker);
+                }
+            }
+        }
+    }
+
+    /// Injects synthetic failures into the session's LLM calls or commands,
+    /// or lists the pending ones.
+    fn cmd_simulate_failure(&mut self, args: &[&str]) {
+        let Some(session) = &self.session else {
+            println!("No active session.");
+            return;
+        };
+        let faults = session.faults();
+
+        match args.first().copied() {
+            None => {
+                let names: Vec<&str> = Fault::ALL.iter().map(Fault::as_str).collect();
+                println!("Usage: /simulate-failure <kind> [count]");
+                println!("Kinds: {}", names.join(", "));
+                let pending = faults.pending();
+                if pending.is_empty() {
+                    println!("No failures injected.");
+                }
+                for (fault, count) in pending {
+                    println!("  - {}: {} left", fault, count);
+                }
+            }
+            Some("clear") => {
+                faults.clear();
+                println!("Injected failures removed.");
+            }
+            Some(kind) => {
+                let fault = match Fault::parse(kind) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("Failed: {}", e);
+                        return;
+                    }
+                };
+                let count = match args.get(1).map(|n| n.parse::<u32>()) {
+                    None => 1,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => {
+                        eprintln!("Failed: count must be a number");
+                        return;
+                    }
+                };
+                faults.inject(fault, count);
+                let target = match fault {
+                    Fault::CommandCrash => "commands",
+                    Fault::MalformedJson => "LLM replies",
+                    _ => "LLM requests",
+                };
+                println!("Next {} {} will fail with '{}'.", count, target, fault);
+            }
+        }
+    }
+
     /// Dispatches `/config` subcommands.
     fn cmd_config(&mut self, args: &[&str]) {
         match args.first().copied() {
@@ -270,16 +740,21 @@ impl Cli {
         let name = prompt_line(&stdin, "Name: ");
         let model = prompt_line(&stdin, "Model: ");
         let base_url = prompt_line(&stdin, "Base URL: ");
-        let api_key_env = prompt_line(&stdin, "API key env var: ");
+        let api_key_env = prompt_line(&stdin, "API key env var (empty for none): ");
 
         let llm = LLMConfig {
             name: name.clone(),
             model,
             base_url,
-            api_key_env,
+            api_key_env: Some(api_key_env).filter(|name| !name.is_empty()),
+            api_key_envs: Vec::new(),
             max_tokens: 4096,
             context_window: 128_000,
             reasoning_effort: None,
+            backend: None,
+            stream: false,
+            protocol: Protocol::JsonSchema,
+            provider: ProviderKind::Openai,
         };
 
         match self.config.add_llm(llm) {
@@ -301,11 +776,202 @@ impl Cli {
     }
 }
 
+/// Prints the `/context` overview: usage bar, token counts, message
+/// composition, largest observations, and system prompt size.
+fn context_overview(session: &Session) {
+    let ctx = session.context();
+    let window = session.context_window();
+    let system_prompt = session.system_prompt();
+    let system_tokens = system_prompt.chars().count().div_ceil(4);
+
+    let (used, source) = match ctx.context_tokens() {
+        0 => (ctx.estimate_tokens() + system_tokens, "estimated"),
+        n => (n, "last call"),
+    };
+    let ratio = used as f64 / window.max(1) as f64;
+    println!(
+        "Context: {} {:.1}% ({} / {} tokens, {})",
+        usage_bar(ratio),
+        ratio * 100.0,
+        used,
+        window,
+        source
+    );
+
+    let last = ctx.last_usage();
+    println!(
+        "Tokens:  last call {} prompt + {} completion; session total {} ({} prompt + {} completion)",
+        last.prompt_tokens,
+        last.completion_tokens,
+        ctx.total_tokens(),
+        ctx.prompt_tokens(),
+        ctx.completion_tokens()
+    );
+
+    // (count, estimated tokens) per role: user, assistant, observation, summary
+    let mut roles = [(0, 0); 4];
+    for m in ctx.messages() {
+        let slot = match m {
+            Message::User { .. } => 0,
+            Message::Assistant { .. } => 1,
+            Message::Observation { .. } => 2,
+            Message::Summary { .. } => 3,
+        };
+        roles[slot].0 += 1;
+        roles[slot].1 += m.estimate_tokens();
+    }
+    println!("Messages: {}", ctx.len());
+    println!("  {:<12} {:<4} ~{} tokens", "system", 1, system_tokens);
+    for (name, (count, tokens)) in ["user", "assistant", "observation", "summary"]
+        .into_iter()
+        .zip(roles)
+    {
+        if count > 0 || name != "summary" {
+            println!("  {:<12} {:<4} ~{} tokens", name, count, tokens);
+        }
+    }
+
+    let mut observations: Vec<(usize, &str, usize)> = ctx
+        .messages()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| match m {
+            Message::Observation { skill, .. } => Some((i, skill.as_str(), m.estimate_tokens())),
+            _ => None,
+        })
+        .collect();
+    if !observations.is_empty() {
+        observations.sort_by_key(|o| std::cmp::Reverse(o.2));
+        println!("Largest observations:");
+        for (i, skill, tokens) in observations.iter().take(TOP_OBSERVATIONS) {
+            println!("  #{:<4} ~{:<7} {}", i, tokens, preview(skill, 60));
+        }
+    }
+    println!("Use /context messages or /context prompt for details.");
+}
+
+/// Prints every message with its index, role, token estimate, and a preview.
+fn context_messages(session: &Session) {
+    let ctx = session.context();
+    if ctx.is_empty() {
+        println!("Context is empty.");
+        return;
+    }
+    for (i, m) in ctx.messages().iter().enumerate() {
+        let (role, text) = match m {
+            Message::User { content } => ("user", content.as_str()),
+            Message::Assistant { thought, .. } => ("assistant", thought.content.as_str()),
+            Message::Observation { skill, .. } => ("observation", skill.as_str()),
+            Message::Summary { content, .. } => ("summary", content.as_str()),
+        };
+        println!(
+            "  #{:<4} {:<12} ~{:<7} {}",
+            i,
+            role,
+            m.estimate_tokens(),
+            preview(text, 60)
+        );
+    }
+}
+
+/// Prints the user inputs and final answers of a session's history.
+fn print_transcript(session: &Session) {
+    for m in session.context().messages() {
+        match m {
+            Message::User { content } if content != "continue" => println!("> {}", content),
+            Message::Assistant { raw, .. } => {
+                if let Action::Completed { answer } = &raw.action {
+                    println!("\n{}\n", answer);
+                }
+            }
+            Message::Summary { replaced, .. } => {
+                println!("\x1b[36m[summary of {} earlier messages]\x1b[0m", replaced)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders a usage bar like `[████░░░░]` for the given ratio.
+fn usage_bar(ratio: f64) -> String {
+    let filled = ((ratio.clamp(0.0, 1.0) * USAGE_BAR_WIDTH as f64).round()) as usize;
+    let color = if ratio >= 0.8 {
+        "\x1b[31m"
+    } else if ratio >= 0.5 {
+        "\x1b[33m"
+    } else {
+        "\x1b[32m"
+    };
+    format!(
+        "[{}{}\x1b[0m{}]",
+        color,
+        "█".repeat(filled),
+        "░".repeat(USAGE_BAR_WIDTH - filled)
+    )
+}
+
+/// Returns the first 8 characters of a session id for display.
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(8)]
+}
+
+/// Returns the first line of `text`, cut to `max` characters.
+fn preview(text: &str, max: usize) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > max {
+        format!("{}…", line.chars().take(max).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Asks the user whether a command flagged by the policy may run.
+///
+/// The answer is read on a blocking thread, so Ctrl+C can cancel the turn
+/// while the prompt waits. A cancelled prompt counts as a denial; its read
+/// is left in `pending` so the line typed next still reaches the REPL.
+/// Anything other than yes or always counts as a denial.
+async fn approve(
+    command: &str,
+    reason: &str,
+    cancel: &CancellationToken,
+    pending: &Mutex<Option<JoinHandle<Option<String>>>>,
+) -> Approval {
+    println!("\x1b[33m[approve] {}\x1b[0m", command);
+    println!("\x1b[2m  {}\x1b[0m", reason);
+    print!("  Run it? [y]es / [n]o / [a]lways: ");
+    io::stdout().flush().unwrap();
+
+    let mut read = tokio::task::spawn_blocking(|| read_line(&io::stdin()));
+    let answer = tokio::select! {
+        line = &mut read => line.ok().flatten().unwrap_or_default(),
+        _ = cancel.cancelled() => {
+            *pending.lock().unwrap() = Some(read);
+            return Approval::Deny;
+        }
+    };
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Approval::Approve,
+        "a" | "always" => Approval::AlwaysAllow,
+        _ => Approval::Deny,
+    }
+}
+
 /// Prompts the user for a line of input.
 fn prompt_line(stdin: &io::Stdin, prompt: &str) -> String {
     print!("{}", prompt);
     io::stdout().flush().unwrap();
+    read_line(stdin).unwrap_or_default().trim().to_string()
+}
+
+/// Reads a line from stdin.
+///
+/// # Returns
+/// The line, or `None` at end of input or on a read error.
+fn read_line(stdin: &io::Stdin) -> Option<String> {
     let mut line = String::new();
-    stdin.lock().read_line(&mut line).unwrap_or(0);
-    line.trim().to_string()
+    match stdin.lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line),
+    }
 }
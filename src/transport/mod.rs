@@ -1 +1,3 @@
+pub mod args;
 pub mod cli;
+pub mod run;
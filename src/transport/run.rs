@@ -0,0 +1,313 @@
+use std::cell::RefCell;
+use std::io::{Read, Write};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Config;
+use crate::core::policy::Approval;
+use crate::session::{Ending, Event, Session};
+use crate::transport::args::RunArgs;
+
+/// Exit status when the agent answered.
+pub const EXIT_ANSWERED: i32 = 0;
+
+/// Exit status when the turn failed or the session could not start.
+pub const EXIT_FAILED: i32 = 1;
+
+/// Exit status for invalid arguments.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Exit status when the agent ran out of steps.
+pub const EXIT_MAX_STEPS: i32 = 3;
+
+/// Exit status when the run was interrupted with Ctrl+C.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Returns a token that is cancelled when Ctrl+C is pressed.
+pub fn interrupt_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            interrupt.cancel();
+        }
+    });
+    cancel
+}
+
+/// Runs a single task without the REPL, for scripts, hooks and Makefiles.
+///
+/// The answer goes to `out`; thoughts, commands, their output and errors
+/// go to `err`, the former unless `quiet` is set. Commands the policy would
+/// ask about are denied unless `yes` is set, since nobody is there to
+/// approve them.
+///
+/// # Arguments
+/// - `config`: The application configuration; `--llm` and `--max-steps`
+///   apply to this run only and are not saved.
+/// - `args`: The parsed `run` arguments.
+/// - `cancel`: Interrupts the run, e.g. from `interrupt_on_ctrl_c`.
+/// - `out`: Receives the answer (stdout).
+/// - `err`: Receives progress and errors (stderr).
+///
+/// # Returns
+/// The process exit status: `EXIT_ANSWERED`, `EXIT_FAILED`, `EXIT_USAGE`,
+/// `EXIT_MAX_STEPS` or `EXIT_INTERRUPTED`.
+pub async fn run(
+    mut config: Config,
+    args: RunArgs,
+    cancel: &CancellationToken,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> i32 {
+    if let Some(name) = &args.llm
+        && let Err(e) = config.select_llm(name)
+    {
+        let _ = writeln!(err, "{}", e);
+        return EXIT_USAGE;
+    }
+    if let Some(max_steps) = args.max_steps {
+        config.agent.max_steps = max_steps;
+    }
+
+    let task = match read_task(&args.task) {
+        Ok(task) => task,
+        Err(e) => {
+            let _ = writeln!(err, "{}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut session = match Session::new(&config) {
+        Ok(s) => s,
+        Err(e) => {
+            let _ = writeln!(err, "Failed to start session: {}", e);
+            return EXIT_FAILED;
+        }
+    };
+
+    let quiet = args.quiet;
+    let yes = args.yes;
+    // Both callbacks report to `err`
+    let progress = RefCell::new(&mut *err);
+    let answer = session
+        .turn(
+            task,
+            cancel,
+            |event| {
+                if !quiet {
+                    report(event, *progress.borrow_mut());
+                }
+            },
+            async |command, reason| {
+                if yes {
+                    return Approval::Approve;
+                }
+                let _ = writeln!(
+                    progress.borrow_mut(),
+                    "[denied] {} ({}; pass --yes to allow)",
+                    command,
+                    reason
+                );
+                Approval::Deny
+            },
+        )
+        .await;
+
+    let ending = session.ending(&answer);
+    let _ = match ending {
+        Ending::Answered => writeln!(out, "{}", answer),
+        _ => writeln!(err, "[error] {}", answer),
+    };
+    match ending {
+        Ending::Answered => EXIT_ANSWERED,
+        Ending::MaxSteps => EXIT_MAX_STEPS,
+        Ending::Interrupted => EXIT_INTERRUPTED,
+        Ending::Failed => EXIT_FAILED,
+    }
+}
+
+/// Returns the task, reading it from stdin if it is `-`.
+fn read_task(task: &str) -> Result<String, String> {
+    if task != "-" {
+        return Ok(task.to_string());
+    }
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("failed to read the task from stdin: {}", e))?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("the task read from stdin is empty".to_string());
+    }
+    Ok(input.to_string())
+}
+
+/// Writes a progress event to `err`, one line per event.
+///
+/// Streamed deltas and output chunks are skipped; the complete thought and
+/// output follow as their own events.
+fn report(event: &Event, err: &mut impl Write) {
+    let _ = match event {
+        Event::ThinkingDelta(_) | Event::OutputChunk(_) => Ok(()),
+        Event::Thinking(content) => writeln!(err, "[thinking] {}", content),
+        Event::Executing(command) => writeln!(err, "[executing] {}", command),
+        Event::Output(content, true) => writeln!(err, "{}", content),
+        Event::Output(content, false) => writeln!(err, "[error] {}", content),
+        Event::Compacted(replaced) => {
+            writeln!(err, "[compacted] {} messages summarized", replaced)
+        }
+        Event::Degraded(name, error) => {
+            writeln!(err, "[fallback] switched to '{}' after: {}", name, error)
+        }
+        Event::Restored(name) => writeln!(err, "[restored] back on '{}'", name),
+        Event::Warning(message) => writeln!(err, "[warning] {}", message),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        AgentConfig, CassetteConfig, ContextConfig, FallbackConfig, HarnessConfig, LLMConfig,
+        PolicyConfig, Protocol, ProviderKind, RetryConfig, SessionsConfig, SkillsConfig,
+    };
+    use std::path::PathBuf;
+
+    /// Writes the script to a fresh directory and returns a config that
+    /// replays it, with the directory to remove afterwards.
+    fn script_config(script: &str) -> (Config, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("minusagent-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script.yaml");
+        std::fs::write(&path, script).unwrap();
+        let config = Config {
+            agent: AgentConfig { max_steps: 5 },
+            llm: vec![LLMConfig {
+                name: "test".to_string(),
+                model: "test-model".to_string(),
+                base_url: path.display().to_string(),
+                api_key_env: None,
+                api_key_envs: Vec::new(),
+                max_tokens: 1024,
+                context_window: 25_600,
+                reasoning_effort: None,
+                backend: None,
+                stream: false,
+                protocol: Protocol::JsonSchema,
+                provider: ProviderKind::Script,
+            }],
+            skills: SkillsConfig::default(),
+            context: ContextConfig::default(),
+            sessions: SessionsConfig::default(),
+            harness: HarnessConfig::default(),
+            policy: PolicyConfig::default(),
+            retry: RetryConfig::default(),
+            fallback: FallbackConfig::default(),
+            cassette: CassetteConfig::default(),
+        };
+        (config, dir)
+    }
+
+    /// Runs the script for `args` and returns the exit status, stdout and stderr.
+    async fn run_script(
+        script: &str,
+        args: RunArgs,
+        cancel: &CancellationToken,
+    ) -> (i32, String, String) {
+        let (config, dir) = script_config(script);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = run(config, args, cancel, &mut out, &mut err).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        let text = |bytes| String::from_utf8(bytes).unwrap();
+        (code, text(out), text(err))
+    }
+
+    fn task(task: &str) -> RunArgs {
+        RunArgs {
+            task: task.to_string(),
+            ..Default::default()
+        }
+    }
+
+    const ANSWER: &str = r#"
+- expect: ["say hello"]
+  thought: { thought_type: planning, content: "Use echo." }
+  action: { action: execute, command: "echo hello" }
+- expect: ["hello"]
+  thought: { thought_type: analysis, content: "It printed hello." }
+  action: { action: completed, answer: "said hello" }
+"#;
+
+    #[tokio::test]
+    async fn test_run_answered() {
+        let cancel = CancellationToken::new();
+        let (code, out, err) = run_script(ANSWER, task("say hello"), &cancel).await;
+        assert_eq!(code, EXIT_ANSWERED);
+        assert_eq!(out, "said hello\n");
+        assert!(err.contains("[thinking] Use echo."), "{}", err);
+        assert!(err.contains("[executing] echo hello"), "{}", err);
+        assert!(!err.contains("said hello"), "{}", err);
+
+        let quiet = RunArgs {
+            quiet: true,
+            ..task("say hello")
+        };
+        let (code, out, err) = run_script(ANSWER, quiet, &cancel).await;
+        assert_eq!(code, EXIT_ANSWERED);
+        assert_eq!(out, "said hello\n");
+        assert_eq!(err, "");
+    }
+
+    #[tokio::test]
+    async fn test_run_failed() {
+        let cancel = CancellationToken::new();
+        let (code, out, err) = run_script("[]", task("say hello"), &cancel).await;
+        assert_eq!(code, EXIT_FAILED);
+        assert_eq!(out, "");
+        assert!(
+            err.contains("[error] LLM request error: script ended"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_max_steps() {
+        let script = r#"
+- thought: { thought_type: planning, content: "Think more." }
+  action: { action: continue }
+"#;
+        let args = RunArgs {
+            max_steps: Some(1),
+            ..task("ponder")
+        };
+        let cancel = CancellationToken::new();
+        let (code, out, err) = run_script(script, args, &cancel).await;
+        assert_eq!(code, EXIT_MAX_STEPS);
+        assert_eq!(out, "");
+        assert!(err.contains("[error]"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_run_interrupted() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let (code, out, _) = run_script(ANSWER, task("say hello"), &cancel).await;
+        assert_eq!(code, EXIT_INTERRUPTED);
+        assert_eq!(out, "");
+    }
+
+    #[tokio::test]
+    async fn test_run_unknown_llm() {
+        let args = RunArgs {
+            llm: Some("missing".to_string()),
+            ..task("say hello")
+        };
+        let cancel = CancellationToken::new();
+        let (code, out, err) = run_script(ANSWER, args, &cancel).await;
+        assert_eq!(code, EXIT_USAGE);
+        assert_eq!(out, "");
+        assert!(err.contains("missing"), "{}", err);
+    }
+}